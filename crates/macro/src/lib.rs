@@ -38,7 +38,10 @@
 //!- *unresponsive `bool`*:\
 //! Define the api should not response, sometimes we want request a tcp or another long connection type service but not need response. Default value `false`.
 //!
-//! The params order is up to down, and you can use assignment expression style define it to break the fixed order.
+//!- *version `&str`*:\
+//! The api version like `"2"` or `"2.1"`, the highest compatible one of the same path apis will be chosen by the requested version. It can only be defined in assignment style like `version = "2"`. Default value `""` means unversioned.
+//!
+//! The params order is up to down (except the `version`), and you can use assignment expression style define it to break the fixed order.
 //!
 //
 //! ## closed_err!(): proc_macro
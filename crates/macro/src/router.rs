@@ -26,7 +26,7 @@ macro_rules! props {
         }
     };
 }
-props!(id, path, serializer, deserializer, redirect, omission, name, unresponsive,);
+props!(id, path, serializer, deserializer, redirect, omission, name, unresponsive, version,);
 const ORDERED_PROPS: [Prop; 8] = [Prop::path, Prop::serializer, Prop::deserializer, Prop::id, Prop::omission, Prop::redirect, Prop::name, Prop::unresponsive];
 
 pub struct Api {
@@ -38,6 +38,7 @@ pub struct Api {
     pub redirect: Option<Expr>,
     pub name: Option<Expr>,
     pub unresponsive: Option<Expr>,
+    pub version: Option<Expr>,
     pub extras: HashMap<String, Expr>,
 }
 
@@ -53,6 +54,7 @@ impl Api {
             Prop::omission => CONSUME_IF(matches!(self.omission, None), &mut || self.omission = Some(expr.clone())),
             Prop::name => CONSUME_IF(matches!(self.name, None), &mut || self.name = Some(expr.clone())),
             Prop::unresponsive => CONSUME_IF(matches!(self.unresponsive, None), &mut || self.unresponsive = Some(expr.clone())),
+            Prop::version => CONSUME_IF(matches!(self.version, None), &mut || self.version = Some(expr.clone())),
             // put all non-standard meta into extras mapping
             Prop::Extra(key) => CONSUME_IF(! self.extras.contains_key(key), &mut || {self.extras.insert(key.clone(), expr.clone()); ()}),
         } {
@@ -161,7 +163,7 @@ impl Api {
                         }), vec![("Box", None), ("new", None)], None);
                         if is_lit {
                             // need an explicit cast to Box<dyn Any> if value is a literal
-                            boxed = Expr::Cast(ExprCast { attrs: vec![], expr: Box::new(boxed), as_token: Default::default(), ty: Box::new(parse_quote!(Box<dyn std::any::Any>))})
+                            boxed = Expr::Cast(ExprCast { attrs: vec![], expr: Box::new(boxed), as_token: Default::default(), ty: Box::new(parse_quote!(Box<dyn std::any::Any + Send + Sync>))})
                         }
                         boxed
                     },
@@ -247,7 +249,7 @@ impl Api {
     }
 
     pub fn processing(self, mut input: ItemFn) -> (ItemFn, Ident, ReturnType, TokenStream) {
-        let Self{path, id, serializers, deserializers, omission, redirect, name,unresponsive , extras} = self;
+        let Self{path, id, serializers, deserializers, omission, redirect, name, unresponsive, version, extras} = self;
         let route_fn_name = input.sig.ident.clone();
         let mut fn_name = input.sig.ident.to_string();
         let path = path.unwrap_or_else(|| Expr::Lit(ExprLit { attrs: vec![], lit: Lit::Str(LitStr::new(fn_name.as_str(), Span::call_site())) }));
@@ -260,6 +262,7 @@ impl Api {
             LitStr::new(&path.as_str()[path.rfind('/').map_or(0, |i| i + 1)..], Span::call_site())
         })}));
         let unresponsive = unresponsive.unwrap_or_else(|| Expr::Lit(ExprLit { attrs: vec![], lit: Lit::Bool(LitBool::new(false, Span::call_site())) }));
+        let version = version.unwrap_or_else(|| Expr::Lit(ExprLit { attrs: vec![], lit: Lit::Str(LitStr::new("", Span::call_site())) }));
 
         fn_name.push_str("_api");
         let (controller, method_extras, req_type_segments) = Self::get_controller_method_extras(fn_name.as_str(), &mut input, extras)
@@ -299,6 +302,7 @@ impl Api {
                 #redirect,
                 #name,
                 #unresponsive,
+                #version,
                 extras,
             )))
         ))
@@ -316,6 +320,7 @@ impl Parse for Api {
             redirect: None,
             name: None,
             unresponsive: None,
+            version: None,
             extras: Default::default(),
         };
         let mut prop_index = 0;
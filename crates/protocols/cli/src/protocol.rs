@@ -114,7 +114,7 @@ impl Into<String> for CliProtocol {
         };
         #[cfg(feature = "session")]
         if let Some(resp_sid) = self.get_resp_sid() {
-            resp.push_str(format!("\n\nNew sid: {}", resp_sid).as_str());
+            resp.push_str(if resp_sid.is_empty() { "\n\nSid cleared".to_string() } else { format!("\n\nNew sid: {}", resp_sid) }.as_str());
         }
         resp
     }
@@ -0,0 +1,244 @@
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use http_body_util::{BodyExt, Empty, Full};
+use http_body_util::combinators::BoxBody;
+use hyper::body::{Body, Bytes};
+use hyper::header::{AUTHORIZATION, CACHE_CONTROL, CONTENT_LENGTH, COOKIE, ETAG, HeaderMap, HeaderName, HeaderValue, IF_NONE_MATCH, ORIGIN, SET_COOKIE, VARY};
+use hyper::{Response, StatusCode};
+use dce_router::api::ApiTrait;
+use dce_router::protocol::RoutableProtocol;
+use dce_router::router::Router;
+use crate::cors::Cors;
+use crate::protocol::HyperHttpProtocol;
+
+/// The extras key of cache policy, could be set in router extras as global config, or in api extras to override the global.
+/// The value could be a [CachePolicy] or a `Cache-Control` str like `#[api(cache = "public, max-age=60")]`
+pub const CACHE_EXTRA_NAME: &str = "cache";
+/// The router extras key of the in-memory [ResponseCache]
+pub const RESPONSE_CACHE_EXTRA_NAME: &str = "response_cache";
+
+
+/// Cache policy of the GET and HEAD requests, the `ETag` will be generated from the response body, and `If-None-Match` will be answered with 304
+#[derive(Debug, Clone, Default)]
+pub struct CachePolicy {
+    control: Option<String>,
+    ttl: Option<Duration>,
+    vary: Vec<HeaderName>,
+}
+
+impl CachePolicy {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the `Cache-Control` header, it will not override the one set by the controller
+    pub fn control<T: ToString>(mut self, control: T) -> Self {
+        self.control = Some(control.to_string());
+        self
+    }
+
+    /// Keep the responses in the [ResponseCache] for the ttl, the responses will not be kept if not set.
+    /// The kept responses are answered before routing and the `before_controller` will not run for them, so the requests with a sid
+    /// will never be answered from or kept in the cache, neither will the ones with `Authorization` or `Cookie` unless the policy varies on it
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Set the request headers which vary the responses, they will be a part of the cache key and be appended to the `Vary` header
+    pub fn vary<I: IntoIterator<Item = T>, T: AsRef<str>>(mut self, headers: I) -> Self {
+        self.vary = headers.into_iter().filter_map(|h| HeaderName::from_bytes(h.as_ref().as_bytes()).ok()).collect();
+        self
+    }
+
+    /// Get the policy from api extras first, or from router extras
+    pub fn from_extras(api: &(dyn ApiTrait<HyperHttpProtocol> + Send + Sync), router: &Router<HyperHttpProtocol>) -> Option<Self> {
+        let policy = api.extras().get(CACHE_EXTRA_NAME).or_else(|| router.extras().get(CACHE_EXTRA_NAME))?;
+        policy.downcast_ref::<CachePolicy>().cloned()
+            .or_else(|| policy.downcast_ref::<&str>().map(|control| Self::new().control(control)))
+            .or_else(|| policy.downcast_ref::<String>().map(|control| Self::new().control(control)))
+    }
+}
+
+
+/// An in-memory LRU response cache, the clones share the same store, so a clone could be kept to invalidate the entries,
+/// e.g. `.set_extra(RESPONSE_CACHE_EXTRA_NAME, Box::new(ResponseCache::new(1024)))`
+#[derive(Debug, Clone)]
+pub struct ResponseCache {
+    store: Arc<Mutex<LruStore>>,
+}
+
+impl ResponseCache {
+    /// New a cache which can keep `capacity` responses at most
+    pub fn new(capacity: usize) -> Self {
+        Self { store: Arc::new(Mutex::new(LruStore { capacity, tick: 0, entries: Default::default(), order: Default::default() })) }
+    }
+
+    pub fn from_router(router: &Router<HyperHttpProtocol>) -> Option<&Self> {
+        router.extras().get(RESPONSE_CACHE_EXTRA_NAME).and_then(|c| c.downcast_ref::<ResponseCache>())
+    }
+
+    /// Remove the cached responses of the api with the name, returns the removed count
+    pub fn invalidate(&self, api_name: &str) -> usize {
+        let Ok(mut store) = self.store.lock() else { return 0 };
+        let keys: Vec<_> = store.entries.iter().filter(|(_, e)| e.api == api_name).map(|(k, _)| k.clone()).collect();
+        keys.iter().for_each(|key| store.remove(key));
+        keys.len()
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut store) = self.store.lock() {
+            store.entries.clear();
+            store.order.clear();
+        }
+    }
+
+    fn get(&self, key: &str) -> Option<Response<BoxBody<Bytes, Infallible>>> {
+        let mut store = self.store.lock().ok()?;
+        match store.entries.get(key) {
+            Some(entry) if entry.expires > Instant::now() => {},
+            Some(_) => { store.remove(key); return None; },
+            None => return None,
+        }
+        store.tick += 1;
+        let tick = store.tick;
+        let entry = store.entries.get_mut(key)?;
+        let last_tick = std::mem::replace(&mut entry.tick, tick);
+        let mut resp = Response::new(Full::from(entry.body.clone()).boxed());
+        *resp.status_mut() = entry.status;
+        *resp.headers_mut() = entry.headers.clone();
+        store.order.remove(&last_tick);
+        store.order.insert(tick, key.to_string());
+        Some(resp)
+    }
+
+    fn put(&self, key: String, api: &'static str, ttl: Duration, status: StatusCode, headers: HeaderMap, body: Bytes) {
+        let Ok(mut store) = self.store.lock() else { return };
+        if store.capacity == 0 {
+            return;
+        }
+        store.remove(&key);
+        while store.entries.len() >= store.capacity {
+            // evict the least recently used
+            let Some((_, oldest)) = store.order.pop_first() else { break };
+            store.entries.remove(&oldest);
+        }
+        store.tick += 1;
+        let tick = store.tick;
+        store.order.insert(tick, key.clone());
+        store.entries.insert(key, CacheEntry { api, tick, expires: Instant::now() + ttl, status, headers, body });
+    }
+}
+
+#[derive(Debug)]
+struct LruStore {
+    capacity: usize,
+    tick: u64,
+    entries: HashMap<String, CacheEntry>,
+    order: BTreeMap<u64, String>,
+}
+
+impl LruStore {
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.order.remove(&entry.tick);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct CacheEntry {
+    api: &'static str,
+    tick: u64,
+    expires: Instant,
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+
+/// The caching state of a GET or HEAD request
+pub(crate) struct Caching {
+    policy: CachePolicy,
+    api: &'static str,
+    key: String,
+    cache: Option<ResponseCache>,
+    if_none_match: Option<String>,
+}
+
+impl Caching {
+    /// Locate the api by the version resolved path to get the cache policy, returns None if the api has no policy
+    pub(crate) fn new(rp: &HyperHttpProtocol, path: &str, router: &Router<HyperHttpProtocol>) -> Option<Self> {
+        let req = rp.req().ok()?;
+        let (api, params, suffix) = router.locate(path, |apis| rp.api_match(apis)).ok()?;
+        let policy = CachePolicy::from_extras(api, router)?;
+        let mut params: Vec<_> = params.iter().map(|(k, v)| format!("{k}={v:?}")).collect();
+        params.sort();
+        let varies: Vec<_> = policy.vary.iter().map(|h| format!("{}={:?}", h, req.headers().get_all(h).iter().collect::<Vec<_>>())).collect();
+        // the responses were decorated with the cors headers of the request origin, so they could only be shared within the origin
+        let origin = Cors::from_extras(Some(api), router).and(req.headers().get(ORIGIN)).and_then(|o| o.to_str().ok()).unwrap_or("");
+        let key = format!("{} {}|{}|{}|{}|{}|{}", api.name(), req.uri().path_and_query().map_or("", |p| p.as_str()), rp.version().unwrap_or(""),
+            params.join("&"), suffix.unwrap_or(""), varies.join("&"), origin);
+        let if_none_match = req.headers().get(IF_NONE_MATCH).and_then(|v| v.to_str().ok()).map(ToString::to_string);
+        let credentialed = [AUTHORIZATION, COOKIE].iter().any(|h| req.headers().contains_key(h) && ! policy.vary.contains(h));
+        #[cfg(feature = "session")]
+        let credentialed = credentialed || rp.sid().is_some();
+        let cache = policy.ttl.filter(|_| ! credentialed).and(ResponseCache::from_router(router).cloned());
+        Some(Self { cache, policy, api: api.name(), key, if_none_match })
+    }
+
+    pub(crate) fn cached(&self) -> Option<Response<BoxBody<Bytes, Infallible>>> {
+        self.cache.as_ref()?.get(&self.key)
+    }
+
+    /// Tag the successful response with `ETag` and `Cache-Control`, keep it if it could be cached, and answer 304 if it was not modified
+    pub(crate) async fn finish(&self, resp: Response<BoxBody<Bytes, Infallible>>, cached: bool) -> Response<BoxBody<Bytes, Infallible>> {
+        if resp.status() != StatusCode::OK {
+            return resp;
+        }
+        let (mut parts, body) = resp.into_parts();
+        let mut body = body;
+        if ! cached {
+            if let Some(control) = self.policy.control.as_deref().and_then(|c| HeaderValue::from_str(c).ok()) {
+                parts.headers.entry(CACHE_CONTROL).or_insert(control);
+            }
+            for vary in &self.policy.vary {
+                parts.headers.append(VARY, HeaderValue::from(vary.clone()));
+            }
+            // only the full body could be tagged or kept, the streams such as event streams and files will be passed through
+            if body.size_hint().exact().is_some() {
+                let Ok(collected) = body.collect().await.map(|c| c.to_bytes());
+                if ! parts.headers.contains_key(ETAG) {
+                    if let Ok(etag) = HeaderValue::from_str(&format!(r#""{:x}-{:x}""#, collected.len(), stable_hash(&collected))) {
+                        parts.headers.insert(ETAG, etag);
+                    }
+                }
+                // the responses with cookies are private to the client
+                if let (Some(cache), Some(ttl), false) = (&self.cache, self.policy.ttl, parts.headers.contains_key(SET_COOKIE)) {
+                    cache.put(self.key.clone(), self.api, ttl, parts.status, parts.headers.clone(), collected.clone());
+                }
+                body = Full::from(collected).boxed();
+            }
+        }
+        let etag = parts.headers.get(ETAG).and_then(|v| v.to_str().ok());
+        if self.if_none_match.as_deref().zip(etag).is_some_and(|(if_none_match, etag)| etag_matched(if_none_match, etag)) {
+            parts.status = StatusCode::NOT_MODIFIED;
+            parts.headers.remove(CONTENT_LENGTH);
+            return Response::from_parts(parts, Empty::new().boxed());
+        }
+        Response::from_parts(parts, body)
+    }
+}
+
+/// The FNV-1a hash, unlike the std hashers, its output is the same across builds, so the etags will survive the restarts and upgrades
+fn stable_hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| (hash ^ *byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Weak comparison, the compressed response tagged with a weak etag should match the origin too
+fn etag_matched(if_none_match: &str, etag: &str) -> bool {
+    let etag = etag.trim_start_matches("W/");
+    if_none_match.split(',').map(str::trim).any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
@@ -0,0 +1,167 @@
+use std::convert::Infallible;
+use std::io::{Result as IoResult, Write};
+use flate2::write::{GzEncoder, ZlibEncoder};
+use http_body_util::{BodyExt, Full};
+use http_body_util::combinators::BoxBody;
+use hyper::body::{Body, Bytes};
+use hyper::header::{CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, HeaderValue, VARY};
+use hyper::Response;
+use log::warn;
+
+/// The router extras key of compression config, e.g. `.set_extra(COMPRESSION_EXTRA_NAME, Box::new(Compression::new()))`
+pub const COMPRESSION_EXTRA_NAME: &str = "compression";
+const DEFAULT_MIN_SIZE: usize = 1024;
+const DEFAULT_LEVEL: u32 = 6;
+const DEFAULT_CONTENT_TYPES: [&str; 6] = ["text/", "application/json", "application/javascript", "application/xml", "application/wasm", "image/svg+xml"];
+const BROTLI_BUFFER_SIZE: usize = 4096;
+const BROTLI_WINDOW_BITS: u32 = 22;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Brotli,
+    Gzip,
+    Deflate,
+}
+
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+}
+
+
+/// Response compression config, the encoding will be negotiated with `Accept-Encoding`, responses which have no exact size
+/// (such as event streams and files) will not be compressed
+#[derive(Debug, Clone)]
+pub struct Compression {
+    encodings: Vec<Encoding>,
+    min_size: usize,
+    content_types: Vec<String>,
+    level: u32,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            encodings: vec![Encoding::Brotli, Encoding::Gzip, Encoding::Deflate],
+            min_size: DEFAULT_MIN_SIZE,
+            content_types: DEFAULT_CONTENT_TYPES.iter().map(ToString::to_string).collect(),
+            level: DEFAULT_LEVEL,
+        }
+    }
+}
+
+impl Compression {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the supported encodings in the order of preference, it will be used when the client accepts several with same quality
+    pub fn encodings<I: IntoIterator<Item = Encoding>>(mut self, encodings: I) -> Self {
+        self.encodings = encodings.into_iter().collect();
+        self
+    }
+
+    /// Set the minimum body size to compress, default 1024 bytes
+    pub fn min_size(mut self, min_size: usize) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// Set the compressible content types, a type ends with `/` means any subtype. Responses without `Content-Type` will be taken as compressible
+    pub fn content_types<I: IntoIterator<Item = T>, T: ToString>(mut self, content_types: I) -> Self {
+        self.content_types = content_types.into_iter().map(|t| t.to_string().to_ascii_lowercase()).collect();
+        self
+    }
+
+    /// Set the compression level between 0 and 9
+    pub fn level(mut self, level: u32) -> Self {
+        self.level = level.min(9);
+        self
+    }
+
+    /// Choose the encoding with the highest quality in the `Accept-Encoding` values
+    pub fn negotiate<'a>(&self, accept_encodings: impl IntoIterator<Item = &'a str>) -> Option<Encoding> {
+        let mut accepts = vec![];
+        for accept in accept_encodings.into_iter().flat_map(|v| v.split(',')) {
+            let mut params = accept.split(';').map(str::trim);
+            let name = params.next().unwrap_or("").to_ascii_lowercase();
+            let quality = params.find_map(|p| p.strip_prefix("q=")).map_or(1.0, |q| q.parse::<f32>().unwrap_or(0.0));
+            accepts.push((name, quality));
+        }
+        self.encodings.iter().filter_map(|encoding| accepts.iter()
+            .find(|(name, _)| name == encoding.name()).or_else(|| accepts.iter().find(|(name, _)| name == "*"))
+            .filter(|(_, quality)| *quality > 0.0).map(|(_, quality)| (*encoding, *quality)))
+            // max_by returns the last max element, so reverse to keep the preference order
+            .rev().max_by(|a, b| a.1.total_cmp(&b.1)).map(|(encoding, _)| encoding)
+    }
+
+    fn compressible(&self, content_type: Option<&str>) -> bool {
+        content_type.is_none_or(|ct| {
+            let ct = ct.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+            self.content_types.iter().any(|t| if t.ends_with('/') { ct.starts_with(t.as_str()) } else { ct == *t })
+        })
+    }
+
+    fn encode(&self, encoding: Encoding, data: &[u8]) -> IoResult<Vec<u8>> {
+        let level = flate2::Compression::new(self.level);
+        match encoding {
+            Encoding::Brotli => {
+                let mut writer = brotli::CompressorWriter::new(vec![], BROTLI_BUFFER_SIZE, self.level, BROTLI_WINDOW_BITS);
+                writer.write_all(data)?;
+                Ok(writer.into_inner())
+            },
+            Encoding::Gzip => {
+                let mut encoder = GzEncoder::new(vec![], level);
+                encoder.write_all(data)?;
+                encoder.finish()
+            },
+            Encoding::Deflate => {
+                let mut encoder = ZlibEncoder::new(vec![], level);
+                encoder.write_all(data)?;
+                encoder.finish()
+            },
+        }
+    }
+
+    /// Compress the response body if the response and the request accepted encodings were suitable
+    pub async fn compress<'a>(&self, resp: Response<BoxBody<Bytes, Infallible>>, accept_encodings: impl IntoIterator<Item = &'a str>) -> Response<BoxBody<Bytes, Infallible>> {
+        let status = resp.status().as_u16();
+        let headers = resp.headers();
+        if ! matches!(status, 200..=299) || status == 204 || status == 206
+            || headers.contains_key(CONTENT_ENCODING) || headers.contains_key(CONTENT_RANGE)
+            || headers.get(CACHE_CONTROL).and_then(|v| v.to_str().ok()).is_some_and(|v| v.contains("no-transform"))
+            || ! self.compressible(headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok()))
+            || resp.body().size_hint().exact().is_none_or(|size| (size as usize) < self.min_size) {
+            return resp;
+        }
+        let Some(encoding) = self.negotiate(accept_encodings) else { return resp };
+        let (mut parts, body) = resp.into_parts();
+        // the body has an exact size, so it should be a full body and could be collected immediately
+        let Ok(collected) = body.collect().await.map(|c| c.to_bytes());
+        let body = match self.encode(encoding, &collected) {
+            Ok(compressed) => {
+                parts.headers.insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.name()));
+                parts.headers.remove(CONTENT_LENGTH);
+                // the strong etag could not identify the encoded representation anymore
+                if let Some(etag) = parts.headers.get(ETAG).and_then(|v| v.to_str().ok()).filter(|v| ! v.starts_with("W/")) {
+                    if let Ok(weak) = HeaderValue::from_str(&format!("W/{}", etag)) {
+                        parts.headers.insert(ETAG, weak);
+                    }
+                }
+                Full::from(compressed)
+            },
+            Err(err) => {
+                warn!("response compression failed: {err}");
+                Full::from(collected)
+            },
+        };
+        parts.headers.append(VARY, HeaderValue::from_static("Accept-Encoding"));
+        Response::from_parts(parts, body.boxed())
+    }
+}
@@ -0,0 +1,161 @@
+use std::fmt::{Display, Formatter, Write};
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+impl Display for SameSite {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        })
+    }
+}
+
+
+/// A response cookie, will be encoded into a `Set-Cookie` header value
+#[derive(Debug, Clone)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<Duration>,
+    expires: Option<SystemTime>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+impl Cookie {
+    /// The octets not allowed by rfc6265 will be percent-encoded when formatting, such as the `;` and CR/LF in the name or value,
+    /// so they could not inject the attributes or headers
+    pub fn new<N: ToString, V: ToString>(name: N, value: V) -> Self {
+        Self {
+            name: name.to_string(),
+            value: value.to_string(),
+            path: None,
+            domain: None,
+            max_age: None,
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        }
+    }
+
+    /// A cookie to tell client to remove the cookie with the same name, the path and domain should be same as it was set
+    pub fn removal<N: ToString>(name: N) -> Self {
+        Self::new(name, "").max_age(Duration::ZERO).expires(SystemTime::UNIX_EPOCH)
+    }
+
+    pub fn path<T: ToString>(mut self, path: T) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    pub fn domain<T: ToString>(mut self, domain: T) -> Self {
+        self.domain = Some(domain.to_string());
+        self
+    }
+
+    /// Set the lifetime in seconds precision, the `Max-Age` will take precedence over `Expires` in modern browsers
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn expires(mut self, expires: SystemTime) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    /// Set the `SameSite` attribute, `SameSite::None` requires the cookie to be secure, so `Secure` will be set automatically
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+}
+
+impl Display for Cookie {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", Encoded(&self.name, is_token), Encoded(&self.value, is_cookie_octet))?;
+        if let Some(path) = &self.path {
+            write!(f, "; Path={}", Encoded(path, is_av_octet))?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={}", Encoded(domain, is_av_octet))?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", httpdate::fmt_http_date(expires))?;
+        }
+        if self.secure || self.same_site == Some(SameSite::None) {
+            f.write_str("; Secure")?;
+        }
+        if self.http_only {
+            f.write_str("; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site)?;
+        }
+        Ok(())
+    }
+}
+
+/// Percent-encode the octets not matched the predicate
+struct Encoded<'a>(&'a str, fn(u8) -> bool);
+
+impl Display for Encoded<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for &b in self.0.as_bytes() {
+            if (self.1)(b) {
+                f.write_char(b as char)?;
+            } else {
+                write!(f, "%{:02X}", b)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The rfc2616 token, any visible ascii except the separators
+fn is_token(b: u8) -> bool {
+    matches!(b, 0x21..=0x7E) && ! b"()<>@,;:\\\"/[]?={}".contains(&b)
+}
+
+/// The rfc6265 cookie-octet, any visible ascii except the DQUOTE, comma, semicolon and backslash
+fn is_cookie_octet(b: u8) -> bool {
+    matches!(b, 0x21 | 0x23..=0x2B | 0x2D..=0x3A | 0x3C..=0x5B | 0x5D..=0x7E)
+}
+
+/// The rfc6265 path-value or domain-value, any char except the CTLs and semicolon
+fn is_av_octet(b: u8) -> bool {
+    matches!(b, 0x20..=0x7E) && b != b';'
+}
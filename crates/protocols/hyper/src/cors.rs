@@ -0,0 +1,147 @@
+use std::collections::BTreeSet;
+use std::convert::Infallible;
+use http_body_util::{BodyExt, Empty};
+use http_body_util::combinators::BoxBody;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{ACCESS_CONTROL_ALLOW_CREDENTIALS, ACCESS_CONTROL_ALLOW_HEADERS, ACCESS_CONTROL_ALLOW_METHODS, ACCESS_CONTROL_ALLOW_ORIGIN,
+                    ACCESS_CONTROL_EXPOSE_HEADERS, ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_HEADERS, ACCESS_CONTROL_REQUEST_METHOD,
+                    HeaderMap, HeaderValue, ORIGIN, VARY};
+use hyper::{Method, Request, Response, StatusCode};
+use dce_router::api::ApiTrait;
+use dce_router::router::{CODE_NOT_FOUND, Router};
+use dce_util::mixed::DceErr;
+use crate::protocol::{HttpMethodSet, HyperHttpProtocol};
+
+/// The extras key of cors config, could be set in router extras as global config, or in api extras to override the global
+pub const CORS_EXTRA_NAME: &str = "cors";
+
+
+/// Cors config, allow any origin by default
+#[derive(Debug, Clone, Default)]
+pub struct Cors {
+    origins: Option<BTreeSet<String>>,
+    methods: Option<Vec<Method>>,
+    headers: Option<Vec<String>>,
+    expose_headers: Vec<String>,
+    credentials: bool,
+    max_age: Option<u64>,
+}
+
+impl Cors {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the allowed origins like `https://drunkce.com`
+    pub fn origins<I: IntoIterator<Item = T>, T: ToString>(mut self, origins: I) -> Self {
+        self.origins = Some(origins.into_iter().map(|o| o.to_string().trim_end_matches('/').to_ascii_lowercase()).collect());
+        self
+    }
+
+    /// Set the allowed methods, the methods defined by the matched apis will be allowed if not set
+    pub fn methods<I: IntoIterator<Item = Method>>(mut self, methods: I) -> Self {
+        self.methods = Some(methods.into_iter().collect());
+        self
+    }
+
+    /// Set the allowed request headers, the `Access-Control-Request-Headers` will be reflected if not set
+    pub fn headers<I: IntoIterator<Item = T>, T: ToString>(mut self, headers: I) -> Self {
+        self.headers = Some(headers.into_iter().map(|h| h.to_string()).collect());
+        self
+    }
+
+    pub fn expose_headers<I: IntoIterator<Item = T>, T: ToString>(mut self, headers: I) -> Self {
+        self.expose_headers = headers.into_iter().map(|h| h.to_string()).collect();
+        self
+    }
+
+    pub fn credentials(mut self, credentials: bool) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    /// Set the seconds the preflight result could be cached
+    pub fn max_age(mut self, seconds: u64) -> Self {
+        self.max_age = Some(seconds);
+        self
+    }
+
+    fn allow_origin(&self, origin: &str) -> Option<String> {
+        match &self.origins {
+            Some(origins) => origins.contains(&origin.trim_end_matches('/').to_ascii_lowercase()).then(|| origin.to_string()),
+            // credentials is not allowed with the wildcard origin
+            None if self.credentials => Some(origin.to_string()),
+            None => Some("*".to_string()),
+        }
+    }
+
+    /// Get the config from api extras first, or from router extras
+    pub fn from_extras<'a>(api: Option<&'a (dyn ApiTrait<HyperHttpProtocol> + Send + Sync)>, router: &'a Router<HyperHttpProtocol>) -> Option<&'a Self> {
+        api.and_then(|a| a.extras().get(CORS_EXTRA_NAME)).and_then(|c| c.downcast_ref::<Cors>())
+            .or_else(|| router.extras().get(CORS_EXTRA_NAME).and_then(|c| c.downcast_ref::<Cors>()))
+    }
+
+    /// Append the cors headers to the actual response
+    pub fn decorate(&self, origin: &str, headers: &mut HeaderMap) {
+        headers.append(VARY, HeaderValue::from_static("Origin"));
+        let Some(allow_origin) = self.allow_origin(origin).and_then(|o| HeaderValue::from_str(&o).ok()) else { return };
+        headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+        if self.credentials {
+            headers.insert(ACCESS_CONTROL_ALLOW_CREDENTIALS, HeaderValue::from_static("true"));
+        }
+        if ! self.expose_headers.is_empty() {
+            if let Ok(expose) = HeaderValue::from_str(&self.expose_headers.join(", ")) {
+                headers.insert(ACCESS_CONTROL_EXPOSE_HEADERS, expose);
+            }
+        }
+    }
+
+    /// Answer the preflight request if it is, the allowed methods will be the union of all the apis matched the path
+    pub fn preflight(req: &Request<Incoming>, path: &str, router: &Router<HyperHttpProtocol>) -> Option<Response<BoxBody<Bytes, Infallible>>> {
+        if req.method() != Method::OPTIONS || ! req.headers().contains_key(ACCESS_CONTROL_REQUEST_METHOD) {
+            return None;
+        }
+        let origin = req.headers().get(ORIGIN)?.to_str().ok()?;
+        let mut methods = BTreeSet::new();
+        let mut any_method = false;
+        let mut cors = None;
+        router.locate(path, |apis| {
+            for api in apis {
+                match api.method().as_ref().and_then(|m| m.as_any()).and_then(|m| m.downcast_ref::<HttpMethodSet>()) {
+                    Some(set) => methods.extend(set.methods().iter().map(ToString::to_string)),
+                    None => any_method = true,
+                }
+                // prefer the api config, the global config will be the fallback
+                cors = cors.or_else(|| api.extras().get(CORS_EXTRA_NAME).and_then(|c| c.downcast_ref::<Cors>()));
+            }
+            apis.first().copied().ok_or_else(|| DceErr::openly(CODE_NOT_FOUND, format!(r#"Path "{}" cannot match any Api"#, path)))
+        }).ok()?;
+        let cors = cors.or_else(|| Self::from_extras(None, router))?;
+
+        let mut resp = Response::builder().status(StatusCode::NO_CONTENT);
+        let headers = resp.headers_mut()?;
+        headers.append(VARY, HeaderValue::from_static("Origin, Access-Control-Request-Method, Access-Control-Request-Headers"));
+        if let Some(allow_origin) = cors.allow_origin(origin).and_then(|o| HeaderValue::from_str(&o).ok()) {
+            headers.insert(ACCESS_CONTROL_ALLOW_ORIGIN, allow_origin);
+            let methods = match &cors.methods {
+                Some(allowed) => allowed.iter().map(ToString::to_string).collect(),
+                None if any_method => req.headers().get(ACCESS_CONTROL_REQUEST_METHOD).and_then(|m| m.to_str().ok()).map(ToString::to_string).into_iter().collect(),
+                _ => methods.into_iter().collect::<Vec<_>>(),
+            };
+            headers.insert(ACCESS_CONTROL_ALLOW_METHODS, HeaderValue::from_str(&methods.join(", ")).ok()?);
+            if let Some(allow_headers) = match &cors.headers {
+                Some(allowed) => HeaderValue::from_str(&allowed.join(", ")).ok(),
+                None => req.headers().get(ACCESS_CONTROL_REQUEST_HEADERS).cloned(),
+            } {
+                headers.insert(ACCESS_CONTROL_ALLOW_HEADERS, allow_headers);
+            }
+            if cors.credentials {
+                headers.insert(ACCESS_CONTROL_ALLOW_CREDENTIALS, HeaderValue::from_static("true"));
+            }
+            if let Some(max_age) = cors.max_age {
+                headers.insert(ACCESS_CONTROL_MAX_AGE, HeaderValue::from(max_age));
+            }
+        }
+        resp.body(Empty::new().boxed()).ok()
+    }
+}
@@ -1,3 +1,20 @@
 pub mod protocol;
+pub mod cache;
+pub mod cors;
+pub mod cookie;
+#[cfg(feature = "compression")]
+pub mod compression;
 #[cfg(feature = "sailfish")]
 pub mod serializer;
+#[cfg(feature = "proxy")]
+pub mod proxy;
+pub mod response;
+#[cfg(feature = "session")]
+pub mod sid;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod sse;
+pub mod static_file;
+pub mod vhost;
+#[cfg(feature = "websocket")]
+pub mod websocket;
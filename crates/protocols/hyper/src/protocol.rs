@@ -1,21 +1,29 @@
 use std::any::Any;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::convert::Infallible;
 use std::fmt::Debug;
 use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
 use async_trait::async_trait;
-use http_body_util::{combinators::BoxBody, BodyExt, Empty, Full};
-use hyper::body::{Bytes, Incoming};
+use http_body_util::{combinators::BoxBody, BodyExt, Empty, Full, LengthLimitError, Limited};
+use hyper::body::{Body, Bytes, Incoming};
 use hyper::{Method, Request, Response, StatusCode};
 #[allow(unused)]
-use hyper::header::{COOKIE, HeaderValue};
-use dce_router::protocol::{Meta, RoutableProtocol};
-use dce_router::request::{Context, Request as DceRequest, Response as DceResponse};
-use dce_router::router::Router;
+use hyper::header::{ALLOW, CONTENT_LENGTH, HeaderMap, HeaderValue, ORIGIN};
+use dce_router::protocol::{CODE_PAYLOAD_TOO_LARGE, CODE_REQUEST_TIMEOUT, HEAD_VERSION_NAME, Meta, RoutableProtocol};
+use dce_router::request::{Context, PathParam, Request as DceRequest, Response as DceResponse};
+use dce_router::router::{CODE_NOT_FOUND, Router};
 use dce_router::serializer::Serialized;
 use dce_util::mixed::{DceErr, DceResult};
 use dce_router::api::Method as DceMethod;
+use crate::cache::Caching;
+use crate::cors::Cors;
+#[cfg(feature = "session")]
+use crate::sid::{SidTransport, SID_TRANSPORT_EXTRA_NAME};
+#[cfg(feature = "compression")]
+use crate::compression::{Compression, COMPRESSION_EXTRA_NAME};
+#[cfg(feature = "compression")]
+use hyper::header::ACCEPT_ENCODING;
 
 pub type HttpRaw<'a> = DceRequest<'a, HyperHttpProtocol, (), ()>;
 pub type HttpGet<'a, Dto> = DceRequest<'a, HyperHttpProtocol, (), Dto>;
@@ -25,36 +33,144 @@ pub type Http<'a, ReqDto, RespDto> = DceRequest<'a, HyperHttpProtocol, ReqDto, R
 #[derive(Debug)]
 pub struct HyperHttpProtocol {
     meta: Meta<Request<Incoming>, Response<BoxBody<Bytes, Infallible>>>,
+    status: Option<StatusCode>,
+    resp_headers: HeaderMap,
+    preset_params: HashMap<&'static str, PathParam>,
+    #[cfg(feature = "session")]
+    sid_transport: SidTransport,
 }
 
 impl HyperHttpProtocol {
+    /// Set the response status, it will be ignored when an error responded
+    pub fn set_status(&mut self, status: StatusCode) {
+        self.status = Some(status);
+    }
+
+    /// The headers will be appended to the response, whatever the response was serialized or raw
+    pub fn resp_headers_mut(&mut self) -> &mut HeaderMap {
+        &mut self.resp_headers
+    }
+
+    /// Preset a param before routing, it will be overridden by the same name path param
+    pub fn put_param(&mut self, key: &'static str, value: PathParam) {
+        self.preset_params.insert(key, value);
+    }
+
     pub async fn route(
-        self,
+        mut self,
         router: Arc<Router<Self>>,
         context_data: HashMap<String, Box<dyn Any + Send>>,
     ) -> Result<Response<BoxBody<Bytes, Infallible>>, Infallible> {
-        Self::handle(self, router, context_data).await.ok_or_else(|| unreachable!("http route should always return Some(Resp)"))
+        // locate with the unversioned path, the router would resolve the same version again while routing
+        let path = router.resolve_version(&mut self).unwrap_or_else(|| self.path().to_string());
+        if let Some(resp) = self.req().ok().and_then(|req| Cors::preflight(req, &path, &router)
+            .or_else(|| Self::answer_options(req, &path, &router))) {
+            return Ok(resp);
+        }
+        let is_head = self.req().is_ok_and(|r| r.method() == Method::HEAD);
+        #[cfg(feature = "session")]
+        if let Some(sid_transport) = router.extras().get(SID_TRANSPORT_EXTRA_NAME).and_then(|t| t.downcast_ref::<SidTransport>()) {
+            self.sid_transport = sid_transport.clone();
+        }
+        #[cfg(feature = "compression")]
+        let accept_encodings = self.req().map_or_else(|_| vec![], |r| r.headers().get_all(ACCEPT_ENCODING).iter()
+            .filter_map(|v| v.to_str().ok().map(ToString::to_string)).collect::<Vec<_>>());
+        #[cfg(feature = "compression")]
+        let compression = router.clone();
+        let caching = match self.req().map(|r| r.method()) {
+            Ok(&Method::GET | &Method::HEAD) => Caching::new(&self, &path, &router),
+            _ => None,
+        };
+        let cached = caching.as_ref().and_then(Caching::cached);
+        let is_cached = cached.is_some();
+        let mut resp = match cached {
+            Some(resp) => resp,
+            None => {
+                let preset_params = std::mem::take(&mut self.preset_params);
+                let mut context = Context::new(router, self, context_data);
+                preset_params.into_iter().for_each(|(key, value)| context.put_param(key, value));
+                let result = Router::route(&mut context).await;
+                let Some(resp) = context.take_rp().and_then(|rp| rp.handle_result(result, &mut context))
+                    else { unreachable!("http route should always return Some(Resp)") };
+                resp
+            },
+        };
+        if let Some(caching) = caching {
+            resp = caching.finish(resp, is_cached).await;
+        }
+        #[cfg(feature = "compression")]
+        if let Some(compression) = compression.extras().get(COMPRESSION_EXTRA_NAME).and_then(|c| c.downcast_ref::<Compression>()) {
+            resp = compression.compress(resp, accept_encodings.iter().map(String::as_str)).await;
+        }
+        if is_head {
+            // drop the body of the GET controller response, but keep the length it should be
+            let (mut parts, body) = resp.into_parts();
+            if let Some(size) = body.size_hint().exact().filter(|_| parts.status != StatusCode::NOT_MODIFIED) {
+                parts.headers.entry(CONTENT_LENGTH).or_insert(HeaderValue::from(size));
+            }
+            resp = Response::from_parts(parts, Empty::new().boxed());
+        }
+        Ok(resp)
+    }
+
+    /// Answer the OPTIONS request with the `Allow` header of the methods of all the apis matched the path,
+    /// unless any of them opted in by declaring the OPTIONS method
+    fn answer_options(req: &Request<Incoming>, path: &str, router: &Router<Self>) -> Option<Response<BoxBody<Bytes, Infallible>>> {
+        if req.method() != Method::OPTIONS {
+            return None;
+        }
+        let mut methods = BTreeSet::new();
+        let mut opted_in = false;
+        router.locate(path, |apis| {
+            for api in apis {
+                match api.method().as_ref().and_then(|m| m.as_any()).and_then(|m| m.downcast_ref::<HttpMethodSet>()) {
+                    Some(set) => {
+                        opted_in |= set.methods().contains(&Method::OPTIONS);
+                        methods.extend(set.methods().iter().cloned());
+                    },
+                    // the api accepts any method, so let it handle the OPTIONS
+                    None => opted_in = true,
+                }
+            }
+            apis.first().copied().ok_or_else(|| DceErr::openly(CODE_NOT_FOUND, format!(r#"Path "{}" cannot match any Api"#, path)))
+        }).ok()?;
+        if opted_in {
+            return None;
+        }
+        if methods.contains(&Method::GET) {
+            methods.insert(Method::HEAD);
+        }
+        methods.insert(Method::OPTIONS);
+        let allow = methods.iter().map(Method::as_str).collect::<Vec<_>>().join(", ");
+        Response::builder().status(StatusCode::NO_CONTENT).header(ALLOW, allow).body(Empty::new().boxed()).ok()
     }
 }
 
 impl From<Request<Incoming>> for HyperHttpProtocol {
     fn from(value: Request<Incoming>) -> Self {
-        Self { meta: Meta::new(value, Default::default()) }
+        // carry the requested api version into heads to let the router choose the api
+        let heads = value.headers().get(HEAD_VERSION_NAME).and_then(|v| v.to_str().ok())
+            .map_or_else(HashMap::new, |v| HashMap::from([(HEAD_VERSION_NAME.to_string(), v.to_string())]));
+        Self { meta: Meta::new(value, heads), status: None, resp_headers: Default::default(), preset_params: Default::default(),
+            #[cfg(feature = "session")] sid_transport: Default::default() }
     }
 }
 
 impl Into<Response<BoxBody<Bytes, Infallible>>> for HyperHttpProtocol {
     fn into(mut self) -> Response<BoxBody<Bytes, Infallible>> {
         let resp = self.resp_mut().take();
-        #[allow(unused_mut)]
         let mut resp = match resp {
             None => Response::new(Empty::new().boxed()),
             Some(DceResponse::Serialized(sd)) => self.pack_resp(sd),
             Some(DceResponse::Raw(rr)) => rr,
         };
+        if let Some(status) = self.status {
+            *resp.status_mut() = status;
+        }
+        resp.headers_mut().extend(std::mem::take(&mut self.resp_headers));
         #[cfg(feature = "session")]
-        if let Some(resp_sid) = self.get_resp_sid() {
-            resp.headers_mut().insert("X-Session-Id", HeaderValue::from_str(resp_sid.as_str()).unwrap());
+        if let Some(resp_sid) = self.get_resp_sid().cloned() {
+            self.sid_transport.respond(&resp_sid, resp.headers_mut());
         }
         resp
     }
@@ -80,8 +196,24 @@ impl RoutableProtocol for HyperHttpProtocol {
     type Resp = Response<BoxBody<Bytes, Infallible>>;
 
     async fn body(&mut self) -> DceResult<Serialized> {
-        let req = self.req_mut().take().ok_or_else(|| DceErr::closed0("Empty request"))?;
-        Ok(Serialized::Bytes(req.collect().await.or_else(DceErr::closed0_wrap)?.to_bytes()))
+        let (max_body, body_timeout) = (self.max_body(), self.body_timeout());
+        // reject early if the declared length exceeded
+        if let Some(length) = self.req()?.headers().get(CONTENT_LENGTH).and_then(|v| v.to_str().ok()).and_then(|v| v.parse::<u64>().ok()) {
+            self.check_body_size(usize::try_from(length).unwrap_or(usize::MAX))?;
+        }
+        let req = self.req_mut().as_mut().ok_or_else(|| DceErr::closed0("Empty request"))?;
+        // collect from the mutable body to keep the request headers for later use
+        let limited = Limited::new(req.body_mut(), max_body).collect();
+        let collected = match body_timeout {
+            Some(timeout) => tokio::time::timeout(timeout, limited).await
+                .map_err(|_| DceErr::openly(CODE_REQUEST_TIMEOUT, "Request body read timeout".to_string()))?,
+            None => limited.await,
+        };
+        let collected = collected.map_err(|err| match err.downcast_ref::<LengthLimitError>() {
+            Some(_) => DceErr::openly(CODE_PAYLOAD_TOO_LARGE, format!("Request body exceeded the limit of {} bytes", max_body)),
+            None => DceErr::closed0(err),
+        })?;
+        Ok(Serialized::Bytes(collected.to_bytes()))
     }
 
     fn pack_resp(&self, serialized: Serialized) -> Self::Resp {
@@ -95,11 +227,15 @@ impl RoutableProtocol for HyperHttpProtocol {
         self.req().map_or("", |r| r.uri().path().trim_start_matches('/'))
     }
 
-    fn handle_result(self, result: DceResult<()>, _: &mut Context<Self>) -> Option<Self::Resp> {
+    fn handle_result(mut self, result: DceResult<()>, context: &mut Context<Self>) -> Option<Self::Resp> {
         Self::try_print_err(&result);
-        Some(match result {
+        let origin = self.req().ok().and_then(|r| r.headers().get(ORIGIN)).and_then(|o| o.to_str().ok()).map(ToString::to_string);
+        let mut resp = match result {
             Ok(_) => self.into(),
             Err(err) => {
+                // the status, headers and cookies set by the failed controller should not be responded
+                self.status = None;
+                self.resp_headers.clear();
                 let code = err.value().code;
                 let is_openly = matches!(err, DceErr::Openly(_));
                 let mut resp = self.err_into(err);
@@ -108,16 +244,16 @@ impl RoutableProtocol for HyperHttpProtocol {
                 }
                 resp
             },
-        })
+        };
+        if let Some((origin, cors)) = origin.zip(Cors::from_extras(context.api(), context.router())) {
+            cors.decorate(&origin, resp.headers_mut());
+        }
+        Some(resp)
     }
 
     #[cfg(feature = "session")]
     fn sid(&self) -> Option<&str> {
-        self.req().map_or(None, |r| r.headers().get("X-Session-Id").map(|v| v.to_str().unwrap())
-            .or_else(|| r.headers().get(COOKIE).iter().find_map(|v| {
-                let mut cookies = v.to_str().unwrap_or("").split(';');
-                cookies.find_map(|kv| if let Some(index) = kv.find("session_id=") { Some(&kv[index + 11 ..]) } else { None } )
-            })))
+        self.req().ok().and_then(|r| self.sid_transport.sid(r.headers()))
     }
 
     fn parse_api_method(prop_mapping: &mut HashMap<&'static str, Box<dyn Any + Send + Sync>>) -> Option<Box<dyn DceMethod<Self> + Send + Sync>> {
@@ -140,7 +276,7 @@ pub trait HttpProtocol: RoutableProtocol + HttpMethodGetter {
             ms.downcast::<Method>().map(|m| HashSet::from([*m])).ok()
         } else {
             ms.downcast::<Vec<Method>>().map(|m| m.into_iter().collect::<HashSet<_>>()).ok()
-        }).unwrap_or_else(|| Some(HashSet::from([Method::GET, Method::HEAD, Method::OPTIONS]))).map(HttpMethodSet)?))
+        }).unwrap_or_else(|| Some(HashSet::from([Method::GET, Method::HEAD]))).map(HttpMethodSet)?))
     }
 }
 
@@ -162,13 +298,24 @@ pub mod HttpMethod {
 #[derive(Debug)]
 pub struct HttpMethodSet(HashSet<Method>);
 
+impl HttpMethodSet {
+    pub fn methods(&self) -> &HashSet<Method> {
+        &self.0
+    }
+}
+
 impl<T: HttpMethodGetter> DceMethod<T> for HttpMethodSet {
     fn to_string(&self) -> String {
         format!("[{}]", self.0.iter().map(|m| m.to_string()).fold(String::new(), |a, b| format!("{a}, {b}")))
     }
 
     fn req_match(&self, raw: &T) -> bool {
-        self.0.contains(raw.method())
+        // the HEAD request will be handled by the GET controller, and the body will be dropped
+        self.0.contains(raw.method()) || raw.method() == Method::HEAD && self.0.contains(&Method::GET)
+    }
+
+    fn as_any(&self) -> Option<&dyn Any> {
+        Some(self)
     }
 }
 
@@ -0,0 +1,229 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::task::{Context as TaskContext, Poll};
+use std::time::Duration;
+use async_trait::async_trait;
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Body, Bytes, Frame, Incoming};
+use hyper::header::{CONNECTION, HeaderMap, HeaderName, HeaderValue, HOST};
+use hyper::{Method, Request, Response, Uri};
+use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioExecutor;
+use log::{debug, warn};
+use dce_router::protocol::RoutableProtocol;
+use dce_router::request::{PathParam, Response as DceResponse};
+use dce_router::serializer::Serialized;
+use dce_util::mixed::{DceErr, DceResult};
+#[cfg(feature = "tls")]
+use dce_util::tls::{PeerIdentity, PEER_IDENTITY_DATA_NAME};
+use crate::protocol::{Http, HttpMethodGetter, HyperHttpProtocol};
+
+/// The api extras key of the [Upstream], e.g. `#[api("legacy/{path*}", upstream = Upstream::new(Uri::from_static("http://127.0.0.1:8080")))]`
+pub const UPSTREAM_EXTRA_NAME: &str = "upstream";
+const DEFAULT_TIMEOUT_SECONDS: u64 = 30;
+const DEFAULT_RETRIES: usize = 1;
+const CODE_BAD_GATEWAY: isize = 502;
+const CODE_GATEWAY_TIMEOUT: isize = 504;
+const HOP_BY_HOP_HEADERS: [&str; 9] = ["connection", "keep-alive", "proxy-connection", "proxy-authenticate", "proxy-authorization",
+    "te", "trailer", "transfer-encoding", "upgrade"];
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+const X_FORWARDED_HOST: &str = "x-forwarded-host";
+const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+
+
+/// An upstream http service config, the connections will be pooled and shared by the clones
+#[derive(Debug, Clone)]
+pub struct Upstream {
+    base: Uri,
+    param: &'static str,
+    timeout: Duration,
+    retries: usize,
+    preserve_host: bool,
+    client: Client<HttpConnector, Full<Bytes>>,
+}
+
+impl Upstream {
+    /// The base uri should be absolute like `http://127.0.0.1:8080/legacy`, the path var will be appended to the base path
+    pub fn new(base: Uri) -> Self {
+        Self {
+            base,
+            param: "path",
+            timeout: Duration::from_secs(DEFAULT_TIMEOUT_SECONDS),
+            retries: DEFAULT_RETRIES,
+            preserve_host: false,
+            client: Client::builder(TokioExecutor::new()).build_http(),
+        }
+    }
+
+    /// Set the path var name of the api path, default `path`
+    pub fn param(mut self, param: &'static str) -> Self {
+        self.param = param;
+        self
+    }
+
+    /// Set the timeout of waiting the upstream response headers, default 30 seconds
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the retry times for idempotent requests when failed to connect or timed out, default 1
+    pub fn retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Send the original `Host` header to upstream instead of the upstream authority
+    pub fn preserve_host(mut self, preserve_host: bool) -> Self {
+        self.preserve_host = preserve_host;
+        self
+    }
+
+    fn target(&self, parts: &[String], query: Option<&str>) -> DceResult<Uri> {
+        let authority = self.base.authority().ok_or_else(|| DceErr::closed0(format!("Upstream uri {} is not absolute", self.base)))?;
+        let mut path = self.base.path().trim_end_matches('/').to_string();
+        for part in parts {
+            path.push('/');
+            path.push_str(part);
+        }
+        if path.is_empty() { path.push('/'); }
+        if let Some(query) = query {
+            path.push('?');
+            path.push_str(query);
+        }
+        Uri::builder().scheme(self.base.scheme_str().unwrap_or("http")).authority(authority.as_str()).path_and_query(path)
+            .build().map_err(DceErr::closed0)
+    }
+
+    async fn send(&self, method: &Method, uri: &Uri, headers: &HeaderMap, body: &Bytes) -> DceResult<Response<Incoming>> {
+        let idempotent = matches!(*method, Method::GET | Method::HEAD | Method::PUT | Method::DELETE | Method::OPTIONS | Method::TRACE);
+        let mut attempts = 0;
+        loop {
+            let mut req = Request::builder().method(method.clone()).uri(uri.clone()).body(Full::new(body.clone())).map_err(DceErr::closed0)?;
+            *req.headers_mut() = headers.clone();
+            let err = match tokio::time::timeout(self.timeout, self.client.request(req)).await {
+                Ok(Ok(resp)) => return Ok(resp),
+                Ok(Err(err)) => DceErr::openly(CODE_BAD_GATEWAY, format!("Bad gateway: {err}")),
+                Err(_) => DceErr::openly(CODE_GATEWAY_TIMEOUT, "Gateway timeout"),
+            };
+            attempts += 1;
+            if ! idempotent || attempts > self.retries {
+                return Err(err);
+            }
+            debug!("retry upstream request {method} {uri} after failed: {err}");
+        }
+    }
+}
+
+
+#[async_trait]
+pub trait ProxyResponder {
+    /// Forward the request to the upstream with the path var, query and headers, the upstream will be got from the api extras
+    /// with key [UPSTREAM_EXTRA_NAME] if None. The hop-by-hop headers will be stripped and the `X-Forwarded-*` headers will
+    /// be appended, the client address of `X-Forwarded-For` is only known when the connections served by `HyperHttpProtocol::serve`
+    async fn proxy(self, upstream: Option<&Upstream>) -> DceResult<Option<DceResponse<<HyperHttpProtocol as RoutableProtocol>::Resp>>>;
+}
+
+#[async_trait]
+impl<ReqDto, RespDto> ProxyResponder for Http<'_, ReqDto, RespDto> {
+    async fn proxy(mut self, upstream: Option<&Upstream>) -> DceResult<Option<DceResponse<<HyperHttpProtocol as RoutableProtocol>::Resp>>> {
+        let upstream = match upstream {
+            Some(upstream) => upstream,
+            None => self.api().and_then(|a| a.extras().get(UPSTREAM_EXTRA_NAME)).and_then(|u| u.downcast_ref::<Upstream>())
+                .ok_or_else(|| DceErr::closed0("No upstream configured"))?,
+        };
+        let mut parts = match self.param(upstream.param) {
+            Ok(PathParam::Vector(parts)) => parts.clone(),
+            Ok(param) => param.as_str().map_or_else(Vec::new, |p| vec![p.to_string()]),
+            Err(_) => vec![],
+        };
+        // the router cut the matched suffix off from the path var, so append it back
+        let suffix = self.suffix();
+        if ! suffix.is_empty() {
+            if let Some(last) = parts.last_mut() {
+                last.push(self.router().suffix_boundary());
+                last.push_str(suffix);
+            }
+        }
+        #[cfg(feature = "tls")]
+        let proto = if self.get_as::<std::sync::Arc<PeerIdentity>>(PEER_IDENTITY_DATA_NAME).is_ok() { "https" } else { "http" };
+        #[cfg(not(feature = "tls"))]
+        let proto = "http";
+        let method = self.rp().method().clone();
+        let req = self.rp().req()?;
+        let version = req.version();
+        let uri = upstream.target(&parts, req.uri().query())?;
+        let mut headers = req.headers().clone();
+        strip_hop_by_hop(&mut headers);
+        let host = req.headers().get(HOST).cloned().or_else(|| req.uri().authority().and_then(|a| HeaderValue::from_str(a.as_str()).ok()));
+        if let Some(addr) = req.extensions().get::<SocketAddr>() {
+            let forwarded_for = match headers.get(X_FORWARDED_FOR).and_then(|v| v.to_str().ok()) {
+                Some(forwarded) => format!("{}, {}", forwarded, addr.ip()),
+                None => addr.ip().to_string(),
+            };
+            headers.insert(HeaderName::from_static(X_FORWARDED_FOR), HeaderValue::from_str(&forwarded_for).map_err(DceErr::closed0)?);
+        }
+        if let Some(host) = &host {
+            headers.insert(HeaderName::from_static(X_FORWARDED_HOST), host.clone());
+        }
+        headers.insert(HeaderName::from_static(X_FORWARDED_PROTO), HeaderValue::from_static(proto));
+        match host.filter(|_| upstream.preserve_host) {
+            Some(host) => { headers.insert(HOST, host); },
+            // the client will set the upstream authority as host
+            None => { headers.remove(HOST); },
+        }
+        let body = match self.rp_mut().body().await? {
+            Serialized::Bytes(bytes) => bytes,
+            Serialized::String(string) => Bytes::from(string),
+        };
+
+        let resp = upstream.send(&method, &uri, &headers, &body).await?;
+        let (mut parts, body) = resp.into_parts();
+        strip_hop_by_hop(&mut parts.headers);
+        // respond with the client version but not the upstream's
+        parts.version = version;
+        self.raw_resp(Response::from_parts(parts, UpstreamBody(body).boxed()))
+    }
+}
+
+fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    let listed: Vec<_> = headers.get_all(CONNECTION).iter().filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(',')).filter_map(|name| HeaderName::from_bytes(name.trim().as_bytes()).ok()).collect();
+    for name in listed {
+        headers.remove(name);
+    }
+    for name in HOP_BY_HOP_HEADERS {
+        headers.remove(name);
+    }
+}
+
+
+struct UpstreamBody(Incoming);
+
+impl Body for UpstreamBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        match Pin::new(&mut self.0).poll_frame(cx) {
+            Poll::Ready(Some(Ok(frame))) => Poll::Ready(Some(Ok(frame))),
+            Poll::Ready(Some(Err(err))) => {
+                // the client will find the body length mismatched or the connection closed
+                warn!("upstream body read failed: {err}");
+                Poll::Ready(None)
+            },
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.0.is_end_stream()
+    }
+
+    fn size_hint(&self) -> hyper::body::SizeHint {
+        self.0.size_hint()
+    }
+}
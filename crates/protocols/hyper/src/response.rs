@@ -0,0 +1,62 @@
+use http_body_util::{BodyExt, Empty};
+use hyper::header::{HeaderName, HeaderValue, LOCATION, SET_COOKIE};
+use hyper::{Response, StatusCode};
+use dce_router::protocol::RoutableProtocol;
+use dce_router::request::Response as DceResponse;
+use dce_util::mixed::{DceErr, DceResult};
+use crate::cookie::Cookie;
+use crate::protocol::{Http, HyperHttpProtocol};
+
+
+/// Set the response status, headers and cookies from controllers, the body will still be serialized by the api serializers.
+/// They will be dropped if the controller returned an error, e.g.
+/// `req.with_status(StatusCode::CREATED).with_cookie(Cookie::new("token", token).http_only(true))?.success(Some(user))`
+pub trait ResponseBuilder: Sized {
+    fn with_status(self, status: StatusCode) -> Self;
+
+    /// Append a response header, the header set by this will override the same name one set by the framework such as `Content-Type`
+    fn with_header<N: TryInto<HeaderName>, V: TryInto<HeaderValue>>(self, name: N, value: V) -> DceResult<Self>;
+
+    /// Append a `Set-Cookie` header, could be called several times to set several cookies
+    fn with_cookie(self, cookie: Cookie) -> DceResult<Self>;
+
+    /// Respond a redirection to the location, the status will be `302 Found` if None
+    fn redirect<T: AsRef<str>>(self, location: T, status: Option<StatusCode>) -> DceResult<Option<DceResponse<<HyperHttpProtocol as RoutableProtocol>::Resp>>>;
+
+    /// Respond `204 No Content` without body
+    fn no_content(self) -> DceResult<Option<DceResponse<<HyperHttpProtocol as RoutableProtocol>::Resp>>>;
+}
+
+impl<ReqDto, RespDto> ResponseBuilder for Http<'_, ReqDto, RespDto> {
+    fn with_status(mut self, status: StatusCode) -> Self {
+        self.rp_mut().set_status(status);
+        self
+    }
+
+    fn with_header<N: TryInto<HeaderName>, V: TryInto<HeaderValue>>(mut self, name: N, value: V) -> DceResult<Self> {
+        let name = name.try_into().map_err(|_| DceErr::closed0("Invalid header name"))?;
+        let value = value.try_into().map_err(|_| DceErr::closed0(format!("Invalid value of header {}", name)))?;
+        // override the framework headers but keep the multiple values set by controller
+        self.rp_mut().resp_headers_mut().append(name, value);
+        Ok(self)
+    }
+
+    fn with_cookie(self, cookie: Cookie) -> DceResult<Self> {
+        let value = HeaderValue::from_str(&cookie.to_string()).map_err(|_| DceErr::closed0(format!("Invalid cookie {}", cookie.name())))?;
+        self.with_header(SET_COOKIE, value)
+    }
+
+    fn redirect<T: AsRef<str>>(self, location: T, status: Option<StatusCode>) -> DceResult<Option<DceResponse<<HyperHttpProtocol as RoutableProtocol>::Resp>>> {
+        let status = status.unwrap_or(StatusCode::FOUND);
+        if ! status.is_redirection() {
+            return Err(DceErr::closed0(format!("{} is not a redirection status", status)));
+        }
+        let resp = Response::builder().status(status).header(LOCATION, location.as_ref()).body(Empty::new().boxed()).map_err(DceErr::closed0)?;
+        self.with_status(status).raw_resp(resp)
+    }
+
+    fn no_content(self) -> DceResult<Option<DceResponse<<HyperHttpProtocol as RoutableProtocol>::Resp>>> {
+        let resp = Response::builder().status(StatusCode::NO_CONTENT).body(Empty::new().boxed()).map_err(DceErr::closed0)?;
+        self.with_status(StatusCode::NO_CONTENT).raw_resp(resp)
+    }
+}
@@ -0,0 +1,280 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+#[cfg(all(feature = "unix", unix))]
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use hyper::body::Incoming;
+use hyper::service::service_fn;
+use hyper::Request;
+use hyper_util::rt::{TokioExecutor, TokioIo, TokioTimer};
+use hyper_util::server::conn::auto::Builder;
+use log::{debug, error, info, warn};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+#[cfg(all(feature = "unix", unix))]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::{watch, Semaphore};
+use tokio::task::JoinSet;
+use dce_util::mixed::{DceErr, DceResult};
+#[cfg(feature = "tls")]
+use dce_util::tls::{PeerIdentity, TlsAcceptor, PEER_IDENTITY_DATA_NAME};
+#[cfg(all(feature = "unix", unix))]
+use dce_util::unix::{self, PeerCred, UnixAddr, PEER_CRED_DATA_NAME};
+use crate::protocol::HyperHttpProtocol;
+use crate::sse::SHUTDOWN_DATA_NAME;
+use crate::vhost::Dispatcher;
+
+const DEFAULT_HEADER_READ_TIMEOUT_SECONDS: u64 = 30;
+const DEFAULT_DRAIN_TIMEOUT_SECONDS: u64 = 30;
+const ACCEPT_ERROR_DELAY_MILLIS: u64 = 100;
+
+
+/// Options of the http server runner
+pub struct ServeOptions {
+    max_connections: Option<usize>,
+    header_read_timeout: Option<Duration>,
+    read_timeout: Option<Duration>,
+    drain_timeout: Duration,
+    shutdown: Option<Pin<Box<dyn Future<Output = ()> + Send>>>,
+    #[cfg(feature = "tls")]
+    tls: Option<Arc<TlsAcceptor>>,
+}
+
+impl Default for ServeOptions {
+    fn default() -> Self {
+        Self {
+            max_connections: None,
+            header_read_timeout: Some(Duration::from_secs(DEFAULT_HEADER_READ_TIMEOUT_SECONDS)),
+            read_timeout: None,
+            drain_timeout: Duration::from_secs(DEFAULT_DRAIN_TIMEOUT_SECONDS),
+            shutdown: None,
+            #[cfg(feature = "tls")]
+            tls: None,
+        }
+    }
+}
+
+impl ServeOptions {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the max living connections, new connections will wait in the backlog until some connection closed
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.max_connections = Some(max.max(1));
+        self
+    }
+
+    /// Set the timeout of reading the http/1 request headers, default 30 seconds
+    pub fn header_read_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.header_read_timeout = timeout;
+        self
+    }
+
+    /// Set the default timeout of reading the request body, the request will be responded with 408 when timed out, it could be
+    /// overridden by the `body_timeout` extras of the router or api
+    pub fn read_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.read_timeout = timeout;
+        self
+    }
+
+    /// Set the max duration to wait for the in-flight requests after shutdown, default 30 seconds
+    pub fn drain_timeout(mut self, timeout: Duration) -> Self {
+        self.drain_timeout = timeout;
+        self
+    }
+
+    /// Serve https with the tls acceptor, the [PeerIdentity] will be put into the context data with key [PEER_IDENTITY_DATA_NAME].
+    /// The ALPN protocols should be set to `["h2", "http/1.1"]` to negotiate http/2
+    #[cfg(feature = "tls")]
+    pub fn tls(mut self, acceptor: Arc<TlsAcceptor>) -> Self {
+        self.tls = Some(acceptor);
+        self
+    }
+
+    /// Set the shutdown signal, the server will stop accepting and drain the connections when it completed.
+    /// The server will shut down on `Ctrl-C` if not set
+    pub fn shutdown<F: Future<Output = ()> + Send + 'static>(mut self, signal: F) -> Self {
+        self.shutdown = Some(Box::pin(signal));
+        self
+    }
+}
+
+
+impl HyperHttpProtocol {
+    /// Serve the router on the address, http/1.1 or h2c will be auto detected, and the http/1 connections support upgrading.
+    /// Returns after the shutdown signal completed and the connections drained or the drain timed out
+    /// The router could be a [Dispatcher] to select the router by host or headers
+    pub async fn serve<A: ToSocketAddrs>(router: impl Into<Dispatcher>, addr: A, options: ServeOptions) -> DceResult<()> {
+        let listener = TcpListener::bind(addr).await.map_err(DceErr::closed0)?;
+        let local_addr = listener.local_addr().map_err(DceErr::closed0)?;
+        info!("Dce started at {} with Hyper", local_addr);
+        Self::serve_listener(router.into(), Listener::Tcp(listener), options).await
+    }
+
+    /// Serve the router on a unix socket, the address prefixed with `@` is an abstract name, such as `@dce.sock`.
+    /// The stale socket file will be removed before binding, and the socket file will be removed after shutdown.
+    /// The [PeerCred] of the client process will be put into the context data with key [PEER_CRED_DATA_NAME].
+    /// Tls is not supported on unix sockets, an error will be returned if the tls option was set
+    #[cfg(all(feature = "unix", unix))]
+    pub async fn serve_unix<P: AsRef<Path>>(router: impl Into<Dispatcher>, addr: P, options: ServeOptions) -> DceResult<()> {
+        #[cfg(feature = "tls")]
+        if options.tls.is_some() {
+            return DceErr::closed0_wrap("tls is not supported on unix sockets");
+        }
+        let addr = UnixAddr::new(addr);
+        let listener = addr.bind().map_err(|e| DceErr::closed0(format!("cannot bind unix socket {addr}: {e}")))?;
+        info!("Dce started at unix:{} with Hyper", addr);
+        let result = Self::serve_listener(router.into(), Listener::Unix(listener), options).await;
+        let _ = addr.unlink().map_err(|e| warn!("cannot remove the socket file {addr}: {e}"));
+        result
+    }
+
+    async fn serve_listener(router: Dispatcher, listener: Listener, mut options: ServeOptions) -> DceResult<()> {
+        let router = Arc::new(router);
+        let mut builder = Builder::new(TokioExecutor::new());
+        builder.http1().timer(TokioTimer::new()).header_read_timeout(options.header_read_timeout);
+        let builder = Arc::new(builder);
+        let limiter = options.max_connections.map(|max| Arc::new(Semaphore::new(max)));
+        let read_timeout = options.read_timeout;
+        let mut shutdown = options.shutdown.take().unwrap_or_else(|| Box::pin(async {
+            if let Err(err) = tokio::signal::ctrl_c().await {
+                error!("cannot listen the ctrl-c signal: {err}");
+                std::future::pending::<()>().await;
+            }
+        }));
+        // every connection holds a receiver, so the sender will be closed after all the connections finished
+        let (drain_tx, drain_rx) = watch::channel(false);
+        // the connections will be aborted if not finished in the drain timeout
+        let mut connections = JoinSet::new();
+
+        loop {
+            let permit = match &limiter {
+                Some(limiter) => tokio::select! {
+                    permit = limiter.clone().acquire_owned() => Some(permit.map_err(DceErr::closed0)?),
+                    _ = &mut shutdown => break,
+                },
+                None => None,
+            };
+            let accepted = tokio::select! {
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(err) => {
+                        // such as too many open files, wait a moment to avoid busy looping
+                        warn!("cannot accept stream: {err}");
+                        tokio::time::sleep(Duration::from_millis(ACCEPT_ERROR_DELAY_MILLIS)).await;
+                        continue;
+                    },
+                },
+                // reap the finished connections
+                Some(_) = connections.join_next(), if ! connections.is_empty() => continue,
+                _ = &mut shutdown => break,
+            };
+
+            let (router, builder, drain_rx) = (router.clone(), builder.clone(), drain_rx.clone());
+            #[cfg(feature = "tls")]
+            let tls = options.tls.clone();
+            connections.spawn(async move {
+                let _permit = permit;
+                let (stream, remote_addr) = match accepted {
+                    Accepted::Tcp(stream, remote_addr) => (stream, remote_addr),
+                    #[cfg(all(feature = "unix", unix))]
+                    Accepted::Unix(stream, peer_cred) => {
+                        let peer_cred = Arc::new(peer_cred);
+                        let context_data = move || HashMap::from([(PEER_CRED_DATA_NAME.to_string(), Box::new(peer_cred.clone()) as Box<dyn Any + Send>)]);
+                        return Self::serve_connection(stream, None, router, builder, read_timeout, drain_rx, context_data).await;
+                    },
+                };
+                #[cfg(feature = "tls")]
+                if let Some(tls) = tls {
+                    // the handshake was bounded by the handshake timeout of the tls config
+                    let stream = match tls.accept(stream).await {
+                        Ok(stream) => stream,
+                        Err(err) => return debug!("tls handshake with {remote_addr} failed: {err}"),
+                    };
+                    let identity = Arc::new(PeerIdentity::from_stream(&stream));
+                    let context_data = move || HashMap::from([(PEER_IDENTITY_DATA_NAME.to_string(), Box::new(identity.clone()) as Box<dyn Any + Send>)]);
+                    return Self::serve_connection(stream, Some(remote_addr), router, builder, read_timeout, drain_rx, context_data).await;
+                }
+                Self::serve_connection(stream, Some(remote_addr), router, builder, read_timeout, drain_rx, Default::default).await
+            });
+        }
+
+        drop(listener);
+        drop(drain_rx);
+        info!("Dce is shutting down, draining the connections");
+        drain_tx.send_replace(true);
+        if tokio::time::timeout(options.drain_timeout, drain_tx.closed()).await.is_err() {
+            warn!("drain timed out with {} connections alive, aborting them", drain_tx.receiver_count());
+            connections.abort_all();
+        }
+        // the upgraded connections will be stopped after the sender dropped
+        drop(drain_tx);
+        while connections.join_next().await.is_some() {}
+        Ok(())
+    }
+
+    async fn serve_connection<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+        stream: S,
+        remote_addr: Option<SocketAddr>,
+        router: Arc<Dispatcher>,
+        builder: Arc<Builder<TokioExecutor>>,
+        read_timeout: Option<Duration>,
+        mut drain_rx: watch::Receiver<bool>,
+        context_data: impl Fn() -> HashMap<String, Box<dyn Any + Send>>,
+    ) {
+        // the event streams will be closed by the shutdown signal, and the drain waits for them
+        let shutdown = drain_rx.clone();
+        let service = service_fn(|mut req: Request<Incoming>| {
+            // the client address could be got from the request extensions, such as for `X-Forwarded-For`
+            if let Some(remote_addr) = remote_addr {
+                req.extensions_mut().insert(remote_addr);
+            }
+            let mut rp = HyperHttpProtocol::from(req);
+            rp.set_body_timeout(read_timeout);
+            let router = router.clone();
+            let mut context_data = context_data();
+            context_data.insert(SHUTDOWN_DATA_NAME.to_string(), Box::new(shutdown.clone()));
+            async move { router.dispatch(rp, context_data).await }
+        });
+        let conn = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+        tokio::pin!(conn);
+        let result = tokio::select! {
+            result = conn.as_mut() => result,
+            _ = drain_rx.changed() => {
+                conn.as_mut().graceful_shutdown();
+                conn.await
+            },
+        };
+        if let Err(err) = result {
+            debug!("error serving connection from {remote_addr:?}: {err}");
+        }
+    }
+}
+
+
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(all(feature = "unix", unix))]
+    Unix(UnixListener),
+}
+
+enum Accepted {
+    Tcp(TcpStream, SocketAddr),
+    #[cfg(all(feature = "unix", unix))]
+    Unix(UnixStream, PeerCred),
+}
+
+impl Listener {
+    async fn accept(&self) -> std::io::Result<Accepted> {
+        match self {
+            Self::Tcp(listener) => listener.accept().await.map(|(stream, addr)| Accepted::Tcp(stream, addr)),
+            #[cfg(all(feature = "unix", unix))]
+            Self::Unix(listener) => unix::accept(listener).await.map(|(stream, cred)| Accepted::Unix(stream, cred)),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use std::time::Duration;
+use hyper::header::{HeaderMap, HeaderValue, SET_COOKIE};
+use dce_session::session::Meta;
+use crate::cookie::{Cookie, SameSite};
+
+/// The router extras key of sid transport config, e.g. `.set_extra(SID_TRANSPORT_EXTRA_NAME, Box::new(SidTransport::new(SidCarrier::Both)))`
+pub const SID_TRANSPORT_EXTRA_NAME: &str = "sid_transport";
+pub const SID_HEADER_NAME: &str = "X-Session-Id";
+const DEFAULT_COOKIE_NAME: &str = "session_id";
+
+
+/// Where the responded sid will be carried, the request sid will always be read from the header first and then the cookie
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SidCarrier {
+    Header,
+    Cookie,
+    Both,
+}
+
+
+/// Sid transport config of http, only respond with `X-Session-Id` header if not configured.
+/// The cookie `Max-Age` will be derived from the ttl minutes of the sid, and the cookie will be removed when the sid cleared
+#[derive(Debug, Clone)]
+pub struct SidTransport {
+    carrier: SidCarrier,
+    cookie_name: String,
+    path: Option<String>,
+    domain: Option<String>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+}
+
+impl Default for SidTransport {
+    fn default() -> Self {
+        Self::new(SidCarrier::Header)
+    }
+}
+
+impl SidTransport {
+    /// The cookie will be `session_id` with path `/`, `HttpOnly` and `SameSite=Lax` by default
+    pub fn new(carrier: SidCarrier) -> Self {
+        Self {
+            carrier,
+            cookie_name: DEFAULT_COOKIE_NAME.to_string(),
+            path: Some("/".to_string()),
+            domain: None,
+            secure: false,
+            http_only: true,
+            same_site: Some(SameSite::Lax),
+        }
+    }
+
+    pub fn cookie_name<T: ToString>(mut self, name: T) -> Self {
+        self.cookie_name = name.to_string();
+        self
+    }
+
+    pub fn path<T: ToString>(mut self, path: T) -> Self {
+        self.path = Some(path.to_string());
+        self
+    }
+
+    pub fn domain<T: ToString>(mut self, domain: T) -> Self {
+        self.domain = Some(domain.to_string());
+        self
+    }
+
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    pub fn same_site(mut self, same_site: Option<SameSite>) -> Self {
+        self.same_site = same_site;
+        self
+    }
+
+    /// Find the sid from the request headers
+    pub fn sid<'a>(&self, headers: &'a HeaderMap) -> Option<&'a str> {
+        headers.get(SID_HEADER_NAME).and_then(|v| v.to_str().ok()).or_else(|| headers.get_all(hyper::header::COOKIE).iter()
+            .filter_map(|v| v.to_str().ok()).flat_map(|v| v.split(';'))
+            .find_map(|kv| kv.trim().split_once('=').filter(|(k, _)| *k == self.cookie_name).map(|(_, v)| v.trim())))
+            .filter(|sid| ! sid.is_empty())
+    }
+
+    /// Write the responded sid into response headers, an empty sid means it was cleared
+    pub fn respond(&self, sid: &str, headers: &mut HeaderMap) {
+        if self.carrier != SidCarrier::Cookie && ! sid.is_empty() {
+            if let Ok(value) = HeaderValue::from_str(sid) {
+                headers.insert(SID_HEADER_NAME, value);
+            }
+        }
+        if self.carrier != SidCarrier::Header {
+            let mut cookie = if sid.is_empty() {
+                Cookie::removal(&self.cookie_name)
+            } else {
+                let cookie = Cookie::new(&self.cookie_name, sid);
+                match Meta::parse_sid(sid) {
+                    Ok((ttl_minutes, _)) => cookie.max_age(Duration::from_secs(ttl_minutes as u64 * 60)),
+                    _ => cookie,
+                }
+            }.secure(self.secure).http_only(self.http_only);
+            if let Some(path) = &self.path { cookie = cookie.path(path); }
+            if let Some(domain) = &self.domain { cookie = cookie.domain(domain); }
+            if let Some(same_site) = self.same_site { cookie = cookie.same_site(same_site); }
+            if let Ok(value) = HeaderValue::from_str(&cookie.to_string()) {
+                headers.append(SET_COOKIE, value);
+            }
+        }
+    }
+}
@@ -0,0 +1,233 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context as TaskContext, Poll};
+use std::time::Duration;
+use http_body_util::BodyExt;
+use hyper::body::{Body, Bytes, Frame};
+use hyper::header::{CACHE_CONTROL, CONTENT_TYPE};
+use hyper::Response;
+use log::{debug, warn};
+use tokio::sync::{mpsc, watch};
+use tokio::time::{interval_at, Instant};
+use dce_router::protocol::RoutableProtocol;
+use dce_router::request::Response as DceResponse;
+use dce_util::mixed::{DceErr, DceResult};
+use crate::protocol::{Http, HyperHttpProtocol};
+
+const DEFAULT_KEEP_ALIVE_SECONDS: u64 = 15;
+const CHANNEL_CAPACITY: usize = 32;
+const KEEP_ALIVE_COMMENT: &[u8] = b":\n\n";
+pub const LAST_EVENT_ID: &str = "Last-Event-ID";
+
+/// The context data key of the shutdown signal, a `watch::Receiver<bool>` which turns true when the server starts draining.
+/// It was put by the servers of [crate::server], and the event streams will be closed when it turned. The hand-rolled serving
+/// loops which route by themselves should put it with a [SseShutdown], or the streams will be closed only by the clients
+pub const SHUTDOWN_DATA_NAME: &str = "$#shutdown#";
+
+
+/// The shutdown signal for the hand-rolled serving loops, attach it to the context data of each request,
+/// then [close](Self::close) it to finish all the event streams of the attached requests, such as before the server stopped
+#[derive(Debug, Clone)]
+pub struct SseShutdown {
+    tx: Arc<watch::Sender<bool>>,
+}
+
+impl SseShutdown {
+    pub fn new() -> Self {
+        Self { tx: Arc::new(watch::channel(false).0) }
+    }
+
+    /// Put the signal receiver into the context data with key [SHUTDOWN_DATA_NAME]
+    pub fn attach(&self, context_data: &mut HashMap<String, Box<dyn Any + Send>>) {
+        context_data.insert(SHUTDOWN_DATA_NAME.to_string(), Box::new(self.tx.subscribe()));
+    }
+
+    pub fn close(&self) {
+        self.tx.send_replace(true);
+    }
+}
+
+impl Default for SseShutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+
+/// A server sent event, each field will be encoded into a line of `field: value`
+#[derive(Debug, Default, Clone)]
+pub struct Event {
+    name: Option<String>,
+    id: Option<String>,
+    data: Option<String>,
+    retry: Option<u64>,
+}
+
+impl Event {
+    pub fn new<T: ToString>(data: T) -> Self {
+        Self { data: Some(data.to_string()), ..Default::default() }
+    }
+
+    /// Set the `event` field, client could listen it with `addEventListener(name, ...)`
+    pub fn name<T: ToString>(mut self, name: T) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
+    pub fn id<T: ToString>(mut self, id: T) -> Self {
+        self.id = Some(id.to_string());
+        self
+    }
+
+    pub fn data<T: ToString>(mut self, data: T) -> Self {
+        self.data = Some(data.to_string());
+        self
+    }
+
+    /// Set the reconnection time in milliseconds
+    pub fn retry(mut self, millis: u64) -> Self {
+        self.retry = Some(millis);
+        self
+    }
+
+    fn encode(&self) -> Bytes {
+        // single line fields cannot contain line breaks, or the client will parse them as another field
+        let single_line = |value: &str| value.replace(['\r', '\n', '\0'], "");
+        let mut text = String::new();
+        if let Some(name) = &self.name {
+            text.push_str(format!("event: {}\n", single_line(name)).as_str());
+        }
+        if let Some(id) = &self.id {
+            text.push_str(format!("id: {}\n", single_line(id)).as_str());
+        }
+        if let Some(retry) = self.retry {
+            text.push_str(format!("retry: {}\n", retry).as_str());
+        }
+        if let Some(data) = &self.data {
+            for line in data.split('\n') {
+                text.push_str(format!("data: {}\n", line.trim_end_matches('\r')).as_str());
+            }
+        }
+        text.push('\n');
+        Bytes::from(text)
+    }
+}
+
+
+#[derive(Debug)]
+enum Chunk {
+    Data(Bytes),
+    Close,
+}
+
+#[derive(Debug)]
+struct EventStream {
+    rx: mpsc::Receiver<Chunk>,
+}
+
+impl Body for EventStream {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        match self.rx.poll_recv(cx) {
+            Poll::Ready(Some(Chunk::Data(bytes))) => Poll::Ready(Some(Ok(Frame::data(bytes)))),
+            Poll::Ready(Some(Chunk::Close) | None) => {
+                // close the receiver to let the senders know the stream was finished
+                self.rx.close();
+                Poll::Ready(None)
+            },
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+
+/// The event sender for an event stream controller, sending will fail after the client disconnected
+#[derive(Debug, Clone)]
+pub struct SseSender {
+    tx: mpsc::Sender<Chunk>,
+    last_event_id: Option<String>,
+}
+
+impl SseSender {
+    /// The `Last-Event-ID` header value, client will send it when reconnecting
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    pub async fn send(&self, event: Event) -> DceResult<()> {
+        self.tx.send(Chunk::Data(event.encode())).await.or(DceErr::closed0_wrap("Event stream was closed"))
+    }
+
+    pub async fn comment<T: ToString>(&self, comment: T) -> DceResult<()> {
+        let comment = comment.to_string().lines().map(|l| format!(":{}\n", l)).collect::<String>();
+        self.tx.send(Chunk::Data(Bytes::from(format!("{}\n", comment)))).await.or(DceErr::closed0_wrap("Event stream was closed"))
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+
+    /// Wait until the client disconnected
+    pub async fn closed(&self) {
+        self.tx.closed().await
+    }
+}
+
+
+pub trait SseResponder {
+    /// Respond an event stream, the handler will run in a new task with an event sender, and keep-alive comments will be sent
+    /// every `keep_alive` duration (15 seconds if None). The stream will be finished when the handler returned, the client
+    /// disconnected or the server started draining, see [SHUTDOWN_DATA_NAME].
+    fn sse<F, Fut>(self, keep_alive: Option<Duration>, handler: F) -> DceResult<Option<DceResponse<<HyperHttpProtocol as RoutableProtocol>::Resp>>>
+        where F: FnOnce(SseSender) -> Fut + Send + 'static,
+              Fut: Future<Output = DceResult<()>> + Send + 'static;
+}
+
+impl<ReqDto, RespDto> SseResponder for Http<'_, ReqDto, RespDto> {
+    fn sse<F, Fut>(self, keep_alive: Option<Duration>, handler: F) -> DceResult<Option<DceResponse<<HyperHttpProtocol as RoutableProtocol>::Resp>>>
+        where F: FnOnce(SseSender) -> Fut + Send + 'static,
+              Fut: Future<Output = DceResult<()>> + Send + 'static
+    {
+        let last_event_id = self.rp().req()?.headers().get(LAST_EVENT_ID).and_then(|v| v.to_str().ok()).map(ToString::to_string);
+        let keep_alive = keep_alive.unwrap_or(Duration::from_secs(DEFAULT_KEEP_ALIVE_SECONDS));
+        let (tx, rx) = mpsc::channel(CHANNEL_CAPACITY);
+        let keeper = tx.clone();
+        let mut shutdown = self.get_as::<watch::Receiver<bool>>(SHUTDOWN_DATA_NAME).ok().cloned();
+        let handler = handler(SseSender { tx, last_event_id });
+        tokio::spawn(async move {
+            let mut ticker = interval_at(Instant::now() + keep_alive, keep_alive);
+            tokio::pin!(handler);
+            loop {
+                tokio::select! {
+                    result = &mut handler => {
+                        if let Err(err) = result { warn!("event stream handler failed: {err}"); }
+                        break;
+                    },
+                    _ = ticker.tick() => if keeper.send(Chunk::Data(Bytes::from_static(KEEP_ALIVE_COMMENT))).await.is_err() { break },
+                    // stop the handler immediately when client disconnected
+                    _ = keeper.closed() => break,
+                    _ = async { match shutdown.as_mut() {
+                        Some(shutdown) => drop(shutdown.wait_for(|closing| *closing).await),
+                        None => std::future::pending().await,
+                    } } => {
+                        let _ = keeper.try_send(Chunk::Close);
+                        break;
+                    },
+                }
+            }
+            debug!("event stream finished");
+        });
+        let resp = Response::builder()
+            .header(CONTENT_TYPE, "text/event-stream")
+            .header(CACHE_CONTROL, "no-cache")
+            .body(EventStream { rx }.boxed())
+            .map_err(DceErr::closed0)?;
+        self.raw_resp(resp)
+    }
+}
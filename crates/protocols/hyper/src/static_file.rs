@@ -0,0 +1,313 @@
+use std::convert::Infallible;
+use std::io::SeekFrom;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::task::{Context as TaskContext, Poll};
+use std::time::{SystemTime, UNIX_EPOCH};
+use async_trait::async_trait;
+use http_body_util::{BodyExt, Empty};
+use hyper::body::{Body, Bytes, Frame};
+use hyper::header::{ACCEPT_ENCODING, ACCEPT_RANGES, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, ETAG, HeaderMap,
+                    IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, LAST_MODIFIED, RANGE, VARY};
+use hyper::{Method, Response, StatusCode};
+use log::warn;
+use tokio::fs::{self, File};
+use tokio::io::{AsyncRead, AsyncSeekExt, ReadBuf};
+use dce_router::protocol::RoutableProtocol;
+use dce_router::request::{PathParam, Response as DceResponse};
+use dce_router::router::CODE_NOT_FOUND;
+use dce_util::mixed::{DceErr, DceResult};
+use crate::protocol::{Http, HttpMethodGetter, HyperHttpProtocol};
+
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+const CODE_METHOD_NOT_ALLOWED: isize = 405;
+/// Precompressed sibling file extensions, in the order of preference
+const PRECOMPRESSED: [(&str, &str); 2] = [("br", "br"), ("gzip", "gz")];
+
+
+/// A static files directory config, the file path will be read from the `{path*}` like var of the api path
+#[derive(Debug, Clone)]
+pub struct StaticDir {
+    root: PathBuf,
+    param: &'static str,
+    index: Option<&'static str>,
+    precompressed: bool,
+}
+
+impl StaticDir {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        Self { root: root.into(), param: "path", index: Some("index.html"), precompressed: false }
+    }
+
+    /// Set the path var name of the api path, default `path`
+    pub fn param(mut self, param: &'static str) -> Self {
+        self.param = param;
+        self
+    }
+
+    /// Set the file to respond when requesting a directory, default `index.html`, None to respond 404
+    pub fn index(mut self, index: Option<&'static str>) -> Self {
+        self.index = index;
+        self
+    }
+
+    /// Try to respond the `.br` or `.gz` sibling file when the client accepts it
+    pub fn precompressed(mut self, precompressed: bool) -> Self {
+        self.precompressed = precompressed;
+        self
+    }
+}
+
+
+#[async_trait]
+pub trait StaticResponder {
+    /// Respond the file matched by the path var under the static directory, conditional requests with `If-None-Match` or
+    /// `If-Modified-Since` will get 304, and a single range of `Range` request will get 206
+    async fn serve_static(self, dir: &StaticDir) -> DceResult<Option<DceResponse<<HyperHttpProtocol as RoutableProtocol>::Resp>>>;
+}
+
+#[async_trait]
+impl<ReqDto, RespDto> StaticResponder for Http<'_, ReqDto, RespDto> {
+    async fn serve_static(mut self, dir: &StaticDir) -> DceResult<Option<DceResponse<<HyperHttpProtocol as RoutableProtocol>::Resp>>> {
+        let method = self.rp().method().clone();
+        if method != Method::GET && method != Method::HEAD {
+            return Err(DceErr::openly(CODE_METHOD_NOT_ALLOWED, "Method not allowed"));
+        }
+        let mut parts = match self.param(dir.param)? {
+            PathParam::Vector(parts) => parts.clone(),
+            param => param.as_str().map_or_else(Vec::new, |p| p.split('/').map(ToString::to_string).collect()),
+        };
+        // the router cut the matched suffix off from the path var, so append it back to get the real file name
+        let suffix = self.suffix();
+        if ! suffix.is_empty() {
+            if let Some(last) = parts.last_mut() {
+                last.push(self.router().suffix_boundary());
+                last.push_str(suffix);
+            }
+        }
+        let path = resolve(&dir.root, &parts, dir.index).await.ok_or_else(|| DceErr::openly(CODE_NOT_FOUND, "File not found"))?;
+        let mime = mime_type(match suffix.rsplit(self.router().suffix_boundary()).next() {
+            Some(ext) if ! ext.is_empty() => ext,
+            _ => path.extension().and_then(|e| e.to_str()).unwrap_or(""),
+        });
+
+        let headers = self.rp().req()?.headers().clone();
+        let (path, encoding) = match dir.precompressed {
+            true => precompressed(&path, &headers).await,
+            _ => (path, None),
+        };
+        let metadata = fs::metadata(&path).await.map_err(|_| DceErr::openly(CODE_NOT_FOUND, "File not found"))?;
+        let len = metadata.len();
+        let modified = metadata.modified().ok();
+        let etag = format!(r#""{:x}-{:x}""#, len, modified.and_then(|m| m.duration_since(UNIX_EPOCH).ok()).map_or(0, |d| d.as_nanos()));
+        let last_modified = modified.map(httpdate::fmt_http_date);
+
+        let mut builder = Response::builder()
+            .header(CONTENT_TYPE, mime)
+            .header(ETAG, etag.as_str())
+            .header(ACCEPT_RANGES, "bytes");
+        if let Some(last_modified) = &last_modified {
+            builder = builder.header(LAST_MODIFIED, last_modified.as_str());
+        }
+        if dir.precompressed {
+            builder = builder.header(VARY, ACCEPT_ENCODING.as_str());
+        }
+        if let Some(encoding) = encoding {
+            builder = builder.header(CONTENT_ENCODING, encoding);
+        }
+
+        if not_modified(&headers, &etag, modified) {
+            return self.raw_resp(builder.status(StatusCode::NOT_MODIFIED).body(Empty::new().boxed()).map_err(DceErr::closed0)?);
+        }
+        let range = match headers.get(RANGE).and_then(|r| r.to_str().ok()) {
+            Some(range) if if_range_matched(&headers, &etag, last_modified.as_deref()) => parse_range(range, len),
+            _ => None,
+        };
+        let (start, end) = match range {
+            Some(Ok(range)) => {
+                builder = builder.status(StatusCode::PARTIAL_CONTENT).header(CONTENT_RANGE, format!("bytes {}-{}/{}", range.0, range.1, len));
+                range
+            },
+            Some(Err(_)) => return self.raw_resp(builder.status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(CONTENT_RANGE, format!("bytes */{}", len)).body(Empty::new().boxed()).map_err(DceErr::closed0)?),
+            None => (0, len.saturating_sub(1)),
+        };
+        let remaining = if len == 0 { 0 } else { end - start + 1 };
+        builder = builder.header(CONTENT_LENGTH, remaining);
+        if method == Method::HEAD {
+            return self.raw_resp(builder.body(Empty::new().boxed()).map_err(DceErr::closed0)?);
+        }
+        let mut file = File::open(&path).await.map_err(|_| DceErr::openly(CODE_NOT_FOUND, "File not found"))?;
+        if start > 0 {
+            file.seek(SeekFrom::Start(start)).await.map_err(DceErr::closed0)?;
+        }
+        self.raw_resp(builder.body(FileBody { file, remaining, buffer: vec![0; READ_BUFFER_SIZE] }.boxed()).map_err(DceErr::closed0)?)
+    }
+}
+
+
+/// Join the path parts to the root and make sure the result will not escape from the root
+async fn resolve(root: &Path, parts: &[String], index: Option<&str>) -> Option<PathBuf> {
+    let mut path = root.to_path_buf();
+    for part in parts.iter().filter(|p| ! p.is_empty()) {
+        let part = percent_decode(part)?;
+        if part == "." || part == ".." || part.contains(['/', '\\', '\0', ':']) {
+            return None;
+        }
+        path.push(part);
+    }
+    let root = fs::canonicalize(root).await.ok()?;
+    let mut path = fs::canonicalize(path).await.ok()?;
+    // symlinks may point to outside
+    if ! path.starts_with(&root) {
+        return None;
+    }
+    if fs::metadata(&path).await.ok()?.is_dir() {
+        path.push(index?);
+    }
+    fs::metadata(&path).await.ok().filter(|m| m.is_file()).map(|_| path)
+}
+
+fn percent_decode(part: &str) -> Option<String> {
+    let bytes = part.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            decoded.push(u8::from_str_radix(part.get(i + 1 .. i + 3)?, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+async fn precompressed(path: &Path, headers: &HeaderMap) -> (PathBuf, Option<&'static str>) {
+    let accepts: Vec<_> = headers.get_all(ACCEPT_ENCODING).iter().filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(',')).filter_map(|v| {
+            let mut segments = v.split(';').map(str::trim);
+            let encoding = segments.next()?;
+            // `q=0` means not acceptable
+            match segments.any(|s| s.strip_prefix("q=").is_some_and(|q| q.parse::<f32>().is_ok_and(|q| q <= 0.0))) {
+                true => None,
+                _ => Some(encoding.to_ascii_lowercase()),
+            }
+        }).collect();
+    for (encoding, ext) in PRECOMPRESSED {
+        if accepts.iter().any(|a| a == encoding) {
+            let mut sibling = path.as_os_str().to_os_string();
+            sibling.push(format!(".{}", ext));
+            let sibling = PathBuf::from(sibling);
+            if fs::metadata(&sibling).await.is_ok_and(|m| m.is_file()) {
+                return (sibling, Some(encoding));
+            }
+        }
+    }
+    (path.to_path_buf(), None)
+}
+
+fn not_modified(headers: &HeaderMap, etag: &str, modified: Option<SystemTime>) -> bool {
+    if let Some(if_none_match) = headers.get(IF_NONE_MATCH).and_then(|v| v.to_str().ok()) {
+        return if_none_match.split(',').map(str::trim).any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag);
+    }
+    match (headers.get(IF_MODIFIED_SINCE).and_then(|v| v.to_str().ok()).and_then(|v| httpdate::parse_http_date(v).ok()), modified) {
+        // http date only has second precision
+        (Some(since), Some(modified)) => modified.duration_since(UNIX_EPOCH).is_ok_and(|m| since.duration_since(UNIX_EPOCH).is_ok_and(|s| m.as_secs() <= s.as_secs())),
+        _ => false,
+    }
+}
+
+fn if_range_matched(headers: &HeaderMap, etag: &str, last_modified: Option<&str>) -> bool {
+    match headers.get(IF_RANGE).and_then(|v| v.to_str().ok()) {
+        Some(condition) if condition.starts_with('"') => condition == etag,
+        Some(condition) => Some(condition) == last_modified,
+        None => true,
+    }
+}
+
+/// Parse a single range, None means should ignore the `Range` header, Err means the range was not satisfiable
+fn parse_range(range: &str, len: u64) -> Option<Result<(u64, u64), ()>> {
+    // multiple ranges are not supported, respond the whole file is allowed
+    let (start, end) = range.trim().strip_prefix("bytes=").filter(|r| ! r.contains(','))?.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+    if start.is_empty() {
+        let suffix_len = end.parse::<u64>().ok()?;
+        if suffix_len == 0 || len == 0 {
+            return Some(Err(()));
+        }
+        return Some(Ok((len.saturating_sub(suffix_len), len - 1)));
+    }
+    let start = start.parse::<u64>().ok()?;
+    let end = if end.is_empty() { len.saturating_sub(1) } else { end.parse::<u64>().ok()?.min(len.saturating_sub(1)) };
+    if start >= len || start > end {
+        return Some(Err(()));
+    }
+    Some(Ok((start, end)))
+}
+
+fn mime_type(ext: &str) -> &'static str {
+    match ext.to_ascii_lowercase().as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "md" => "text/markdown; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "mp3" => "audio/mpeg",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => "application/octet-stream",
+    }
+}
+
+
+struct FileBody {
+    file: File,
+    remaining: u64,
+    buffer: Vec<u8>,
+}
+
+impl Body for FileBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        let this = self.get_mut();
+        if this.remaining == 0 {
+            return Poll::Ready(None);
+        }
+        let size = this.buffer.len().min(this.remaining as usize);
+        let mut buf = ReadBuf::new(&mut this.buffer[..size]);
+        match Pin::new(&mut this.file).poll_read(cx, &mut buf) {
+            Poll::Ready(Ok(())) if ! buf.filled().is_empty() => {
+                this.remaining -= buf.filled().len() as u64;
+                Poll::Ready(Some(Ok(Frame::data(Bytes::copy_from_slice(buf.filled())))))
+            },
+            Poll::Ready(result) => {
+                // the file was truncated or failed to read, the client will find the body length mismatched
+                if let Err(err) = result { warn!("static file read failed: {err}"); }
+                this.remaining = 0;
+                Poll::Ready(None)
+            },
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,154 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::sync::Arc;
+use http_body_util::{BodyExt, Full};
+use http_body_util::combinators::BoxBody;
+use hyper::body::{Bytes, Incoming};
+use hyper::header::{HeaderName, HOST};
+use hyper::{Request, Response, StatusCode};
+use dce_router::request::PathParam;
+use dce_router::router::{CODE_NOT_FOUND, Router};
+use dce_util::mixed::{DceErr, DceResult};
+use crate::protocol::HyperHttpProtocol;
+
+
+#[derive(Debug)]
+enum Segment {
+    Exact(&'static str),
+    /// `{name}` matches and captures one host label or a header value
+    Param(&'static str),
+    /// `{name*}` matches and captures one or more leftmost host labels
+    Params(&'static str),
+    /// `*` matches one or more leftmost host labels, or any header value
+    Any,
+}
+
+impl Segment {
+    fn parse(pattern: &'static str) -> Self {
+        match pattern {
+            "*" => Segment::Any,
+            _ if pattern.starts_with('{') && pattern.ends_with("*}") => Segment::Params(&pattern[1..pattern.len() - 2]),
+            _ if pattern.starts_with('{') && pattern.ends_with('}') => Segment::Param(&pattern[1..pattern.len() - 1]),
+            _ => Segment::Exact(pattern),
+        }
+    }
+}
+
+
+#[derive(Debug)]
+enum Rule {
+    Host(Vec<Segment>),
+    Header(HeaderName, Segment),
+}
+
+impl Rule {
+    fn matches(&self, req: &Request<Incoming>) -> Option<HashMap<&'static str, PathParam>> {
+        let mut params = HashMap::new();
+        match self {
+            Rule::Host(segments) => {
+                let host = req.headers().get(HOST).and_then(|h| h.to_str().ok()).or_else(|| req.uri().host())?;
+                // strip the port, the ipv6 address will be kept as a single label
+                let host = match host.rsplit_once(':') {
+                    Some((name, port)) if ! name.contains(':') || name.ends_with(']') && port.chars().all(|c| c.is_ascii_digit()) => name,
+                    _ => host,
+                };
+                let labels: Vec<_> = host.trim_end_matches('.').split('.').collect();
+                let (first, rest) = segments.split_first()?;
+                let leftmost = match first {
+                    Segment::Any | Segment::Params(_) => labels.len().checked_sub(rest.len()).filter(|n| *n > 0)?,
+                    _ if labels.len() == segments.len() => 1,
+                    _ => return None,
+                };
+                let (left_labels, rest_labels) = labels.split_at(leftmost);
+                match first {
+                    Segment::Exact(exact) if left_labels[0].eq_ignore_ascii_case(exact) => {},
+                    Segment::Param(name) => { params.insert(*name, PathParam::Required(left_labels[0].to_ascii_lowercase())); },
+                    Segment::Params(name) => { params.insert(*name, PathParam::Vector(left_labels.iter().map(|l| l.to_ascii_lowercase()).collect())); },
+                    Segment::Any => {},
+                    _ => return None,
+                }
+                for (segment, label) in rest.iter().zip(rest_labels) {
+                    match segment {
+                        Segment::Exact(exact) if label.eq_ignore_ascii_case(exact) => {},
+                        Segment::Param(name) => { params.insert(*name, PathParam::Required(label.to_ascii_lowercase())); },
+                        _ => return None,
+                    }
+                }
+            },
+            Rule::Header(name, segment) => {
+                let value = req.headers().get(name)?.to_str().ok()?.trim();
+                match segment {
+                    Segment::Exact(exact) if value == *exact => {},
+                    Segment::Param(name) | Segment::Params(name) => { params.insert(*name, PathParam::Required(value.to_string())); },
+                    Segment::Any => {},
+                    _ => return None,
+                }
+            },
+        }
+        Some(params)
+    }
+}
+
+
+/// Dispatch the requests of one listener into several routers by `Host` or other headers, the rules will be matched in the
+/// order they were added, the fallback router will be used if no rule matched. The captured host labels or header values
+/// will be preset as path params, e.g. `req.param("tenant")`
+#[derive(Debug, Default)]
+pub struct Dispatcher {
+    rules: Vec<(Rule, Arc<Router<HyperHttpProtocol>>)>,
+    fallback: Option<Arc<Router<HyperHttpProtocol>>>,
+}
+
+impl Dispatcher {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Add a host rule, the pattern labels could be exact like `api.drunkce.com`, a param like `{tenant}.drunkce.com`,
+    /// or leftmost wildcards like `*.drunkce.com` or `{subdomains*}.drunkce.com` to match one or more labels
+    pub fn host(mut self, pattern: &'static str, router: Arc<Router<HyperHttpProtocol>>) -> Self {
+        let segments = pattern.trim_end_matches('.').split('.').map(Segment::parse).collect();
+        self.rules.push((Rule::Host(segments), router));
+        self
+    }
+
+    /// Add a header rule, the name is case-insensitive, and the value pattern could be exact like `2`, `*` to match any value,
+    /// or a param like `{tenant}`. An error will be returned if the name was not a valid header name
+    pub fn header(mut self, name: &str, pattern: &'static str, router: Arc<Router<HyperHttpProtocol>>) -> DceResult<Self> {
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| DceErr::closed0(format!(r#"Invalid header name "{name}": {e}"#)))?;
+        self.rules.push((Rule::Header(name, Segment::parse(pattern)), router));
+        Ok(self)
+    }
+
+    /// Set the router to use if no rule matched, a 404 will be responded if not set
+    pub fn fallback(mut self, router: Arc<Router<HyperHttpProtocol>>) -> Self {
+        self.fallback = Some(router);
+        self
+    }
+
+    /// Select the router and route the request
+    pub async fn dispatch(&self, mut rp: HyperHttpProtocol, context_data: HashMap<String, Box<dyn Any + Send>>) -> Result<Response<BoxBody<Bytes, Infallible>>, Infallible> {
+        let matched = rp.req().ok().and_then(|req| self.rules.iter().find_map(|(rule, router)| rule.matches(req).map(|params| (router, params))));
+        let (router, params) = match matched {
+            Some(matched) => matched,
+            None => match &self.fallback {
+                Some(router) => (router, HashMap::new()),
+                None => {
+                    let err = DceErr::openly(CODE_NOT_FOUND, "No router matched the request host");
+                    let mut resp = Response::new(Full::from(err.to_responsible()).boxed());
+                    *resp.status_mut() = StatusCode::NOT_FOUND;
+                    return Ok(resp);
+                },
+            },
+        };
+        params.into_iter().for_each(|(key, value)| rp.put_param(key, value));
+        rp.route(router.clone(), context_data).await
+    }
+}
+
+impl From<Arc<Router<HyperHttpProtocol>>> for Dispatcher {
+    fn from(router: Arc<Router<HyperHttpProtocol>>) -> Self {
+        Self::new().fallback(router)
+    }
+}
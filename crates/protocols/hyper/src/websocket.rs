@@ -0,0 +1,152 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use http_body_util::{BodyExt, Empty};
+use hyper::header::{CONNECTION, HeaderMap, SEC_WEBSOCKET_ACCEPT, SEC_WEBSOCKET_EXTENSIONS, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_PROTOCOL, SEC_WEBSOCKET_VERSION, UPGRADE};
+use hyper::{Method, Response, StatusCode, Uri};
+use hyper_util::rt::TokioIo;
+use log::{debug, error};
+use tokio::sync::watch;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::WebSocketStream;
+#[cfg(feature = "session")]
+use dce_router::protocol::HEAD_SID_NAME;
+use dce_router::protocol::RoutableProtocol;
+use dce_router::request::Response as DceResponse;
+use dce_router::router::Router;
+use dce_tokio_tungstenite::deflate::{self, DeflateStream};
+use dce_tokio_tungstenite::driver::SemiWebsocketDriver;
+use dce_tokio_tungstenite::envelope::Framing;
+use dce_tokio_tungstenite::protocol::SemiWebsocketProtocol;
+use dce_util::mixed::{DceErr, DceResult};
+use crate::protocol::{Http, HyperHttpProtocol};
+use crate::sse::SHUTDOWN_DATA_NAME;
+
+/// The context data key of the [Handshake], can be got in the websocket router with `context.get_as::<Arc<Handshake>>(HANDSHAKE_DATA_NAME)`
+pub const HANDSHAKE_DATA_NAME: &str = "$#handshake#";
+
+
+/// The upgrading http request info, will be shared by all the messages of the upgraded connection
+#[derive(Debug)]
+pub struct Handshake {
+    uri: Uri,
+    headers: HeaderMap,
+    sid: Option<String>,
+    session: Mutex<Option<Box<dyn Any + Send>>>,
+}
+
+impl Handshake {
+    pub fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    pub fn headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    /// The session id of the handshake request, a new sid will be used if the http middlewares responded it
+    pub fn sid(&self) -> Option<&str> {
+        self.sid.as_deref()
+    }
+
+    /// Take the session bound by the http middlewares, it can only be taken once, the later will get None
+    pub fn take_session<S: 'static>(&self) -> Option<S> {
+        let mut session = self.session.lock().ok()?;
+        match session.take()?.downcast::<S>() {
+            Ok(session) => Some(*session),
+            Err(origin) => {
+                // put it back if not the expected type
+                *session = Some(origin);
+                None
+            },
+        }
+    }
+}
+
+
+pub trait WebsocketUpgrader {
+    /// Upgrade current http request to websocket, then route the messages of the upgraded connection with the websocket router.
+    /// The sid of the handshake request will be carried into the messages which have no sid, and the session bound in http
+    /// context will be moved into the [Handshake], the handshake can be got from context data with key [HANDSHAKE_DATA_NAME].
+    /// The http connection must be served `with_upgrades()`, or the upgrading will fail. The permessage-deflate extension will
+    /// be negotiated if it was switched on in the websocket router extras, and the [Framing] subprotocol will be negotiated if offered.
+    fn upgrade_websocket(self, router: Arc<Router<SemiWebsocketProtocol>>, binary: bool) -> DceResult<Option<DceResponse<<HyperHttpProtocol as RoutableProtocol>::Resp>>>;
+}
+
+impl<ReqDto, RespDto> WebsocketUpgrader for Http<'_, ReqDto, RespDto> {
+    fn upgrade_websocket(mut self, router: Arc<Router<SemiWebsocketProtocol>>, binary: bool) -> DceResult<Option<DceResponse<<HyperHttpProtocol as RoutableProtocol>::Resp>>> {
+        let req = self.rp_mut().req_mut().as_mut().ok_or_else(|| DceErr::closed0("Empty request"))?;
+        let header_contains = |name, value: &str| req.headers().get_all(name).iter()
+            .any(|v| v.to_str().is_ok_and(|v| v.split(',').any(|v| v.trim().eq_ignore_ascii_case(value))));
+        if req.method() != Method::GET || ! header_contains(CONNECTION, "upgrade") || ! header_contains(UPGRADE, "websocket") {
+            return Err(DceErr::openly(400, "Not a websocket upgrade request".to_string()));
+        } else if ! header_contains(SEC_WEBSOCKET_VERSION, "13") {
+            return Err(DceErr::openly(426, "Unsupported websocket version".to_string()));
+        }
+        let accept_key = derive_accept_key(req.headers().get(SEC_WEBSOCKET_KEY)
+            .ok_or_else(|| DceErr::openly(400, "Missing websocket key".to_string()))?.as_bytes());
+        let extension = deflate::negotiate(req.headers().get_all(SEC_WEBSOCKET_EXTENSIONS).iter().filter_map(|v| v.to_str().ok()))
+            .filter(|_| deflate::enabled(&router));
+        let framing = Framing::negotiate(req.headers().get_all(SEC_WEBSOCKET_PROTOCOL).iter().filter_map(|v| v.to_str().ok()));
+        let on_upgrade = hyper::upgrade::on(&mut *req);
+        let (uri, headers) = (req.uri().clone(), req.headers().clone());
+        #[cfg(feature = "session")]
+        let sid = match self.rp_mut().get_resp_sid() {
+            // an empty resp sid means the sid was cleared
+            Some(sid) => Some(sid.clone()).filter(|sid| ! sid.is_empty()),
+            None => self.rp().sid().map(ToString::to_string),
+        };
+        #[cfg(feature = "session")]
+        let session = HyperHttpProtocol::take_session(&mut self);
+        #[cfg(not(feature = "session"))]
+        let (sid, session) = (None, None);
+        let handshake = Arc::new(Handshake { uri, headers, sid, session: Mutex::new(session) });
+        // hold the drain receiver of the server, so the drain waits for the upgraded connection, and stop it after the server stopped
+        let mut drain = self.get_as::<watch::Receiver<bool>>(SHUTDOWN_DATA_NAME).ok().cloned();
+
+        tokio::spawn(async move {
+            let upgraded = match on_upgrade.await {
+                Ok(upgraded) => upgraded,
+                Err(err) => return error!("websocket upgrade failed: {err}"),
+            };
+            let config = SemiWebsocketProtocol::ws_config(&router);
+            let stream = DeflateStream::new(TokioIo::new(upgraded), extension.is_some(), config.max_message_size.unwrap_or(usize::MAX));
+            let ws_stream = WebSocketStream::from_raw_socket(stream, Role::Server, Some(config)).await;
+            let headers = handshake.headers().iter().fold(HashMap::<String, String>::new(), |mut headers, (name, value)| {
+                let value = String::from_utf8_lossy(value.as_bytes());
+                headers.entry(name.to_string()).and_modify(|v| { v.push_str(", "); v.push_str(&value); }).or_insert_with(|| value.to_string());
+                headers
+            });
+            let driver = SemiWebsocketDriver::new(router).binary(binary).headers(headers);
+            let driving = driver.drive(ws_stream, |_rp| {
+                #[cfg(feature = "session")]
+                if let (None, Some(sid)) = (_rp.sid(), handshake.sid()) {
+                    _rp.heads_mut().insert(HEAD_SID_NAME.to_string(), sid.to_string());
+                }
+                HashMap::from([(HANDSHAKE_DATA_NAME.to_string(), Box::new(handshake.clone()) as Box<dyn Any + Send>)])
+            });
+            tokio::select! {
+                _ = driving => debug!("upgraded websocket connection closed"),
+                _ = async { match drain.as_mut() {
+                    Some(drain) => while drain.changed().await.is_ok() {},
+                    None => std::future::pending().await,
+                } } => debug!("upgraded websocket connection stopped with the server"),
+            }
+        });
+
+        let mut resp = Response::builder()
+            .status(StatusCode::SWITCHING_PROTOCOLS)
+            .header(CONNECTION, "Upgrade")
+            .header(UPGRADE, "websocket")
+            .header(SEC_WEBSOCKET_ACCEPT, accept_key);
+        if let Some(extension) = extension {
+            resp = resp.header(SEC_WEBSOCKET_EXTENSIONS, extension);
+        }
+        if let Some(framing) = framing {
+            resp = resp.header(SEC_WEBSOCKET_PROTOCOL, framing.subprotocol());
+        }
+        let resp = resp.body(Empty::new().boxed()).map_err(DceErr::closed0)?;
+        self.raw_resp(resp)
+    }
+}
@@ -0,0 +1,101 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use http_body_util::{BodyExt, Empty};
+use hyper::body::Bytes;
+use hyper::header::{ACCESS_CONTROL_ALLOW_ORIGIN, AUTHORIZATION, ETAG, IF_NONE_MATCH, ORIGIN};
+use hyper::{Request, StatusCode};
+use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioExecutor;
+use tokio::test;
+use dce_hyper::cache::{CachePolicy, ResponseCache, RESPONSE_CACHE_EXTRA_NAME};
+use dce_hyper::cors::Cors;
+use dce_hyper::protocol::HttpRaw;
+use dce_hyper::server::ServeOptions;
+use dce_macro::api;
+use dce_router::router::Router;
+use dce_router::serializer::Serialized;
+
+mod common;
+
+static HANDLED: AtomicUsize = AtomicUsize::new(0);
+static GREETED: AtomicUsize = AtomicUsize::new(0);
+
+#[api("counted/{id}", name = "counted", cache = CachePolicy::new().control("public").ttl(Duration::from_millis(500)))]
+async fn counted(req: HttpRaw) {
+    let handled = HANDLED.fetch_add(1, Ordering::SeqCst) + 1;
+    req.pack(Serialized::String(handled.to_string()))
+}
+
+#[api("greeting", cache = CachePolicy::new().ttl(Duration::from_secs(10)), cors = Cors::new().origins(["https://a.com", "https://b.com"]))]
+async fn greeting(req: HttpRaw) {
+    GREETED.fetch_add(1, Ordering::SeqCst);
+    req.pack(Serialized::String("greeting".to_string()))
+}
+
+#[test]
+async fn keep_and_evict() {
+    let cache = ResponseCache::new(2);
+    let router = Router::new().unwrap().set_extra(RESPONSE_CACHE_EXTRA_NAME, Box::new(cache.clone())).push(counted).ready().unwrap();
+    let (addr, _, _) = common::serve(router.clone(), ServeOptions::new()).await;
+    let client = Client::builder(TokioExecutor::new()).build_http::<Empty<Bytes>>();
+
+    let (status, etag, body) = get(&client, addr, "counted/a", &[]).await;
+    assert_eq!((status, body.as_str()), (StatusCode::OK, "1"));
+    assert_eq!(get(&client, addr, "counted/a", &[]).await.2, "1");
+    let etag = etag.unwrap();
+    assert_eq!(get(&client, addr, "counted/a", &[(IF_NONE_MATCH.as_str(), &etag)]).await.0, StatusCode::NOT_MODIFIED);
+    assert_eq!(get(&client, addr, "counted/a", &[(IF_NONE_MATCH.as_str(), r#""other""#)]).await.0, StatusCode::OK);
+    // the credentialed requests are handled every time, and their responses are not kept
+    assert_eq!(get(&client, addr, "counted/a", &[(AUTHORIZATION.as_str(), "Bearer x")]).await.2, "2");
+    assert_eq!(get(&client, addr, "counted/a", &[(AUTHORIZATION.as_str(), "Bearer y")]).await.2, "3");
+    assert_eq!(get(&client, addr, "counted/a", &[]).await.2, "1");
+
+    // the least recently used `a` was evicted by `c`
+    assert_eq!(get(&client, addr, "counted/b", &[]).await.2, "4");
+    assert_eq!(get(&client, addr, "counted/c", &[]).await.2, "5");
+    assert_eq!(get(&client, addr, "counted/a", &[]).await.2, "6");
+    assert_eq!(get(&client, addr, "counted/c", &[]).await.2, "5");
+
+    assert_eq!(cache.invalidate("counted"), 2);
+    assert_eq!(get(&client, addr, "counted/c", &[]).await.2, "7");
+    assert_eq!(get(&client, addr, "counted/c", &[]).await.2, "7");
+    tokio::time::sleep(Duration::from_millis(600)).await;
+    assert_eq!(get(&client, addr, "counted/c", &[]).await.2, "8");
+}
+
+#[test]
+async fn cors_origins() {
+    let router = Router::new().unwrap().set_extra(RESPONSE_CACHE_EXTRA_NAME, Box::new(ResponseCache::new(8))).push(greeting).ready().unwrap();
+    let (addr, _, _) = common::serve(router.clone(), ServeOptions::new()).await;
+    let client = Client::builder(TokioExecutor::new()).build_http::<Empty<Bytes>>();
+    let allow_origin = |origin: Option<&'static str>| {
+        let client = client.clone();
+        async move {
+            let request = origin.into_iter().fold(Request::get(format!("http://{addr}/greeting")), |r, o| r.header(ORIGIN, o));
+            let resp = client.request(request.body(Empty::new()).unwrap()).await.unwrap();
+            // the etag is stable across the builds
+            assert_eq!(resp.headers().get(ETAG).unwrap(), r#""8-dbdc244fa0b52af6""#);
+            resp.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).map(|v| v.to_str().unwrap().to_string())
+        }
+    };
+
+    assert_eq!(allow_origin(Some("https://a.com")).await.as_deref(), Some("https://a.com"));
+    // the origins do not share the cached responses decorated for the others
+    assert_eq!(allow_origin(Some("https://b.com")).await.as_deref(), Some("https://b.com"));
+    assert_eq!(allow_origin(None).await, None);
+    assert_eq!(GREETED.load(Ordering::SeqCst), 3);
+    assert_eq!(allow_origin(Some("https://a.com")).await.as_deref(), Some("https://a.com"));
+    assert_eq!(allow_origin(None).await, None);
+    assert_eq!(GREETED.load(Ordering::SeqCst), 3);
+}
+
+async fn get(client: &Client<HttpConnector, Empty<Bytes>>, addr: SocketAddr, path: &str, headers: &[(&str, &str)]) -> (StatusCode, Option<String>, String) {
+    let request = headers.iter().fold(Request::get(format!("http://{addr}/{path}")), |r, (k, v)| r.header(*k, *v));
+    let resp = client.request(request.body(Empty::new()).unwrap()).await.unwrap();
+    let etag = resp.headers().get(ETAG).map(|v| v.to_str().unwrap().to_string());
+    let status = resp.status();
+    let body = String::from_utf8(resp.into_body().collect().await.unwrap().to_bytes().to_vec()).unwrap();
+    (status, etag, body)
+}
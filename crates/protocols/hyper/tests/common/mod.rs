@@ -0,0 +1,56 @@
+// every test crate uses a part of the helpers
+#![allow(dead_code)]
+
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use dce_hyper::protocol::HyperHttpProtocol;
+use dce_hyper::server::ServeOptions;
+use dce_hyper::vhost::Dispatcher;
+use dce_util::mixed::DceResult;
+
+/// Serve the router on a free local port and wait until it is accepting, the server will be shut down when the sender sent,
+/// but not when dropped
+pub async fn serve(router: impl Into<Dispatcher>, options: ServeOptions) -> (SocketAddr, oneshot::Sender<()>, JoinHandle<DceResult<()>>) {
+    let addr = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap();
+    let (shutdown, signal) = oneshot::channel::<()>();
+    let server = tokio::spawn(HyperHttpProtocol::serve(router.into(), addr, options.shutdown(async {
+        if signal.await.is_err() {
+            std::future::pending::<()>().await;
+        }
+    })));
+    for _ in 0..50 {
+        if TcpStream::connect(addr).await.is_ok() { break; }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    (addr, shutdown, server)
+}
+
+/// Send a http/1.1 request with `Connection: close` and read the whole response, the `Host` defaults to `localhost`
+pub async fn request(addr: SocketAddr, method: &str, path: &str, headers: &[(&str, &str)]) -> String {
+    let host = if headers.iter().any(|(k, _)| k.eq_ignore_ascii_case("host")) { "" } else { "Host: localhost\r\n" };
+    let headers: String = headers.iter().map(|(k, v)| format!("{k}: {v}\r\n")).collect();
+    exchange(addr, &format!("{method} /{path} HTTP/1.1\r\n{host}{headers}Connection: close\r\n\r\n")).await
+}
+
+/// Send the raw request and read until the server closed the connection
+pub async fn exchange(addr: SocketAddr, raw: &str) -> String {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(raw.as_bytes()).await.unwrap();
+    let mut resp = String::new();
+    stream.read_to_string(&mut resp).await.unwrap();
+    resp
+}
+
+/// Find the header value in the response head case-insensitively
+pub fn header(resp: &str, name: &str) -> Option<String> {
+    resp.split("\r\n\r\n").next()?.lines().skip(1)
+        .find_map(|line| line.split_once(':').filter(|(k, _)| k.eq_ignore_ascii_case(name)).map(|(_, v)| v.trim().to_string()))
+}
+
+pub fn body(resp: &str) -> &str {
+    resp.split_once("\r\n\r\n").map_or("", |(_, body)| body)
+}
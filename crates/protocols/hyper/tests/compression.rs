@@ -0,0 +1,57 @@
+use std::convert::Infallible;
+use std::io::Read;
+use http_body_util::{BodyExt, Full};
+use http_body_util::combinators::BoxBody;
+use hyper::body::Bytes;
+use hyper::header::{CACHE_CONTROL, CONTENT_ENCODING, CONTENT_TYPE, ETAG, VARY};
+use hyper::{Response, StatusCode};
+use tokio::test;
+use dce_hyper::compression::{Compression, Encoding};
+
+fn response(content_type: &str, body: &str) -> Response<BoxBody<Bytes, Infallible>> {
+    Response::builder().header(CONTENT_TYPE, content_type).header(ETAG, r#""abc""#).body(Full::from(body.to_string()).boxed()).unwrap()
+}
+
+async fn body(resp: Response<BoxBody<Bytes, Infallible>>) -> Vec<u8> {
+    resp.into_body().collect().await.unwrap().to_bytes().to_vec()
+}
+
+#[test]
+async fn negotiate() {
+    let compression = Compression::new();
+    assert_eq!(compression.negotiate(["gzip, deflate, br"]), Some(Encoding::Brotli));
+    assert_eq!(compression.negotiate(["gzip;q=0.8", "deflate;q=0.9"]), Some(Encoding::Deflate));
+    assert_eq!(compression.negotiate(["*;q=0.5, br;q=0"]), Some(Encoding::Gzip));
+    assert_eq!(compression.negotiate(["identity"]), None);
+    assert_eq!(Compression::new().encodings([Encoding::Gzip]).negotiate(["br, gzip"]), Some(Encoding::Gzip));
+}
+
+#[test]
+async fn compress() {
+    let compression = Compression::new().min_size(16);
+    let text = "dce ".repeat(64);
+
+    let resp = compression.compress(response("text/plain; charset=utf-8", &text), ["gzip"]).await;
+    assert_eq!(resp.headers().get(CONTENT_ENCODING).unwrap(), "gzip");
+    assert_eq!(resp.headers().get(VARY).unwrap(), "Accept-Encoding");
+    assert_eq!(resp.headers().get(ETAG).unwrap(), r#"W/"abc""#);
+    let mut decoded = String::new();
+    flate2::read::GzDecoder::new(&body(resp).await[..]).read_to_string(&mut decoded).unwrap();
+    assert_eq!(decoded, text);
+
+    let resp = compression.compress(response("application/json", &text), ["br"]).await;
+    let mut decoded = String::new();
+    brotli::Decompressor::new(&body(resp).await[..], 4096).read_to_string(&mut decoded).unwrap();
+    assert_eq!(decoded, text);
+
+    // passed through: too small, not compressible, no-transform, not successful
+    for resp in [
+        response("text/plain", "short"),
+        response("image/png", &text),
+        Response::builder().header(CACHE_CONTROL, "no-transform").body(Full::from(text.clone()).boxed()).unwrap(),
+        Response::builder().status(StatusCode::NOT_FOUND).body(Full::from(text.clone()).boxed()).unwrap(),
+    ] {
+        let resp = compression.compress(resp, ["gzip"]).await;
+        assert!(resp.headers().get(CONTENT_ENCODING).is_none());
+    }
+}
@@ -0,0 +1,21 @@
+use std::time::Duration;
+use dce_hyper::cookie::{Cookie, SameSite};
+
+#[test]
+fn plain() {
+    let cookie = Cookie::new("token", "a1-B2_c3.d4").path("/").max_age(Duration::from_secs(60)).http_only(true).same_site(SameSite::Lax);
+    assert_eq!(cookie.to_string(), "token=a1-B2_c3.d4; Path=/; Max-Age=60; HttpOnly; SameSite=Lax");
+}
+
+#[test]
+fn injection_encoded() {
+    let cookie = Cookie::new("to;ken=", "x; Path=/evil\r\nSet-Cookie: admin=1").path("/;Secure").domain("a.com\r\n");
+    assert_eq!(cookie.to_string(), "to%3Bken%3D=x%3B%20Path=/evil%0D%0ASet-Cookie:%20admin=1; Path=/%3BSecure; Domain=a.com%0D%0A");
+    // the origin will be kept
+    assert_eq!(cookie.value(), "x; Path=/evil\r\nSet-Cookie: admin=1");
+}
+
+#[test]
+fn non_ascii_encoded() {
+    assert_eq!(Cookie::new("名", "\"值\"").to_string(), "%E5%90%8D=%22%E5%80%BC%22");
+}
@@ -0,0 +1,83 @@
+use std::net::SocketAddr;
+use tokio::test;
+use dce_hyper::cors::{Cors, CORS_EXTRA_NAME};
+use dce_hyper::protocol::HttpMethod::{Delete, Get, Post};
+use dce_hyper::protocol::HttpRaw;
+use dce_hyper::server::ServeOptions;
+use dce_macro::api;
+use dce_router::router::Router;
+use dce_router::serializer::Serialized;
+use crate::common::{body, header, request};
+
+mod common;
+
+#[api("items", method = [Get, Post])]
+async fn items(req: HttpRaw) {
+    req.pack(Serialized::String("items".to_string()))
+}
+
+#[api("items", method = Delete, name = "remove")]
+async fn remove(req: HttpRaw) {
+    req.pack(Serialized::String("removed".to_string()))
+}
+
+#[api("open", cors = Cors::new().credentials(true).max_age(600).expose_headers(["X-Total"]))]
+async fn open(req: HttpRaw) {
+    req.pack(Serialized::String("open".to_string()))
+}
+
+#[test]
+async fn preflight() {
+    let addr = start().await;
+    let resp = request(addr, "OPTIONS", "items", &[("Origin", "https://drunkce.com"), ("Access-Control-Request-Method", "DELETE")]).await;
+    assert!(resp.starts_with("HTTP/1.1 204"), "{resp}");
+    assert_eq!(header(&resp, "access-control-allow-origin").as_deref(), Some("https://drunkce.com"));
+    // the union of the methods of all the apis matched the path, though the apis did not declare OPTIONS
+    assert_eq!(header(&resp, "access-control-allow-methods").as_deref(), Some("DELETE, GET, POST"));
+    assert_eq!(header(&resp, "access-control-allow-headers").as_deref(), Some("Content-Type"));
+    assert!(header(&resp, "vary").is_some_and(|v| v.contains("Access-Control-Request-Method")));
+    assert_eq!(header(&resp, "access-control-allow-credentials"), None);
+    assert_eq!(body(&resp), "");
+
+    let resp = request(addr, "OPTIONS", "items", &[("Origin", "https://evil.com"), ("Access-Control-Request-Method", "GET")]).await;
+    assert!(resp.starts_with("HTTP/1.1 204"), "{resp}");
+    assert_eq!(header(&resp, "access-control-allow-origin"), None);
+
+    // the api config overrides the global one
+    let resp = request(addr, "OPTIONS", "open", &[("Origin", "https://evil.com"), ("Access-Control-Request-Method", "GET"),
+        ("Access-Control-Request-Headers", "X-Token")]).await;
+    assert_eq!(header(&resp, "access-control-allow-origin").as_deref(), Some("https://evil.com"));
+    assert_eq!(header(&resp, "access-control-allow-credentials").as_deref(), Some("true"));
+    assert_eq!(header(&resp, "access-control-allow-headers").as_deref(), Some("X-Token"));
+    assert_eq!(header(&resp, "access-control-max-age").as_deref(), Some("600"));
+
+    assert!(request(addr, "OPTIONS", "missing", &[("Origin", "https://drunkce.com"), ("Access-Control-Request-Method", "GET")]).await
+        .starts_with("HTTP/1.1 404"));
+}
+
+#[test]
+async fn actual() {
+    let addr = start().await;
+    let resp = request(addr, "POST", "items", &[("Origin", "https://drunkce.com"), ("Content-Length", "0")]).await;
+    assert!(resp.starts_with("HTTP/1.1 200") && body(&resp) == "items", "{resp}");
+    assert_eq!(header(&resp, "access-control-allow-origin").as_deref(), Some("https://drunkce.com"));
+    assert_eq!(header(&resp, "vary").as_deref(), Some("Origin"));
+    let resp = request(addr, "GET", "items", &[("Origin", "https://evil.com")]).await;
+    assert!(resp.starts_with("HTTP/1.1 200"), "{resp}");
+    assert_eq!(header(&resp, "access-control-allow-origin"), None);
+
+    let resp = request(addr, "GET", "open", &[("Origin", "https://evil.com")]).await;
+    assert_eq!(header(&resp, "access-control-allow-origin").as_deref(), Some("https://evil.com"));
+    assert_eq!(header(&resp, "access-control-allow-credentials").as_deref(), Some("true"));
+    assert_eq!(header(&resp, "access-control-expose-headers").as_deref(), Some("X-Total"));
+    // not a cors request
+    assert_eq!(header(&request(addr, "GET", "items", &[]).await, "access-control-allow-origin"), None);
+}
+
+
+async fn start() -> SocketAddr {
+    let router = Router::new().unwrap()
+        .set_extra(CORS_EXTRA_NAME, Box::new(Cors::new().origins(["https://drunkce.com/"]).headers(["Content-Type"])))
+        .push(items).push(remove).push(open).ready().unwrap();
+    common::serve(router.clone(), ServeOptions::new()).await.0
+}
@@ -0,0 +1,39 @@
+use std::time::Duration;
+use tokio::test;
+use dce_hyper::protocol::HttpMethod::Post;
+use dce_hyper::protocol::HttpRaw;
+use dce_hyper::server::ServeOptions;
+use dce_macro::api;
+use dce_router::protocol::{BODY_TIMEOUT_EXTRA_NAME, MAX_BODY_EXTRA_NAME, RoutableProtocol};
+use dce_router::router::Router;
+use dce_router::serializer::Serialized;
+
+mod common;
+
+#[api("upload", method = Post)]
+async fn upload(mut req: HttpRaw) {
+    let size = match req.rp_mut().body().await? {
+        Serialized::Bytes(bytes) => bytes.len(),
+        Serialized::String(str) => str.len(),
+    };
+    req.pack(Serialized::String(size.to_string()))
+}
+
+#[test]
+async fn body_limits() {
+    let router = Router::new().unwrap()
+        .set_extra(MAX_BODY_EXTRA_NAME, Box::new(16usize))
+        .set_extra(BODY_TIMEOUT_EXTRA_NAME, Box::new(Duration::from_millis(100)))
+        .push(upload).ready().unwrap();
+    let (addr, _, _) = common::serve(router.clone(), ServeOptions::new()).await;
+    let post = |length: usize, body: &'static str| async move {
+        common::exchange(addr, &format!("POST /upload HTTP/1.1\r\nHost: localhost\r\nContent-Length: {length}\r\nConnection: close\r\n\r\n{body}")).await
+    };
+
+    let resp = post(16, "0123456789abcdef").await;
+    assert!(resp.starts_with("HTTP/1.1 200") && resp.ends_with("\r\n\r\n16"), "{resp}");
+    // rejected by the declared length before reading
+    assert!(post(17, "0123456789abcdefg").await.starts_with("HTTP/1.1 413"));
+    // the body was not finished in time
+    assert!(post(10, "01234").await.starts_with("HTTP/1.1 408"));
+}
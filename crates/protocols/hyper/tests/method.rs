@@ -0,0 +1,63 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use http_body_util::{BodyExt, Full};
+use hyper::Response;
+use tokio::test;
+use dce_hyper::protocol::HttpMethod::{Get, Options, Post, Put};
+use dce_hyper::protocol::HttpRaw;
+use dce_hyper::server::ServeOptions;
+use dce_macro::api;
+use dce_router::router::Router;
+use dce_router::serializer::Serialized;
+use crate::common::{header, request};
+
+mod common;
+
+static HANDLED: AtomicUsize = AtomicUsize::new(0);
+
+#[api("doc", method = [Get, Post])]
+async fn doc(req: HttpRaw) {
+    HANDLED.fetch_add(1, Ordering::SeqCst);
+    req.pack(Serialized::String("hello".to_string()))
+}
+
+#[api("doc", method = Put, name = "put_doc")]
+async fn put_doc(req: HttpRaw) {
+    HANDLED.fetch_add(1, Ordering::SeqCst);
+    req.pack(Serialized::String("put".to_string()))
+}
+
+#[api("raw")]
+async fn raw(req: HttpRaw) {
+    req.raw_resp(Response::new(Full::from("raw body").boxed()))
+}
+
+#[api("custom", method = [Get, Options])]
+async fn custom(req: HttpRaw) {
+    req.pack(Serialized::String("custom options".to_string()))
+}
+
+#[test]
+async fn head_and_options() {
+    let router = Router::new().unwrap().push(doc).push(put_doc).push(raw).push(custom).ready().unwrap();
+    let (addr, _, _) = common::serve(router.clone(), ServeOptions::new()).await;
+
+    // HEAD runs the GET controller, but drops the body and keeps the length
+    for (path, length) in [("doc", "5"), ("raw", "8")] {
+        let resp = request(addr, "HEAD", path, &[]).await;
+        assert!(resp.starts_with("HTTP/1.1 200") && resp.ends_with("\r\n\r\n"), "{resp}");
+        assert_eq!(header(&resp, "content-length").as_deref(), Some(length), "{resp}");
+    }
+    assert_eq!(HANDLED.load(Ordering::SeqCst), 1);
+    assert!(request(addr, "GET", "doc", &[]).await.ends_with("\r\n\r\nhello"));
+
+    // OPTIONS is answered with the methods of all the apis of the path, and not reached the controllers
+    let resp = request(addr, "OPTIONS", "doc", &[]).await;
+    assert!(resp.starts_with("HTTP/1.1 204"), "{resp}");
+    assert_eq!(header(&resp, "allow").as_deref(), Some("GET, HEAD, OPTIONS, POST, PUT"));
+    assert_eq!(HANDLED.load(Ordering::SeqCst), 2);
+    // unless the api opted in
+    let resp = request(addr, "OPTIONS", "custom", &[]).await;
+    assert!(resp.starts_with("HTTP/1.1 200") && resp.ends_with("\r\n\r\ncustom options"), "{resp}");
+    assert!(request(addr, "OPTIONS", "missing", &[]).await.starts_with("HTTP/1.1 404"));
+}
+
@@ -0,0 +1,95 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::OnceLock;
+use std::time::Duration;
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode, Uri};
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use tokio::net::TcpListener;
+use tokio::test;
+use dce_hyper::protocol::HttpMethod::{Delete, Get, Head, Patch, Post, Put};
+use dce_hyper::protocol::HttpRaw;
+use dce_hyper::proxy::{ProxyResponder, Upstream};
+use dce_hyper::server::ServeOptions;
+use dce_macro::api;
+use dce_router::router::Router;
+
+mod common;
+
+static UPSTREAM: OnceLock<Upstream> = OnceLock::new();
+static SLOW_ATTEMPTS: AtomicUsize = AtomicUsize::new(0);
+
+#[api("legacy/{path*}", method = [Get, Head, Post, Put, Patch, Delete])]
+async fn legacy(req: HttpRaw) {
+    req.proxy(UPSTREAM.get()).await
+}
+
+#[test]
+async fn forward() {
+    let upstream = stand_in().await;
+    UPSTREAM.set(Upstream::new(format!("http://{upstream}/base").parse::<Uri>().unwrap())
+        .timeout(Duration::from_millis(200)).retries(1)).unwrap();
+    let gateway = gateway().await;
+    let client = Client::builder(TokioExecutor::new()).build_http::<Full<Bytes>>();
+    let request = |method: Method, path: &str| Request::builder().method(method).uri(format!("http://{gateway}/{path}"))
+        .header("Connection", "keep-alive, X-Hop").header("X-Hop", "1").header("X-Custom", "dce")
+        .body(Full::new(Bytes::from("payload"))).unwrap();
+
+    let resp = client.request(request(Method::POST, "legacy/a/b.json?q=1")).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp.headers().get("X-Upstream-Hop").is_none());
+    let body = String::from_utf8(resp.into_body().collect().await.unwrap().to_bytes().to_vec()).unwrap();
+    let echoed: Vec<_> = body.lines().collect();
+    assert_eq!(echoed[0], "POST /base/a/b.json?q=1");
+    assert_eq!(echoed[1], format!("host: {upstream}"));
+    assert_eq!(echoed[2], "x-forwarded-for: 127.0.0.1");
+    assert_eq!(echoed[3], format!("x-forwarded-host: {gateway}"));
+    assert_eq!(echoed[4], "x-forwarded-proto: http");
+    assert_eq!(echoed[5], "x-custom: dce");
+    assert_eq!(echoed[6], "x-hop: ");
+    assert_eq!(echoed[7], "payload");
+
+    // the first attempt timed out and the retry succeed
+    let resp = client.request(request(Method::GET, "legacy/slow")).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(SLOW_ATTEMPTS.load(Ordering::SeqCst), 2);
+    // non idempotent requests will not be retried
+    let resp = client.request(request(Method::POST, "legacy/slow")).await.unwrap();
+    assert_eq!(resp.status(), StatusCode::GATEWAY_TIMEOUT);
+    assert_eq!(SLOW_ATTEMPTS.load(Ordering::SeqCst), 3);
+}
+
+
+async fn gateway() -> SocketAddr {
+    let router = Router::new().unwrap().push(legacy).ready().unwrap();
+    common::serve(router.clone(), ServeOptions::new()).await.0
+}
+
+/// An upstream stand-in echoes the request line, the interested headers and the body
+async fn stand_in() -> SocketAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(http1::Builder::new().serve_connection(TokioIo::new(stream), service_fn(echo)));
+        }
+    });
+    addr
+}
+
+async fn echo(req: Request<Incoming>) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    if req.uri().path().ends_with("slow") && SLOW_ATTEMPTS.fetch_add(1, Ordering::SeqCst) != 1 {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+    }
+    let mut lines = vec![format!("{} {}", req.method(), req.uri())];
+    for name in ["host", "x-forwarded-for", "x-forwarded-host", "x-forwarded-proto", "x-custom", "x-hop"] {
+        lines.push(format!("{}: {}", name, req.headers().get(name).and_then(|v| v.to_str().ok()).unwrap_or("")));
+    }
+    let (_, body) = req.into_parts();
+    lines.push(String::from_utf8(body.collect().await?.to_bytes().to_vec()).unwrap_or_default());
+    Ok(Response::builder().header("Connection", "X-Upstream-Hop").header("X-Upstream-Hop", "1").body(Full::new(Bytes::from(lines.join("\n")))).unwrap())
+}
@@ -0,0 +1,36 @@
+use hyper::StatusCode;
+use tokio::test;
+use dce_hyper::cookie::Cookie;
+use dce_hyper::protocol::HttpRaw;
+use dce_hyper::response::ResponseBuilder;
+use dce_hyper::server::ServeOptions;
+use dce_macro::api;
+use dce_router::router::Router;
+use dce_router::serializer::Serialized;
+use dce_util::mixed::DceErr;
+use crate::common::request;
+
+mod common;
+
+#[api("signin/{result}")]
+async fn signin(req: HttpRaw) {
+    let passed = req.param("result")?.as_str() == Some("passed");
+    let req = req.with_status(StatusCode::CREATED).with_header("X-Trace", "1")?.with_cookie(Cookie::new("token", "secret"))?;
+    if ! passed {
+        return Err(DceErr::openly(401, "Unauthorized"));
+    }
+    req.pack(Serialized::String("welcome".to_string()))
+}
+
+#[test]
+async fn drop_on_error() {
+    let router = Router::new().unwrap().push(signin).ready().unwrap();
+    let (addr, _, _) = common::serve(router.clone(), ServeOptions::new()).await;
+
+    let passed = request(addr, "GET", "signin/passed", &[]).await;
+    assert!(passed.starts_with("HTTP/1.1 201"), "{passed}");
+    assert!(passed.contains("\r\nx-trace: 1\r\n") && passed.contains("\r\nset-cookie: token=secret"), "{passed}");
+    let failed = request(addr, "GET", "signin/failed", &[]).await;
+    assert!(failed.starts_with("HTTP/1.1 401"), "{failed}");
+    assert!(! failed.contains("x-trace") && ! failed.contains("set-cookie"), "{failed}");
+}
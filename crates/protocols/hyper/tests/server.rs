@@ -0,0 +1,93 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use http_body_util::{BodyExt, Empty};
+use hyper::body::Bytes;
+use hyper::client::conn::http2;
+use hyper::{Request, Version};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::test;
+use dce_hyper::protocol::{HttpRaw, HyperHttpProtocol};
+use dce_hyper::server::ServeOptions;
+use dce_macro::api;
+use dce_router::router::Router;
+use dce_router::serializer::Serialized;
+use crate::common::request;
+
+mod common;
+
+#[api("sleep/{millis}")]
+async fn sleep(req: HttpRaw) {
+    let millis = req.param("millis")?.as_str().and_then(|m| m.parse().ok()).unwrap_or(0);
+    tokio::time::sleep(Duration::from_millis(millis)).await;
+    req.pack(Serialized::String(format!("slept {millis}")))
+}
+
+#[test]
+async fn drain() {
+    let (addr, shutdown, server) = common::serve(router(), ServeOptions::new().drain_timeout(Duration::from_secs(5))).await;
+    let in_flight = tokio::spawn(request(addr, "GET", "sleep/300", &[]));
+    // an idle keep-alive connection should be closed by the drain
+    let mut idle = TcpStream::connect(addr).await.unwrap();
+    idle.write_all(b"GET /sleep/0 HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+    let mut buf = [0; 1024];
+    assert!(idle.read(&mut buf).await.unwrap() > 0);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    shutdown.send(()).unwrap();
+    let started = Instant::now();
+    server.await.unwrap().unwrap();
+    // waited for the in-flight request
+    assert!(started.elapsed() >= Duration::from_millis(150));
+    assert!(in_flight.await.unwrap().ends_with("\r\n\r\nslept 300"));
+    assert_eq!(idle.read(&mut buf).await.unwrap(), 0);
+    assert!(TcpStream::connect(addr).await.is_err());
+}
+
+#[test]
+async fn drain_timeout() {
+    let (addr, shutdown, server) = common::serve(router(), ServeOptions::new().drain_timeout(Duration::from_millis(100))).await;
+    let in_flight = tokio::spawn(request(addr, "GET", "sleep/3000", &[]));
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    shutdown.send(()).unwrap();
+    let started = Instant::now();
+    server.await.unwrap().unwrap();
+    assert!(started.elapsed() < Duration::from_secs(1));
+    // the connection was aborted without response
+    let resp = tokio::time::timeout(Duration::from_secs(1), in_flight).await.unwrap();
+    assert!(! resp.is_ok_and(|resp| resp.contains("slept")));
+}
+
+#[test]
+async fn max_connections() {
+    let (addr, _shutdown, _server) = common::serve(router(), ServeOptions::new().max_connections(1)).await;
+    // the only permit was held by the silent connection
+    let silent = TcpStream::connect(addr).await.unwrap();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let waiting = tokio::spawn(request(addr, "GET", "sleep/0", &[]));
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert!(! waiting.is_finished());
+    drop(silent);
+    let resp = tokio::time::timeout(Duration::from_secs(2), waiting).await.unwrap().unwrap();
+    assert!(resp.ends_with("\r\n\r\nslept 0"), "{resp}");
+}
+
+#[test]
+async fn h2c() {
+    let (addr, _shutdown, _server) = common::serve(router(), ServeOptions::new()).await;
+    let stream = TcpStream::connect(addr).await.unwrap();
+    let (mut sender, conn) = http2::handshake(TokioExecutor::new(), TokioIo::new(stream)).await.unwrap();
+    tokio::spawn(conn);
+    let resp = sender.send_request(Request::get(format!("http://{addr}/sleep/0")).body(Empty::<Bytes>::new()).unwrap()).await.unwrap();
+    assert_eq!(resp.version(), Version::HTTP_2);
+    assert_eq!(resp.into_body().collect().await.unwrap().to_bytes(), "slept 0");
+    // http/1.1 on the same port
+    assert!(request(addr, "GET", "sleep/0", &[]).await.starts_with("HTTP/1.1 200"));
+}
+
+
+
+fn router() -> Arc<Router<HyperHttpProtocol>> {
+    Router::new().unwrap().push(sleep).ready().unwrap().clone()
+}
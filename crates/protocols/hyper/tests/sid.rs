@@ -0,0 +1,74 @@
+use std::net::SocketAddr;
+use tokio::test;
+use dce_hyper::protocol::{HttpRaw, HyperHttpProtocol};
+use dce_hyper::server::ServeOptions;
+use dce_hyper::sid::{SidCarrier, SidTransport, SID_TRANSPORT_EXTRA_NAME};
+use dce_macro::api;
+use dce_router::protocol::RoutableProtocol;
+use dce_router::router::Router;
+use dce_router::serializer::Serialized;
+use dce_session::session::Meta;
+use crate::common::{body, header, request};
+
+mod common;
+
+#[api("whoami")]
+async fn whoami(req: HttpRaw) {
+    let sid = req.rp().sid().unwrap_or_default().to_string();
+    req.pack(Serialized::String(sid))
+}
+
+#[api("login")]
+async fn login(mut req: HttpRaw) {
+    let (sid, _) = Meta::gen_id(30)?;
+    req.rp_mut().set_resp_sid(sid.clone());
+    req.pack(Serialized::String(sid))
+}
+
+#[api("logout")]
+async fn logout(mut req: HttpRaw) {
+    req.rp_mut().clear_resp_sid();
+    req.pack(Serialized::String("bye".to_string()))
+}
+
+#[test]
+async fn cookie_and_header() {
+    let addr = start(Router::new().unwrap().set_extra(SID_TRANSPORT_EXTRA_NAME, Box::new(SidTransport::new(SidCarrier::Both)
+        .cookie_name("sid").domain("drunkce.com").secure(true)))).await;
+    // the header takes precedence over the cookie
+    assert_eq!(body(&request(addr, "GET", "whoami", &[("Cookie", "a=1; sid=abc")]).await), "abc");
+    assert_eq!(body(&request(addr, "GET", "whoami", &[("Cookie", "session_id=abc")]).await), "");
+    assert_eq!(body(&request(addr, "GET", "whoami", &[("Cookie", "sid=abc"), ("X-Session-Id", "def")]).await), "def");
+
+    let resp = request(addr, "GET", "login", &[]).await;
+    let sid = body(&resp);
+    assert_eq!(header(&resp, "x-session-id").as_deref(), Some(sid));
+    assert_eq!(header(&resp, "set-cookie"), Some(format!("sid={sid}; Path=/; Domain=drunkce.com; Max-Age=1800; Secure; HttpOnly; SameSite=Lax")));
+
+    let resp = request(addr, "GET", "logout", &[("Cookie", format!("sid={sid}").as_str())]).await;
+    assert_eq!(header(&resp, "x-session-id"), None);
+    assert_eq!(header(&resp, "set-cookie").as_deref(),
+        Some("sid=; Path=/; Domain=drunkce.com; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT; Secure; HttpOnly; SameSite=Lax"));
+    // no sid responded if not changed
+    assert_eq!(header(&request(addr, "GET", "whoami", &[("Cookie", "sid=abc")]).await, "set-cookie"), None);
+
+    let addr = start(Router::new().unwrap().set_extra(SID_TRANSPORT_EXTRA_NAME, Box::new(SidTransport::new(SidCarrier::Cookie)))).await;
+    let resp = request(addr, "GET", "login", &[]).await;
+    assert_eq!(header(&resp, "x-session-id"), None);
+    assert!(header(&resp, "set-cookie").is_some_and(|c| c.starts_with(&format!("session_id={}; ", body(&resp)))));
+}
+
+#[test]
+async fn header_only() {
+    let addr = start(Router::new().unwrap()).await;
+    let resp = request(addr, "GET", "login", &[]).await;
+    assert_eq!(header(&resp, "x-session-id").as_deref(), Some(body(&resp)));
+    assert_eq!(header(&resp, "set-cookie"), None);
+    assert_eq!(header(&request(addr, "GET", "logout", &[]).await, "set-cookie"), None);
+}
+
+
+async fn start(router: Router<HyperHttpProtocol>) -> SocketAddr {
+    let router = router.push(whoami).push(login).push(logout).ready().unwrap();
+    common::serve(router.clone(), ServeOptions::new()).await.0
+}
@@ -0,0 +1,97 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper_util::rt::TokioIo;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::test;
+use dce_hyper::protocol::{HttpRaw, HyperHttpProtocol};
+use dce_hyper::server::ServeOptions;
+use dce_hyper::sse::{Event, SseResponder, SseShutdown};
+use dce_macro::api;
+use dce_router::router::Router;
+use dce_util::mixed::DceResult;
+
+mod common;
+
+#[api("ticks")]
+async fn ticks(req: HttpRaw) {
+    req.sse(None, |sender| async move {
+        sender.send(Event::new("tick")).await?;
+        sender.closed().await;
+        Ok(())
+    })
+}
+
+#[test]
+async fn close_on_shutdown() {
+    let (addr, shutdown, server) = serve().await;
+    let mut stream = subscribe(addr).await;
+    shutdown.send(()).unwrap();
+    // the stream was finished by the server shutdown, and then the server drained
+    let mut rest = vec![];
+    tokio::time::timeout(Duration::from_millis(500), stream.read_to_end(&mut rest)).await.unwrap().unwrap();
+    assert!(String::from_utf8_lossy(&rest).ends_with("0\r\n\r\n"));
+    tokio::time::timeout(Duration::from_millis(500), server).await.unwrap().unwrap().unwrap();
+
+    // the streams of another server were not affected by the previous shutdown
+    let (addr, _shutdown, _server) = serve().await;
+    let mut stream = subscribe(addr).await;
+    assert!(tokio::time::timeout(Duration::from_millis(200), stream.read(&mut [0; 64])).await.is_err());
+}
+
+#[test]
+async fn close_by_handle() {
+    // a hand-rolled serving loop routes by itself, and closes the event streams with the handle
+    let router = Router::new().unwrap().push(ticks).ready().unwrap();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let shutdown = SseShutdown::new();
+    let handle = shutdown.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let (router, shutdown) = (router.clone(), shutdown.clone());
+            tokio::spawn(http1::Builder::new().serve_connection(TokioIo::new(stream), service_fn(move |req| {
+                let mut context_data = HashMap::new();
+                shutdown.attach(&mut context_data);
+                HyperHttpProtocol::from(req).route(router.clone(), context_data)
+            })));
+        }
+    });
+    let mut stream = subscribe(addr).await;
+    handle.close();
+    // the chunked body was finished, but the connection kept alive
+    let mut rest = vec![];
+    tokio::time::timeout(Duration::from_millis(500), async {
+        while ! String::from_utf8_lossy(&rest).ends_with("0\r\n\r\n") {
+            let mut buf = [0; 256];
+            let len = stream.read(&mut buf).await.unwrap();
+            assert!(len > 0);
+            rest.extend_from_slice(&buf[..len]);
+        }
+    }).await.unwrap();
+}
+
+async fn serve() -> (SocketAddr, oneshot::Sender<()>, JoinHandle<DceResult<()>>) {
+    let router = Router::new().unwrap().push(ticks).ready().unwrap();
+    common::serve(router.clone(), ServeOptions::new().drain_timeout(Duration::from_secs(1))).await
+}
+
+/// Request the event stream and read until the first event
+async fn subscribe(addr: SocketAddr) -> TcpStream {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"GET /ticks HTTP/1.1\r\nHost: localhost\r\n\r\n").await.unwrap();
+    let mut received = vec![];
+    while ! String::from_utf8_lossy(&received).contains("data: tick\n\n") {
+        let mut buf = [0; 256];
+        let len = stream.read(&mut buf).await.unwrap();
+        assert!(len > 0, "{}", String::from_utf8_lossy(&received));
+        received.extend_from_slice(&buf[..len]);
+    }
+    assert!(received.starts_with(b"HTTP/1.1 200"));
+    stream
+}
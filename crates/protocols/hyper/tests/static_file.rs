@@ -0,0 +1,110 @@
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use tokio::test;
+use dce_hyper::protocol::HttpRaw;
+use dce_hyper::server::ServeOptions;
+use dce_hyper::static_file::{StaticDir, StaticResponder};
+use dce_macro::api;
+use dce_router::router::Router;
+use crate::common::{body, header, request};
+
+mod common;
+
+#[api("assets/{path*}")]
+async fn assets(req: HttpRaw) {
+    req.serve_static(&StaticDir::new(root().join("public")).precompressed(true)).await
+}
+
+#[test]
+async fn traversal() {
+    let addr = start().await;
+    assert_eq!(body(&request(addr, "GET", "assets/a.txt", &[]).await), "0123456789");
+    assert_eq!(body(&request(addr, "GET", "assets/sub", &[]).await), "index");
+    assert_eq!(body(&request(addr, "GET", "assets/sub/index%2Ehtml", &[]).await), "index");
+    for path in ["assets/../secret.txt", "assets/sub/../../secret.txt", "assets/%2e%2e/secret.txt", "assets/..%2fsecret.txt",
+        "assets/..%5csecret.txt", "assets/%2", "assets/link.txt", "assets/missing.txt", "assets/empty"] {
+        assert!(request(addr, "GET", path, &[]).await.starts_with("HTTP/1.1 404"), "{path}");
+    }
+}
+
+#[test]
+async fn ranges() {
+    let addr = start().await;
+    for (range, content_range, expected) in [("bytes=2-4", "bytes 2-4/10", "234"), ("bytes=-3", "bytes 7-9/10", "789"), ("bytes=8-", "bytes 8-9/10", "89"),
+        ("bytes=5-100", "bytes 5-9/10", "56789"), ("bytes=-20", "bytes 0-9/10", "0123456789")] {
+        let resp = request(addr, "GET", "assets/a.txt", &[("Range", range)]).await;
+        assert!(resp.starts_with("HTTP/1.1 206"), "{range}: {resp}");
+        assert_eq!((header(&resp, "content-range").as_deref(), body(&resp)), (Some(content_range), expected));
+    }
+    for range in ["bytes=10-", "bytes=-0", "bytes=4-2"] {
+        let resp = request(addr, "GET", "assets/a.txt", &[("Range", range)]).await;
+        assert!(resp.starts_with("HTTP/1.1 416"), "{range}: {resp}");
+        assert_eq!(header(&resp, "content-range").as_deref(), Some("bytes */10"));
+    }
+    // the unsupported or invalid ranges will be ignored
+    for range in ["bytes=0-1,3-4", "bytes=abc", "items=0-1"] {
+        let resp = request(addr, "GET", "assets/a.txt", &[("Range", range)]).await;
+        assert!(resp.starts_with("HTTP/1.1 200") && body(&resp) == "0123456789", "{range}: {resp}");
+    }
+    let etag = header(&request(addr, "GET", "assets/a.txt", &[]).await, "etag").unwrap();
+    assert_eq!(body(&request(addr, "GET", "assets/a.txt", &[("Range", "bytes=0-1"), ("If-Range", &etag)]).await), "01");
+    assert_eq!(body(&request(addr, "GET", "assets/a.txt", &[("Range", "bytes=0-1"), ("If-Range", r#""stale""#)]).await), "0123456789");
+}
+
+#[test]
+async fn conditional() {
+    let addr = start().await;
+    let resp = request(addr, "GET", "assets/a.txt", &[]).await;
+    let (etag, last_modified) = (header(&resp, "etag").unwrap(), header(&resp, "last-modified").unwrap());
+    assert_eq!(header(&resp, "content-type").as_deref(), Some("text/plain; charset=utf-8"));
+    for (name, value) in [("If-None-Match", etag.as_str()), ("If-None-Match", &format!(r#""other", W/{etag}"#)), ("If-None-Match", "*"),
+        ("If-Modified-Since", &last_modified)] {
+        let resp = request(addr, "GET", "assets/a.txt", &[(name, value)]).await;
+        assert!(resp.starts_with("HTTP/1.1 304") && body(&resp).is_empty(), "{name}: {value}");
+    }
+    assert!(request(addr, "GET", "assets/a.txt", &[("If-None-Match", r#""other""#)]).await.starts_with("HTTP/1.1 200"));
+    assert!(request(addr, "GET", "assets/a.txt", &[("If-Modified-Since", "Thu, 01 Jan 1970 00:00:00 GMT")]).await.starts_with("HTTP/1.1 200"));
+    // If-None-Match takes precedence over If-Modified-Since
+    assert!(request(addr, "GET", "assets/a.txt", &[("If-None-Match", r#""other""#), ("If-Modified-Since", &last_modified)]).await.starts_with("HTTP/1.1 200"));
+
+    let resp = request(addr, "HEAD", "assets/a.txt", &[]).await;
+    assert!(resp.starts_with("HTTP/1.1 200") && body(&resp).is_empty());
+    assert_eq!(header(&resp, "content-length").as_deref(), Some("10"));
+}
+
+#[test]
+async fn precompressed() {
+    let addr = start().await;
+    for (accept, encoding, expected) in [("gzip, br", Some("br"), "br"), ("gzip", Some("gzip"), "gz"), ("br;q=0, gzip", Some("gzip"), "gz"),
+        ("BR", Some("br"), "br"), ("deflate", None, "0123456789"), ("br;q=0", None, "0123456789")] {
+        let resp = request(addr, "GET", "assets/a.txt", &[("Accept-Encoding", accept)]).await;
+        assert_eq!((header(&resp, "content-encoding").as_deref(), body(&resp)), (encoding, expected), "{accept}");
+        assert_eq!(header(&resp, "vary").as_deref(), Some("accept-encoding"));
+        assert_eq!(header(&resp, "content-type").as_deref(), Some("text/plain; charset=utf-8"));
+    }
+    let resp = request(addr, "GET", "assets/sub/index.html", &[("Accept-Encoding", "gzip, br")]).await;
+    assert_eq!((header(&resp, "content-encoding"), body(&resp)), (None, "index"));
+}
+
+
+fn root() -> PathBuf {
+    std::env::temp_dir().join(format!("dce-hyper-static-{}", std::process::id()))
+}
+
+/// Prepare the files and start a server, the `secret.txt` is outside the static root
+async fn start() -> SocketAddr {
+    let public = root().join("public");
+    fs::create_dir_all(public.join("sub")).unwrap();
+    fs::create_dir_all(public.join("empty")).unwrap();
+    fs::write(root().join("secret.txt"), "secret").unwrap();
+    fs::write(public.join("a.txt"), "0123456789").unwrap();
+    fs::write(public.join("a.txt.gz"), "gz").unwrap();
+    fs::write(public.join("a.txt.br"), "br").unwrap();
+    fs::write(public.join("sub/index.html"), "index").unwrap();
+    #[cfg(unix)]
+    let _ = std::os::unix::fs::symlink(root().join("secret.txt"), public.join("link.txt"));
+
+    let router = Router::new().unwrap().push(assets).ready().unwrap();
+    common::serve(router.clone(), ServeOptions::new()).await.0
+}
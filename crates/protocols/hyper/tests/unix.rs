@@ -0,0 +1,67 @@
+#![cfg(unix)]
+
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::oneshot;
+use tokio::test;
+use dce_hyper::protocol::{HttpRaw, HyperHttpProtocol};
+use dce_hyper::server::ServeOptions;
+use dce_macro::api;
+use dce_router::router::Router;
+use dce_router::serializer::Serialized;
+use dce_util::unix::{PeerCred, UnixAddr, PEER_CRED_DATA_NAME};
+
+#[api("whoami")]
+async fn whoami(req: HttpRaw) {
+    let cred = req.get_as::<Arc<PeerCred>>(PEER_CRED_DATA_NAME)?;
+    let body = format!("{}:{:?}", cred.uid(), cred.pid());
+    req.pack(Serialized::String(body))
+}
+
+#[test]
+async fn serve_unix() {
+    let path = std::env::temp_dir().join(format!("dce-hyper-{}.sock", std::process::id()));
+    // a stale socket file left by a crashed server
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+    let router = Router::new().unwrap().push(whoami).ready().unwrap();
+    let (shutdown, signal) = oneshot::channel::<()>();
+    let server = tokio::spawn(HyperHttpProtocol::serve_unix(router.clone(), path.clone(), ServeOptions::new()
+        .drain_timeout(Duration::from_millis(100)).shutdown(async { let _ = signal.await; })));
+
+    let addr = UnixAddr::new(&path);
+    let mut stream = loop {
+        match addr.connect().await {
+            Ok(stream) => break stream,
+            Err(_) => tokio::time::sleep(Duration::from_millis(20)).await,
+        }
+    };
+    stream.write_all(b"GET /whoami HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").await.unwrap();
+    let mut resp = String::new();
+    stream.read_to_string(&mut resp).await.unwrap();
+    assert!(resp.starts_with("HTTP/1.1 200"), "{resp}");
+    // the socket file was created by this process, so its owner is the peer uid
+    let uid = std::os::unix::fs::MetadataExt::uid(&std::fs::metadata(&path).unwrap());
+    assert!(resp.ends_with(&format!("\r\n\r\n{uid}:Some({})", std::process::id())), "{resp}");
+
+    shutdown.send(()).unwrap();
+    server.await.unwrap().unwrap();
+    assert!(! path.exists());
+}
+
+#[cfg(feature = "tls")]
+#[test]
+async fn tls_rejected() {
+    use dce_util::tls::{TlsAcceptor, TlsConfig};
+    let dir = std::env::temp_dir().join(format!("dce-hyper-unix-tls-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let cert = rcgen::generate_simple_self_signed(["localhost".to_string()]).unwrap();
+    std::fs::write(dir.join("server.crt"), cert.cert.pem()).unwrap();
+    std::fs::write(dir.join("server.key"), cert.key_pair.serialize_pem()).unwrap();
+    let acceptor = TlsAcceptor::new(TlsConfig::new(dir.join("server.crt"), dir.join("server.key")).watch_interval(None)).unwrap();
+    let path = dir.join("dce.sock");
+    let router = Router::new().unwrap().push(whoami).ready().unwrap();
+    assert!(HyperHttpProtocol::serve_unix(router.clone(), path.clone(), ServeOptions::new().tls(acceptor)).await.is_err());
+    assert!(! path.exists());
+    let _ = std::fs::remove_dir_all(dir);
+}
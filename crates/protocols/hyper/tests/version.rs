@@ -0,0 +1,86 @@
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use http_body_util::{BodyExt, Empty};
+use hyper::body::Bytes;
+use hyper::header::{ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_REQUEST_METHOD, ALLOW, ORIGIN};
+use hyper::{Method, Request, StatusCode};
+use hyper_util::client::legacy::Client;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::rt::TokioExecutor;
+use tokio::test;
+use dce_hyper::cache::{CachePolicy, ResponseCache, RESPONSE_CACHE_EXTRA_NAME};
+use dce_hyper::cors::{Cors, CORS_EXTRA_NAME};
+use dce_hyper::protocol::HttpRaw;
+use dce_hyper::server::ServeOptions;
+use dce_macro::api;
+use dce_router::router::Router;
+use dce_router::serializer::Serialized;
+
+mod common;
+
+static HANDLED: AtomicUsize = AtomicUsize::new(0);
+
+#[api("users", version = "1", cache = CachePolicy::new().control("public").ttl(Duration::from_secs(10)))]
+async fn users(req: HttpRaw) {
+    let handled = HANDLED.fetch_add(1, Ordering::SeqCst) + 1;
+    req.pack(Serialized::String(format!("v1 {handled}")))
+}
+
+#[api("users", version = "2.1")]
+async fn users_v2(req: HttpRaw) {
+    let handled = HANDLED.fetch_add(1, Ordering::SeqCst) + 1;
+    req.pack(Serialized::String(format!("v2.1 {handled}")))
+}
+
+#[api]
+async fn ping(req: HttpRaw) {
+    req.pack(Serialized::String("pong".to_string()))
+}
+
+#[test]
+async fn resolve() {
+    let router = Router::new().unwrap()
+        .set_extra(RESPONSE_CACHE_EXTRA_NAME, Box::new(ResponseCache::new(8)))
+        .set_extra(CORS_EXTRA_NAME, Box::new(Cors::new().origins(["https://drunkce.com"])))
+        .push(users).push(users_v2).push(ping).ready().unwrap();
+    let (addr, _, _) = common::serve(router.clone(), ServeOptions::new()).await;
+    let client = Client::builder(TokioExecutor::new()).build_http::<Empty<Bytes>>();
+
+    // the latest version will be chosen if not requested
+    assert!(get(&client, addr, "users", &[]).await.1.starts_with("v2.1 "));
+    assert!(get(&client, addr, "v2/users", &[]).await.1.starts_with("v1 "));
+    assert!(get(&client, addr, "users.v1", &[]).await.1.starts_with("v1 "));
+    // choose the highest compatible version
+    assert!(get(&client, addr, "v3/users", &[]).await.1.starts_with("v2.1 "));
+    assert!(get(&client, addr, "users.v2.1", &[]).await.1.starts_with("v2.1 "));
+    assert!(get(&client, addr, "users", &[("Api-Version", "v1.5")]).await.1.starts_with("v1 "));
+    // the header takes precedence over the path
+    assert!(get(&client, addr, "v3/users", &[("Api-Version", "1")]).await.1.starts_with("v1 "));
+    assert_eq!(get(&client, addr, "v0/users", &[]).await.0, StatusCode::NOT_FOUND);
+    // the unversioned apis match any version
+    assert_eq!(get(&client, addr, "v9/ping", &[]).await.1, "pong");
+
+    // the versioned paths get the policies of the versioned apis
+    let cached = get(&client, addr, "v1/users", &[]).await.1;
+    assert_eq!(get(&client, addr, "v1/users", &[]).await.1, cached);
+    assert_ne!(get(&client, addr, "v3/users", &[]).await.1, get(&client, addr, "v3/users", &[]).await.1);
+
+    let resp = request(&client, addr, Method::OPTIONS, "v1/users", &[]).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    assert_eq!(resp.headers().get(ALLOW).unwrap(), "GET, HEAD, OPTIONS");
+    let resp = request(&client, addr, Method::OPTIONS, "users.v2", &[(ORIGIN.as_str(), "https://drunkce.com"), (ACCESS_CONTROL_REQUEST_METHOD.as_str(), "GET")]).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    assert_eq!(resp.headers().get(ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(), "https://drunkce.com");
+}
+
+async fn request(client: &Client<HttpConnector, Empty<Bytes>>, addr: SocketAddr, method: Method, path: &str, headers: &[(&str, &str)]) -> hyper::Response<hyper::body::Incoming> {
+    let request = headers.iter().fold(Request::builder().method(method).uri(format!("http://{addr}/{path}")), |r, (k, v)| r.header(*k, *v));
+    client.request(request.body(Empty::new()).unwrap()).await.unwrap()
+}
+
+async fn get(client: &Client<HttpConnector, Empty<Bytes>>, addr: SocketAddr, path: &str, headers: &[(&str, &str)]) -> (StatusCode, String) {
+    let resp = request(client, addr, Method::GET, path, headers).await;
+    let status = resp.status();
+    (status, String::from_utf8(resp.into_body().collect().await.unwrap().to_bytes().to_vec()).unwrap())
+}
@@ -0,0 +1,38 @@
+use tokio::test;
+use dce_hyper::protocol::HttpRaw;
+use dce_hyper::server::ServeOptions;
+use dce_hyper::vhost::Dispatcher;
+use dce_macro::api;
+use dce_router::router::Router;
+use dce_router::serializer::Serialized;
+use crate::common::request;
+
+mod common;
+
+#[api("who")]
+async fn tenant(req: HttpRaw) {
+    let tenant = req.param("tenant")?.as_str().unwrap_or_default().to_string();
+    req.pack(Serialized::String(format!("tenant {tenant}")))
+}
+
+#[api("who")]
+async fn fallback(req: HttpRaw) {
+    req.pack(Serialized::String("fallback".to_string()))
+}
+
+#[test]
+async fn dispatch() {
+    let tenant_router = Router::new().unwrap().push(tenant).ready().unwrap();
+    let fallback_router = Router::new().unwrap().push(fallback).ready().unwrap();
+    assert!(Dispatcher::new().header("X Tenant", "*", tenant_router.clone()).is_err());
+    let dispatcher = Dispatcher::new()
+        .host("{tenant}.drunkce.com", tenant_router.clone())
+        // the uppercase names are fine
+        .header("X-Tenant", "{tenant}", tenant_router.clone()).unwrap()
+        .fallback(fallback_router.clone());
+    let (addr, _, _) = common::serve(dispatcher, ServeOptions::new()).await;
+
+    assert!(request(addr, "GET", "who", &[("Host", "drunk.drunkce.com:2046")]).await.ends_with("\r\n\r\ntenant drunk"));
+    assert!(request(addr, "GET", "who", &[("x-tenant", "cheers")]).await.ends_with("\r\n\r\ntenant cheers"));
+    assert!(request(addr, "GET", "who", &[("Host", "a.b.drunkce.com")]).await.ends_with("\r\n\r\nfallback"));
+}
@@ -0,0 +1,90 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::test;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::WebSocketStream;
+use dce_hyper::protocol::HttpRaw;
+use dce_hyper::server::ServeOptions;
+use dce_hyper::websocket::{Handshake, HANDSHAKE_DATA_NAME, WebsocketUpgrader};
+use dce_macro::api;
+use dce_router::protocol::RoutableProtocol;
+use dce_router::router::Router;
+use dce_router::serializer::Serialized;
+use dce_tokio_tungstenite::client::{SemiWebsocketClient, SemiWebsocketMessage};
+use dce_tokio_tungstenite::protocol::{SemiWebsocketProtocol, SemiWebsocketRaw};
+use dce_util::mixed::DceResult;
+
+mod common;
+
+static WS_ROUTER: OnceLock<Arc<Router<SemiWebsocketProtocol>>> = OnceLock::new();
+
+#[api("ws")]
+async fn ws(req: HttpRaw) {
+    req.upgrade_websocket(WS_ROUTER.get().unwrap().clone(), false)
+}
+
+#[api]
+async fn greet(mut req: SemiWebsocketRaw) {
+    let body = req.rp_mut().body().await?;
+    let handshake = req.get_as::<Arc<Handshake>>(HANDSHAKE_DATA_NAME)?;
+    let tenant = handshake.headers().get("X-Tenant").and_then(|v| v.to_str().ok()).unwrap_or_default();
+    let greeting = format!("{tenant}:{} {body}", handshake.uri().path());
+    req.pack(Serialized::String(greeting))
+}
+
+#[test]
+async fn upgrade() {
+    let (addr, _shutdown, _server) = start(Duration::from_secs(30)).await;
+
+    // a plain request could not be upgraded
+    let resp = common::request(addr, "GET", "ws", &[]).await;
+    assert!(resp.starts_with("HTTP/1.1 400"), "{resp}");
+
+    let stream = handshake(addr).await;
+    let client = SemiWebsocketClient::new(WebSocketStream::from_raw_socket(stream, Role::Client, None).await, None)
+        .timeout(Duration::from_millis(500));
+    let resp = client.request(SemiWebsocketMessage::new("greet").body("hi")).await.unwrap();
+    assert_eq!(&resp.body[..], b"drunk:/ws hi");
+    let resp = client.request(SemiWebsocketMessage::new("greet").body("again")).await.unwrap();
+    assert_eq!(&resp.body[..], b"drunk:/ws again");
+}
+
+#[test]
+async fn drain() {
+    let (addr, shutdown, server) = start(Duration::from_millis(300)).await;
+    let mut stream = handshake(addr).await;
+    shutdown.send(()).unwrap();
+    let started = Instant::now();
+    tokio::time::timeout(Duration::from_secs(2), server).await.unwrap().unwrap().unwrap();
+    // the drain waited for the upgraded connection, then stopped it
+    assert!(started.elapsed() >= Duration::from_millis(250));
+    let closed = tokio::time::timeout(Duration::from_secs(1), stream.read(&mut [0; 64])).await.unwrap();
+    assert!(closed.map_or(true, |len| len == 0));
+}
+
+async fn start(drain_timeout: Duration) -> (SocketAddr, oneshot::Sender<()>, JoinHandle<DceResult<()>>) {
+    WS_ROUTER.get_or_init(|| Router::new().unwrap().push(greet).ready().unwrap().clone());
+    let router = Router::new().unwrap().push(ws).ready().unwrap();
+    common::serve(router.clone(), ServeOptions::new().drain_timeout(drain_timeout)).await
+}
+
+/// Upgrade a connection and check the handshake response
+async fn handshake(addr: SocketAddr) -> TcpStream {
+    let mut stream = TcpStream::connect(addr).await.unwrap();
+    stream.write_all(b"GET /ws HTTP/1.1\r\nHost: localhost\r\nConnection: Upgrade\r\nUpgrade: websocket\r\nSec-WebSocket-Version: 13\r\n\
+        Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nX-Tenant: drunk\r\n\r\n").await.unwrap();
+    // read the response head only, the following bytes belong to the websocket
+    let mut head = Vec::new();
+    while ! head.ends_with(b"\r\n\r\n") {
+        head.push(stream.read_u8().await.unwrap());
+    }
+    let head = String::from_utf8(head).unwrap().to_ascii_lowercase();
+    assert!(head.starts_with("http/1.1 101"), "{head}");
+    assert!(head.contains("sec-websocket-accept: s3pplmbitxaq9kygzzhzrbk+xoo=\r\n"), "{head}");
+    stream
+}
@@ -0,0 +1,261 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "session")]
+use std::sync::RwLock;
+use std::time::Duration;
+use bytes::Bytes;
+use futures_util::{SinkExt, StreamExt};
+use log::{debug, error};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use dce_router::protocol::{HEAD_CODE_NAME, HEAD_ID_NAME, HEAD_PATH_NAME, RoutableProtocol};
+#[cfg(feature = "session")]
+use dce_router::protocol::HEAD_SID_NAME;
+use dce_router::router::Router;
+use dce_router::serializer::Serialized;
+use dce_util::mixed::{DceErr, DceResult};
+use crate::envelope::{self, Framing, PROTOCOL_HEADER};
+use crate::protocol::SemiWebsocketProtocol;
+
+/// Default timeout of waiting for the response
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// The pending outgoing messages, the sending will wait if it was full
+const OUTGOING_BUFFER_SIZE: usize = 64;
+
+
+/// A semi websocket message for the clients, `id;path\nkey:value\n>BODY>>>\nbody`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SemiWebsocketMessage {
+    pub id: Option<String>,
+    pub path: String,
+    pub heads: HashMap<String, String>,
+    pub body: Bytes,
+}
+
+impl SemiWebsocketMessage {
+    pub fn new(path: &str) -> Self {
+        Self { path: path.to_string(), ..Default::default() }
+    }
+
+    pub fn head(mut self, key: &str, value: &str) -> Self {
+        self.heads.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub fn body<T: Into<Bytes>>(mut self, body: T) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Parse a message in the framing, the invalid envelopes will be taken as the messages with empty path
+    pub fn parse(msg: Message, framing: Framing) -> Self {
+        if framing == Framing::Semi {
+            return Self::from(msg);
+        }
+        match envelope::parse(&msg) {
+            Ok((mut heads, body)) => Self { id: heads.remove(HEAD_ID_NAME), path: heads.remove(HEAD_PATH_NAME).unwrap_or_default(), heads, body },
+            Err(e) => {
+                debug!("{e}");
+                Default::default()
+            },
+        }
+    }
+
+    fn into_message(self, framing: Framing, binary: bool) -> Message {
+        framing.pack(self.id.as_deref(), &self.path, &self.heads, Some(Serialized::Bytes(self.body)), binary)
+    }
+}
+
+impl From<Message> for SemiWebsocketMessage {
+    fn from(value: Message) -> Self {
+        let data = Bytes::from(value.into_data());
+        let (mut heads, body_index) = SemiWebsocketProtocol::parse_head(&data);
+        let id = heads.remove(HEAD_ID_NAME);
+        let path = heads.remove(HEAD_PATH_NAME).unwrap_or_default();
+        Self { id, path, heads, body: data.slice(body_index.min(data.len())..) }
+    }
+}
+
+
+/// A semi websocket client, the requests will be sent with an auto increment id, and the responses will be matched back by id,
+/// so the requests could be sent concurrently through the clones. The messages without id or with an unknown id are taken as
+/// the server pushes, and will be routed to the push router if set, the responses of the push apis will be dropped
+#[derive(Debug, Clone)]
+pub struct SemiWebsocketClient {
+    outgoing: mpsc::Sender<Message>,
+    shared: Arc<Shared>,
+    timeout: Duration,
+    binary: bool,
+    framing: Framing,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    next_id: AtomicU64,
+    pending: Mutex<HashMap<String, oneshot::Sender<SemiWebsocketMessage>>>,
+    #[cfg(feature = "session")]
+    sid: RwLock<Option<String>>,
+    tasks: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl SemiWebsocketClient {
+    /// Connect to a semi websocket server, the request could be an url or a request with the handshake headers.
+    /// The framing will be the one the server accepted if the request offered the `Sec-WebSocket-Protocol`
+    pub async fn connect<R: IntoClientRequest + Unpin>(request: R, router: Option<Arc<Router<SemiWebsocketProtocol>>>) -> DceResult<Self> {
+        let (ws_stream, resp) = connect_async(request).await.map_err(DceErr::closed0)?;
+        let framing = Framing::negotiate(resp.headers().get_all(PROTOCOL_HEADER).iter().filter_map(|v| v.to_str().ok())).unwrap_or_default();
+        Ok(Self::with_framing(ws_stream, router, framing))
+    }
+
+    /// Start the client on a websocket stream
+    pub fn new<S>(ws_stream: WebSocketStream<S>, router: Option<Arc<Router<SemiWebsocketProtocol>>>) -> Self
+    where S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        Self::with_framing(ws_stream, router, Framing::Semi)
+    }
+
+    /// Start the client on a websocket stream in the framing, the server side should be in the same framing
+    pub fn with_framing<S>(ws_stream: WebSocketStream<S>, router: Option<Arc<Router<SemiWebsocketProtocol>>>, framing: Framing) -> Self
+    where S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (mut sink, mut stream) = ws_stream.split();
+        let (outgoing, mut receiver) = mpsc::channel::<Message>(OUTGOING_BUFFER_SIZE);
+        let shared = Arc::new(Shared::default());
+        let writer = tokio::spawn(async move {
+            while let Some(msg) = receiver.recv().await {
+                if let Err(e) = sink.send(msg).await {
+                    error!("{e}");
+                    break;
+                }
+            }
+        });
+        let reader_shared = shared.clone();
+        let reader = tokio::spawn(async move {
+            while let Some(msg) = stream.next().await {
+                let msg = match msg {
+                    Ok(msg) if msg.is_text() || msg.is_binary() => msg,
+                    Ok(msg) if msg.is_close() => break,
+                    Ok(_) => continue, // ping pong was handled by tungstenite
+                    Err(e) => {
+                        error!("{e}");
+                        break;
+                    },
+                };
+                let parsed = SemiWebsocketMessage::parse(msg.clone(), framing);
+                let waiter = parsed.id.as_ref().and_then(|id| reader_shared.pending.lock().ok()?.remove(id));
+                match (waiter, &router) {
+                    (Some(waiter), _) => { let _ = waiter.send(parsed); },
+                    (None, Some(router)) => { SemiWebsocketProtocol::handle(SemiWebsocketProtocol::new(msg, framing), router.clone(), Default::default()).await; },
+                    (None, None) => debug!("unhandled push: {}", parsed.path),
+                }
+            }
+            // the waiters will get a closed error after their senders were dropped
+            if let Ok(mut pending) = reader_shared.pending.lock() {
+                pending.clear();
+            }
+        });
+        if let Ok(mut tasks) = shared.tasks.lock() {
+            tasks.extend([writer, reader]);
+        }
+        Self { outgoing, shared, timeout: DEFAULT_REQUEST_TIMEOUT, binary: false, framing }
+    }
+
+    /// Set the timeout of waiting for the responses
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Send with binary messages, the msgpack envelopes in the envelope framing
+    pub fn binary(mut self, binary: bool) -> Self {
+        self.binary = binary;
+        self
+    }
+
+    /// Set the sid to carry in the requests
+    #[cfg(feature = "session")]
+    pub fn with_sid(self, sid: Option<String>) -> Self {
+        if let Ok(mut current) = self.shared.sid.write() {
+            *current = sid;
+        }
+        self
+    }
+
+    /// The current sid, it will be replaced with the renewed one from the response heads automatically
+    #[cfg(feature = "session")]
+    pub fn sid(&self) -> Option<String> {
+        self.shared.sid.read().ok()?.clone()
+    }
+
+    /// Send a request and wait for its response, the id of the message will be replaced with an auto increment one.
+    /// The error responses will be converted to the openly errors with the responded code
+    pub async fn request(&self, mut msg: SemiWebsocketMessage) -> DceResult<SemiWebsocketMessage> {
+        let id = self.shared.next_id.fetch_add(1, Ordering::Relaxed).to_string();
+        msg.id = Some(id.clone());
+        let (waiter, response) = oneshot::channel();
+        self.shared.pending.lock().map_err(DceErr::closed0)?.insert(id.clone(), waiter);
+        if let Err(e) = self.send(msg).await {
+            self.forget(&id);
+            return Err(e);
+        }
+        let msg = match tokio::time::timeout(self.timeout, response).await {
+            Ok(Ok(msg)) => msg,
+            Ok(Err(_)) => return Err(DceErr::closed0("Connection closed before responded")),
+            Err(_) => {
+                self.forget(&id);
+                return Err(DceErr::closed0(format!("Request \"{id}\" timeout")));
+            },
+        };
+        #[cfg(feature = "session")]
+        if let Some(sid) = msg.heads.get(HEAD_SID_NAME) {
+            if let Ok(mut current) = self.shared.sid.write() {
+                // an empty sid means the session was dropped by the server, such as after logout
+                *current = Some(sid.clone()).filter(|sid| ! sid.is_empty());
+            }
+        }
+        match msg.heads.get(HEAD_CODE_NAME).and_then(|code| code.parse::<isize>().ok()) {
+            Some(code) => {
+                let message = String::from_utf8_lossy(&msg.body);
+                let message = message.strip_prefix(format!("{code}: ").as_str()).unwrap_or(&message);
+                Err(DceErr::openly(code, message.to_string()))
+            },
+            None => Ok(msg),
+        }
+    }
+
+    /// Send a message without waiting for the response, the sid will be carried if set
+    pub async fn send(&self, #[cfg_attr(not(feature = "session"), allow(unused_mut))] mut msg: SemiWebsocketMessage) -> DceResult<()> {
+        #[cfg(feature = "session")]
+        if let Some(sid) = self.sid() {
+            msg.heads.entry(HEAD_SID_NAME.to_string()).or_insert(sid);
+        }
+        self.outgoing.send(msg.into_message(self.framing, self.binary)).await.map_err(|_| DceErr::closed0("Connection closed"))
+    }
+
+    /// Stop the reading and writing, the clones will be closed too
+    pub fn close(&self) {
+        if let Ok(mut tasks) = self.shared.tasks.lock() {
+            tasks.drain(..).for_each(|task| task.abort());
+        }
+    }
+
+    pub fn framing(&self) -> Framing {
+        self.framing
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.outgoing.is_closed()
+    }
+
+    fn forget(&self, id: &str) {
+        if let Ok(mut pending) = self.shared.pending.lock() {
+            pending.remove(id);
+        }
+    }
+}
@@ -0,0 +1,397 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Result as IoResult, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use flate2::{Compression, Decompress, FlushDecompress};
+use flate2::write::DeflateEncoder;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::{accept_hdr_async_with_config, WebSocketStream};
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::http::HeaderValue;
+use dce_router::router::Router;
+use dce_util::mixed::{DceErr, DceResult};
+use crate::envelope::{Framing, PROTOCOL_HEADER};
+use crate::protocol::SemiWebsocketProtocol;
+
+/// The router extras key to switch on the permessage-deflate extension, e.g. `.set_extra(DEFLATE_EXTRA_NAME, Box::new(true))`
+pub const DEFLATE_EXTRA_NAME: &str = "permessage_deflate";
+pub const EXTENSIONS_HEADER: &str = "Sec-WebSocket-Extensions";
+const EXTENSION_NAME: &str = "permessage-deflate";
+// no context takeover in both directions, so that every message could be compressed and decompressed independently
+const ACCEPTED_EXTENSION: &str = "permessage-deflate; server_no_context_takeover; client_no_context_takeover";
+const DEFLATE_TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+const MIN_COMPRESS_SIZE: usize = 64;
+const READ_CHUNK_SIZE: usize = 8192;
+const MAX_PENDING_WRITE: usize = 1 << 20;
+
+const FIN: u8 = 0x80;
+const RSV1: u8 = 0x40;
+const OPCODE_MASK: u8 = 0x0f;
+const MASK: u8 = 0x80;
+const OPCODE_CONTINUATION: u8 = 0x0;
+
+
+/// Whether the permessage-deflate extension was switched on in the router extras
+pub fn enabled(router: &Router<SemiWebsocketProtocol>) -> bool {
+    router.extras().get(DEFLATE_EXTRA_NAME).and_then(|v| v.downcast_ref::<bool>()).is_some_and(|v| *v)
+}
+
+/// Negotiate with the `Sec-WebSocket-Extensions` offers of the handshake request, the response header value will be returned if accepted.
+/// Offers with `server_max_window_bits` will be declined, because the window bits is not configurable here
+pub fn negotiate<'a>(offers: impl IntoIterator<Item = &'a str>) -> Option<&'static str> {
+    offers.into_iter().flat_map(|v| v.split(',')).find_map(|offer| {
+        let mut params = offer.split(';').map(str::trim);
+        (params.next() == Some(EXTENSION_NAME) && params.all(|p| ! p.starts_with("server_max_window_bits"))).then_some(ACCEPTED_EXTENSION)
+    })
+}
+
+
+/// Accept a websocket connection, and negotiate the permessage-deflate extension if it was switched on in the router,
+/// the framing subprotocol will be negotiated too
+pub async fn accept<S: AsyncRead + AsyncWrite + Unpin>(stream: S, router: &Router<SemiWebsocketProtocol>) -> DceResult<WebSocketStream<DeflateStream<S>>> {
+    accept_with_headers(stream, router).await.map(|(ws_stream, _)| ws_stream)
+}
+
+/// Accept a websocket connection like [accept], and returns the handshake request headers too, the multiple values of a header will be joined with `, `
+// the large error response of the handshake callback was required by tungstenite
+#[allow(clippy::result_large_err)]
+pub async fn accept_with_headers<S: AsyncRead + AsyncWrite + Unpin>(stream: S, router: &Router<SemiWebsocketProtocol>) -> DceResult<(WebSocketStream<DeflateStream<S>>, HashMap<String, String>)> {
+    let switched_on = enabled(router);
+    let mut accepted = false;
+    let mut headers = HashMap::<String, String>::new();
+    let config = SemiWebsocketProtocol::ws_config(router);
+    let mut ws_stream = accept_hdr_async_with_config(DeflateStream::new(stream, false, config.max_message_size.unwrap_or(usize::MAX)), |req: &Request, mut resp: Response| {
+        for (name, value) in req.headers() {
+            let value = String::from_utf8_lossy(value.as_bytes());
+            headers.entry(name.to_string()).and_modify(|v| { v.push_str(", "); v.push_str(&value); }).or_insert_with(|| value.to_string());
+        }
+        if let Some(extension) = negotiate(req.headers().get_all(EXTENSIONS_HEADER).iter().filter_map(|v| v.to_str().ok())).filter(|_| switched_on) {
+            resp.headers_mut().insert(EXTENSIONS_HEADER, HeaderValue::from_static(extension));
+            accepted = true;
+        }
+        if let Some(framing) = Framing::negotiate(req.headers().get_all(PROTOCOL_HEADER).iter().filter_map(|v| v.to_str().ok())) {
+            resp.headers_mut().insert(PROTOCOL_HEADER, HeaderValue::from_static(framing.subprotocol()));
+        }
+        Ok(resp)
+    }, Some(config)).await.map_err(DceErr::closed0)?;
+    if accepted {
+        ws_stream.get_mut().activate();
+    }
+    Ok((ws_stream, headers))
+}
+
+
+struct FrameHead {
+    first: u8,
+    masked: bool,
+    head_len: usize,
+    payload_len: usize,
+}
+
+impl FrameHead {
+    fn parse(buf: &[u8], max_size: usize) -> IoResult<Option<Self>> {
+        if buf.len() < 2 {
+            return Ok(None);
+        }
+        let (first, masked, len7) = (buf[0], buf[1] & MASK > 0, (buf[1] & !MASK) as usize);
+        let (ext_len, payload_len) = match len7 {
+            126 if buf.len() >= 4 => (2, u16::from_be_bytes([buf[2], buf[3]]) as usize),
+            127 if buf.len() >= 10 => (8, usize::try_from(u64::from_be_bytes(buf[2..10].try_into().unwrap_or_default()))
+                .map_err(|_| Error::new(ErrorKind::InvalidData, "Websocket frame too large"))?),
+            126 | 127 => return Ok(None),
+            len => (0, len),
+        };
+        if payload_len > max_size {
+            return Err(Error::new(ErrorKind::InvalidData, "Websocket frame too large"));
+        }
+        Ok(Some(Self { first, masked, head_len: 2 + ext_len + if masked { 4 } else { 0 }, payload_len }))
+    }
+
+    fn opcode(&self) -> u8 {
+        self.first & OPCODE_MASK
+    }
+
+    fn total_len(&self) -> usize {
+        self.head_len + self.payload_len
+    }
+
+    fn unmasked_payload(&self, frame: &[u8]) -> Vec<u8> {
+        let mut payload = frame[self.head_len ..].to_vec();
+        if self.masked {
+            let key = &frame[self.head_len - 4 .. self.head_len];
+            payload.iter_mut().enumerate().for_each(|(i, b)| *b ^= key[i % 4]);
+        }
+        payload
+    }
+}
+
+fn encode_frame(first: u8, masked: bool, payload: &[u8], out: &mut Vec<u8>) {
+    let mask_bit = if masked { MASK } else { 0 };
+    out.push(first);
+    match payload.len() {
+        len if len < 126 => out.push(mask_bit | len as u8),
+        len if len <= u16::MAX as usize => {
+            out.push(mask_bit | 126);
+            out.extend((len as u16).to_be_bytes());
+        },
+        len => {
+            out.push(mask_bit | 127);
+            out.extend((len as u64).to_be_bytes());
+        },
+    }
+    if masked {
+        // the client frames must be masked with a fresh random key as the rfc6455 required
+        let key: [u8; 4] = rand::random();
+        out.extend(key);
+        out.extend(payload.iter().enumerate().map(|(i, b)| b ^ key[i % 4]));
+    } else {
+        out.extend(payload);
+    }
+}
+
+fn deflate(payload: &[u8]) -> IoResult<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(Vec::with_capacity(payload.len() / 2), Compression::default());
+    encoder.write_all(payload)?;
+    // sync flush then cut off the empty block tail as the rfc7692 required
+    encoder.flush()?;
+    let mut compressed = std::mem::take(encoder.get_mut());
+    if compressed.ends_with(&DEFLATE_TAIL) {
+        compressed.truncate(compressed.len() - DEFLATE_TAIL.len());
+    }
+    Ok(compressed)
+}
+
+fn inflate(mut compressed: Vec<u8>, max_size: usize) -> IoResult<Vec<u8>> {
+    compressed.extend(DEFLATE_TAIL);
+    let mut decompress = Decompress::new(false);
+    // the buffer grows up to one byte over the limit, so a deflate bomb will be rejected before fully inflated
+    let bound = max_size.saturating_add(1);
+    let mut payload = Vec::with_capacity(compressed.len().saturating_mul(2).min(bound));
+    loop {
+        let (total_in, total_out) = (decompress.total_in(), decompress.total_out());
+        if payload.len() == payload.capacity() {
+            payload.reserve_exact(payload.len().max(READ_CHUNK_SIZE).min(bound - payload.len()));
+        }
+        decompress.decompress_vec(&compressed[total_in as usize ..], &mut payload, FlushDecompress::Sync)
+            .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+        if payload.len() > max_size {
+            return Err(Error::new(ErrorKind::InvalidData, "Websocket message too large"));
+        } else if decompress.total_in() as usize == compressed.len() && payload.len() < payload.capacity() {
+            return Ok(payload);
+        } else if decompress.total_in() == total_in && decompress.total_out() == total_out && payload.len() < payload.capacity() {
+            return Err(Error::new(ErrorKind::InvalidData, "Invalid deflate data"));
+        }
+    }
+}
+
+
+/// A stream wrapper to implement the permessage-deflate extension under tungstenite, it will decompress the received messages
+/// and compress the messages to send, the frames keep their mask bits and the masked are masked again with a random key,
+/// so it works on both the server and client sides.
+/// The wrapper will pass through bytes until activated, so it could wrap a stream before handshake
+#[derive(Debug)]
+pub struct DeflateStream<S> {
+    inner: S,
+    active: bool,
+    read_raw: Vec<u8>,
+    read_out: Vec<u8>,
+    read_pos: usize,
+    // the opcode, mask and payload of the compressed message which is receiving fragments
+    fragmented: Option<(u8, bool, Vec<u8>)>,
+    write_raw: Vec<u8>,
+    write_out: Vec<u8>,
+    write_pos: usize,
+    // whether the sending fragmented message was passed through without compressing
+    passing: bool,
+    max_size: usize,
+}
+
+impl<S> DeflateStream<S> {
+    /// The frames or inflated messages larger than `max_size` will be rejected, it should be the `max_message_size` of [SemiWebsocketProtocol::ws_config]
+    pub fn new(inner: S, active: bool, max_size: usize) -> Self {
+        Self {
+            inner,
+            active,
+            read_raw: vec![],
+            read_out: vec![],
+            read_pos: 0,
+            fragmented: None,
+            write_raw: vec![],
+            write_out: vec![],
+            write_pos: 0,
+            passing: false,
+            max_size,
+        }
+    }
+
+    /// Start to transform frames, should be called after the handshake with the extension accepted
+    pub fn activate(&mut self) {
+        self.active = true;
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+
+    fn transform_received(&mut self) -> IoResult<()> {
+        let mut offset = 0;
+        while let Some(head) = FrameHead::parse(&self.read_raw[offset ..], self.max_size)? {
+            let frame = &self.read_raw[offset .. offset + head.total_len().min(self.read_raw.len() - offset)];
+            if frame.len() < head.total_len() {
+                break;
+            }
+            let fin = head.first & FIN > 0;
+            match head.opcode() {
+                // control frames could be interleaved in fragments, just pass them through
+                opcode if opcode >= 0x8 => self.read_out.extend(frame),
+                OPCODE_CONTINUATION if self.fragmented.is_some() => {
+                    let payload = head.unmasked_payload(frame);
+                    if let Some((_, _, message)) = self.fragmented.as_mut() {
+                        message.extend(payload);
+                        if message.len() > self.max_size {
+                            return Err(Error::new(ErrorKind::InvalidData, "Websocket message too large"));
+                        }
+                    }
+                    if fin {
+                        let (opcode, masked, message) = self.fragmented.take().unwrap_or_default();
+                        encode_frame(FIN | opcode, masked, &inflate(message, self.max_size)?, &mut self.read_out);
+                    }
+                },
+                opcode if head.first & RSV1 > 0 && opcode != OPCODE_CONTINUATION => {
+                    let payload = head.unmasked_payload(frame);
+                    // keep the mask bit, the server expects masked frames and the client expects unmasked ones
+                    if fin {
+                        encode_frame(FIN | opcode, head.masked, &inflate(payload, self.max_size)?, &mut self.read_out);
+                    } else {
+                        self.fragmented = Some((opcode, head.masked, payload));
+                    }
+                },
+                _ => self.read_out.extend(frame),
+            }
+            offset += head.total_len();
+        }
+        self.read_raw.drain(.. offset);
+        Ok(())
+    }
+
+    fn transform_sending(&mut self) -> IoResult<()> {
+        let mut offset = 0;
+        while let Some(head) = FrameHead::parse(&self.write_raw[offset ..], self.max_size)? {
+            let frame = &self.write_raw[offset .. offset + head.total_len().min(self.write_raw.len() - offset)];
+            if frame.len() < head.total_len() {
+                break;
+            }
+            let fin = head.first & FIN > 0;
+            match head.opcode() {
+                // compress unfragmented data messages only, the fragmented will be sent as is
+                0x1 | 0x2 if fin && head.payload_len >= MIN_COMPRESS_SIZE => {
+                    let compressed = deflate(&head.unmasked_payload(frame))?;
+                    encode_frame(head.first | RSV1, head.masked, &compressed, &mut self.write_out);
+                },
+                0x1 | 0x2 => {
+                    self.passing = ! fin;
+                    self.write_out.extend(frame);
+                },
+                OPCODE_CONTINUATION if self.passing => {
+                    self.passing = ! fin;
+                    self.write_out.extend(frame);
+                },
+                _ => self.write_out.extend(frame),
+            }
+            offset += head.total_len();
+        }
+        self.write_raw.drain(.. offset);
+        Ok(())
+    }
+}
+
+impl<S: AsyncWrite + Unpin> DeflateStream<S> {
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+        while self.write_pos < self.write_out.len() {
+            match Pin::new(&mut self.inner).poll_write(cx, &self.write_out[self.write_pos ..]) {
+                Poll::Ready(Ok(0)) => return Poll::Ready(Err(Error::from(ErrorKind::WriteZero))),
+                Poll::Ready(Ok(written)) => self.write_pos += written,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        self.write_out.clear();
+        self.write_pos = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for DeflateStream<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<IoResult<()>> {
+        let this = self.get_mut();
+        if ! this.active {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        }
+        loop {
+            if this.read_pos < this.read_out.len() {
+                let size = buf.remaining().min(this.read_out.len() - this.read_pos);
+                buf.put_slice(&this.read_out[this.read_pos .. this.read_pos + size]);
+                this.read_pos += size;
+                if this.read_pos == this.read_out.len() {
+                    this.read_out.clear();
+                    this.read_pos = 0;
+                }
+                return Poll::Ready(Ok(()));
+            }
+            let mut chunk = [0; READ_CHUNK_SIZE];
+            let mut chunk_buf = ReadBuf::new(&mut chunk);
+            match Pin::new(&mut this.inner).poll_read(cx, &mut chunk_buf) {
+                Poll::Ready(Ok(())) if chunk_buf.filled().is_empty() => return Poll::Ready(Ok(())),
+                Poll::Ready(Ok(())) => {
+                    this.read_raw.extend(chunk_buf.filled());
+                    this.transform_received()?;
+                },
+                result => return result,
+            }
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for DeflateStream<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<IoResult<usize>> {
+        let this = self.get_mut();
+        if ! this.active {
+            return Pin::new(&mut this.inner).poll_write(cx, buf);
+        }
+        // apply back pressure when too many bytes pending
+        if this.write_out.len() - this.write_pos > MAX_PENDING_WRITE {
+            if let Poll::Ready(Err(err)) = this.poll_drain(cx) {
+                return Poll::Ready(Err(err));
+            }
+            if this.write_out.len() - this.write_pos > MAX_PENDING_WRITE {
+                return Poll::Pending;
+            }
+        }
+        this.write_raw.extend(buf);
+        this.transform_sending()?;
+        if let Poll::Ready(Err(err)) = this.poll_drain(cx) {
+            return Poll::Ready(Err(err));
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+        let this = self.get_mut();
+        match this.poll_drain(cx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut this.inner).poll_flush(cx),
+            result => result,
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+        let this = self.get_mut();
+        match this.poll_drain(cx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut this.inner).poll_shutdown(cx),
+            result => result,
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use futures_util::{SinkExt, StreamExt};
+use log::{debug, error};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{Mutex, Semaphore};
+use tokio::time::{Instant, MissedTickBehavior};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use dce_router::protocol::{ConnectionInfo, CONNECTION_DATA_NAME, EVENT_CONNECT_PATH, EVENT_DATA_NAME, EVENT_DISCONNECT_PATH, EVENT_IDLE_PATH, EVENT_PATH_PREFIX, RoutableProtocol};
+use dce_router::router::Router;
+use dce_util::mixed::DceErr;
+use dce_util::registry::{ConnectionRegistry, RegisteredConnection, REGISTRY_DATA_NAME};
+use crate::envelope::Framing;
+use crate::protocol::SemiWebsocketProtocol;
+use crate::registry::SemiWebsocketRegistry;
+
+/// Default max count of the concurrently handling requests per connection
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 64;
+/// The peer will be taken as dead if nothing received in this count of heartbeat intervals
+const HEARTBEAT_TOLERANCE: u32 = 2;
+
+
+/// Drive a websocket connection, the text and binary messages will be routed concurrently, and the responses will be written back
+/// through a shared sink as soon as they were handled, so they may arrive out of order, the clients should match them by id.
+/// The lifecycle events `$connect`, `$disconnect` and `$idle` will be routed to the apis with the same paths if registered,
+/// the responses of `$connect` and `$idle` will be sent to the client, and the [ConnectionInfo] can be got from the context data.
+/// The events could be told apart by the [EVENT_DATA_NAME] context data, and the client requests with the `$` prefixed paths will be rejected.
+/// The [Framing] will be selected by the negotiated subprotocol in the handshake headers, or by the router extras
+#[derive(Debug, Clone)]
+pub struct SemiWebsocketDriver {
+    router: Arc<Router<SemiWebsocketProtocol>>,
+    max_in_flight: usize,
+    binary: bool,
+    idle_timeout: Option<Duration>,
+    heartbeat: Option<Duration>,
+    peer: Option<SocketAddr>,
+    registry: Option<SemiWebsocketRegistry>,
+    headers: HashMap<String, String>,
+}
+
+impl SemiWebsocketDriver {
+    pub fn new(router: Arc<Router<SemiWebsocketProtocol>>) -> Self {
+        Self { router, max_in_flight: DEFAULT_MAX_IN_FLIGHT, binary: false, idle_timeout: None, heartbeat: None, peer: None, registry: None, headers: Default::default() }
+    }
+
+    /// Set the max in-flight requests, the reading will be paused until some of them were handled when reached, 1 means handle one by one
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = max_in_flight.max(1);
+        self
+    }
+
+    /// Respond with binary messages, the envelope framing responds in the same message type with the requests, so it will be ignored there
+    pub fn binary(mut self, binary: bool) -> Self {
+        self.binary = binary;
+        self
+    }
+
+    /// Close the connection if no request received in the timeout, the `$idle` event will be routed before closing.
+    /// The ping pong frames will not be taken as requests
+    pub fn idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    /// Send a ping every interval, the peer will be taken as dead and the connection will be closed if nothing received in 2 intervals
+    pub fn heartbeat(mut self, interval: Duration) -> Self {
+        self.heartbeat = Some(interval);
+        self
+    }
+
+    /// Set the peer address of the connection
+    pub fn peer(mut self, peer: SocketAddr) -> Self {
+        self.peer = Some(peer);
+        self
+    }
+
+    /// Register the connection into the registry, so that the server could push messages to it, it will be unregistered after closed.
+    /// The sid head of the requests is not trusted, so the connection should be bound with the sid by the apis or middlewares
+    /// after the session validated, through the [RegisteredConnection] in the context data
+    pub fn registry(mut self, registry: SemiWebsocketRegistry) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// Set the handshake headers of the connection
+    pub fn headers(mut self, headers: HashMap<String, String>) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Read and route the messages until the connection closed, then wait for the in-flight requests to be finished.
+    /// The `context_data` will be called for each request to build its context data, the protocol could be prepared here too
+    pub async fn drive<S, F>(&self, ws_stream: WebSocketStream<S>, context_data: F)
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        F: Fn(&mut SemiWebsocketProtocol) -> HashMap<String, Box<dyn Any + Send>>,
+    {
+        let connection = Arc::new(ConnectionInfo::new(self.peer, self.headers.clone()));
+        let framing = Framing::select(&self.router, &self.headers);
+        let (registered, pushes) = match &self.registry {
+            Some(registry) => {
+                let (id, receiver) = registry.register(self.peer);
+                (Some(Arc::new(RegisteredConnection::new(id, ConnectionRegistry::clone(registry)))), Some(receiver))
+            },
+            None => (None, None),
+        };
+        let context_data = |rp: &mut SemiWebsocketProtocol| {
+            let mut data = context_data(rp);
+            data.insert(CONNECTION_DATA_NAME.to_string(), Box::new(connection.clone()));
+            if let Some(registered) = &registered {
+                data.insert(REGISTRY_DATA_NAME.to_string(), Box::new(registered.clone()));
+            }
+            data
+        };
+        let (sink, mut stream) = ws_stream.split();
+        let sink = Arc::new(Mutex::new(sink));
+        // forward the pushed messages to the connection
+        let forwarder = pushes.map(|mut receiver| {
+            let sink = sink.clone();
+            tokio::spawn(async move {
+                while let Some(pushed) = receiver.recv().await {
+                    let _ = sink.lock().await.send(pushed).await.map_err(|e| error!("{e}"));
+                }
+            })
+        });
+        if let Some(handled) = self.emit(EVENT_CONNECT_PATH, framing, &context_data).await {
+            let _ = sink.lock().await.send(handled).await.map_err(|e| error!("{e}"));
+        }
+        let permits = Arc::new(Semaphore::new(self.max_in_flight));
+        let interval = self.heartbeat.unwrap_or_default();
+        let mut ticker = tokio::time::interval_at(Instant::now() + interval, interval.max(Duration::from_millis(1)));
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let (mut last_seen, mut last_active) = (Instant::now(), Instant::now());
+        loop {
+            let idle_deadline = last_active + self.idle_timeout.unwrap_or_default();
+            // the disabled branches will not be polled
+            let msg = tokio::select! {
+                msg = stream.next() => msg,
+                _ = ticker.tick(), if self.heartbeat.is_some() => {
+                    if last_seen.elapsed() > interval * HEARTBEAT_TOLERANCE {
+                        debug!("websocket peer {:?} was dead", self.peer);
+                        break;
+                    }
+                    let _ = sink.lock().await.send(Message::Ping(vec![])).await.map_err(|e| error!("{e}"));
+                    continue;
+                },
+                _ = tokio::time::sleep_until(idle_deadline), if self.idle_timeout.is_some() => {
+                    debug!("websocket connection of {:?} idle timeout", self.peer);
+                    if let Some(handled) = self.emit(EVENT_IDLE_PATH, framing, &context_data).await {
+                        let _ = sink.lock().await.send(handled).await.map_err(|e| error!("{e}"));
+                    }
+                    break;
+                },
+            };
+            last_seen = Instant::now();
+            let msg = match msg {
+                Some(Ok(msg)) if msg.is_text() || msg.is_binary() => msg,
+                Some(Ok(_)) => continue, // ping pong was handled by tungstenite
+                Some(Err(err)) => {
+                    error!("{err}");
+                    break;
+                },
+                None => break,
+            };
+            last_active = last_seen;
+            let mut rp = SemiWebsocketProtocol::new(msg, framing);
+            if self.binary && framing == Framing::Semi {
+                rp = rp.binary();
+            }
+            // the event paths are reserved for the lifecycle events, they could not be requested by the clients
+            if rp.path().starts_with(EVENT_PATH_PREFIX) {
+                debug!("rejected the reserved path \"{}\" from {:?}", rp.path(), self.peer);
+                let rejected = rp.err_into(DceErr::openly(404, "Not Found".to_string()));
+                let _ = sink.lock().await.send(rejected).await.map_err(|e| error!("{e}"));
+                continue;
+            }
+            let Ok(permit) = permits.clone().acquire_owned().await else { break };
+            let context_data = context_data(&mut rp);
+            let (router, sink) = (self.router.clone(), sink.clone());
+            tokio::spawn(async move {
+                if let Some(handled) = SemiWebsocketProtocol::handle(rp, router, context_data).await {
+                    let _ = sink.lock().await.send(handled).await.map_err(|e| error!("{e}"));
+                }
+                drop(permit);
+            });
+        }
+        // all the permits could be acquired only after the in-flight requests were finished
+        let _ = permits.acquire_many(self.max_in_flight as u32).await;
+        if let Some(forwarder) = forwarder {
+            forwarder.abort();
+        }
+        let _ = sink.lock().await.close().await;
+        // the connection was closed, so the response will be dropped
+        self.emit(EVENT_DISCONNECT_PATH, framing, &context_data).await;
+        if let Some(registered) = registered {
+            registered.registry().unregister(registered.id());
+        }
+    }
+
+    async fn emit<F>(&self, path: &str, framing: Framing, context_data: F) -> Option<Message>
+    where F: Fn(&mut SemiWebsocketProtocol) -> HashMap<String, Box<dyn Any + Send>>,
+    {
+        if ! self.router.routable(path) {
+            return None;
+        }
+        let mut rp = SemiWebsocketProtocol::new(framing.pack(None, path, &Default::default(), None, self.binary), framing);
+        if self.binary {
+            rp = rp.binary();
+        }
+        let mut context_data = context_data(&mut rp);
+        context_data.insert(EVENT_DATA_NAME.to_string(), Box::new(path.to_string()));
+        SemiWebsocketProtocol::handle(rp, self.router.clone(), context_data).await
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use serde_json::value::RawValue;
+use tokio_tungstenite::tungstenite::Message;
+use dce_router::protocol::{HEAD_ID_NAME, HEAD_PATH_NAME};
+use dce_router::router::Router;
+use dce_router::serializer::Serialized;
+use dce_util::mixed::{DceErr, DceResult};
+use crate::protocol::SemiWebsocketProtocol;
+
+/// The router extras key to select the default framing of the connections, e.g. `.set_extra(FRAMING_EXTRA_NAME, Box::new(Framing::Envelope))`
+pub const FRAMING_EXTRA_NAME: &str = "framing";
+pub const PROTOCOL_HEADER: &str = "Sec-WebSocket-Protocol";
+/// The handshake subprotocol of the [Framing::Semi]
+pub const SEMI_SUBPROTOCOL: &str = "dce.semi";
+/// The handshake subprotocol of the [Framing::Envelope]
+pub const ENVELOPE_SUBPROTOCOL: &str = "dce.envelope";
+
+
+/// The message framing of the semi websocket protocol
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Framing {
+    /// `id;path\nkey:value\n>BODY>>>\nbody`
+    #[default]
+    Semi,
+    /// `{"id":..,"path":..,"heads":{..},"body":..}` in the text messages, and the msgpack equivalent in the binary messages,
+    /// the responses will be in the same message type with the requests. A JSON body is embedded in `body` as is,
+    /// and the others are embedded in `text` as a string, such as `{"path":..,"text":"plain"}`
+    Envelope,
+}
+
+impl Framing {
+    /// The framing selected in the router extras, [Framing::Semi] if not set
+    pub fn of(router: &Router<SemiWebsocketProtocol>) -> Self {
+        router.extras().get(FRAMING_EXTRA_NAME).and_then(|v| v.downcast_ref::<Framing>()).copied().unwrap_or_default()
+    }
+
+    /// Negotiate with the `Sec-WebSocket-Protocol` offers of the handshake request, the first supported one will be selected
+    pub fn negotiate<'a>(offers: impl IntoIterator<Item = &'a str>) -> Option<Self> {
+        offers.into_iter().flat_map(|v| v.split(',')).find_map(|offer| match offer.trim() {
+            SEMI_SUBPROTOCOL => Some(Self::Semi),
+            ENVELOPE_SUBPROTOCOL => Some(Self::Envelope),
+            _ => None,
+        })
+    }
+
+    /// Select the framing of a connection with the handshake headers, the negotiated subprotocol takes precedence over the router extras
+    pub fn select(router: &Router<SemiWebsocketProtocol>, headers: &HashMap<String, String>) -> Self {
+        let offers = headers.iter().filter(|(k, _)| k.eq_ignore_ascii_case(PROTOCOL_HEADER)).map(|(_, v)| v.as_str());
+        Self::negotiate(offers).unwrap_or_else(|| Self::of(router))
+    }
+
+    pub fn subprotocol(&self) -> &'static str {
+        match self {
+            Self::Semi => SEMI_SUBPROTOCOL,
+            Self::Envelope => ENVELOPE_SUBPROTOCOL,
+        }
+    }
+
+    /// Pack a message in the framing, the binary means a binary semi message or a msgpack envelope
+    pub fn pack(&self, id: Option<&str>, path: &str, heads: &HashMap<String, String>, body: Option<Serialized>, binary: bool) -> Message {
+        match self {
+            Self::Semi => SemiWebsocketProtocol::pack_message(id, path, heads, body, binary),
+            Self::Envelope => pack(id, path, heads, body, binary),
+        }
+    }
+}
+
+
+#[derive(Serialize, Deserialize)]
+struct TextEnvelope {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(default)]
+    path: String,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    heads: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<Box<RawValue>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BinaryEnvelope {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(default)]
+    path: String,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    heads: HashMap<String, String>,
+    #[serde(default)]
+    body: ByteBuf,
+}
+
+
+/// Pack an envelope message, a JSON text message or a msgpack binary message. The body of a text message will be embedded
+/// in `body` verbatim if it was a valid JSON, such as the responses of the JSON serializers, or else in `text` as a string
+pub fn pack(id: Option<&str>, path: &str, heads: &HashMap<String, String>, body: Option<Serialized>, binary: bool) -> Message {
+    let (id, path, heads) = (id.map(ToString::to_string), path.to_string(), heads.clone());
+    let body = match body {
+        Some(Serialized::Bytes(bytes)) => bytes.to_vec(),
+        Some(Serialized::String(str)) => str.into_bytes(),
+        None => vec![],
+    };
+    if binary {
+        let envelope = BinaryEnvelope { id, path, heads, body: ByteBuf::from(body) };
+        // the envelope only contains strings, maps and bytes, so it could always be encoded
+        return Message::Binary(rmp_serde::to_vec_named(&envelope).unwrap_or_default());
+    }
+    let text = String::from_utf8_lossy(&body).to_string();
+    // the surrounding whitespaces would be trimmed as a JSON value and a null body would be parsed as empty, so keep them in the text
+    let body = serde_json::from_str::<&RawValue>(&text).ok()
+        .filter(|raw| raw.get().len() == text.len() && raw.get() != "null").map(ToOwned::to_owned);
+    let text = (body.is_none() && ! text.is_empty()).then_some(text);
+    Message::Text(serde_json::to_string(&TextEnvelope { id, path, heads, body, text }).unwrap_or_default())
+}
+
+/// Parse an envelope message, returns the heads with the id and path, and the body. The JSON `body` will be taken verbatim,
+/// so that the apis could deserialize it with the JSON deserializers, and the `text` will be taken as the raw body
+pub fn parse(msg: &Message) -> DceResult<(HashMap<String, String>, Bytes)> {
+    let (id, path, mut heads, body) = match msg {
+        Message::Text(text) => {
+            let envelope = serde_json::from_str::<TextEnvelope>(text).map_err(|e| DceErr::openly(400, format!("Invalid envelope: {e}")))?;
+            let body = match (envelope.body, envelope.text) {
+                (Some(raw), _) => Bytes::from(raw.get().to_string()),
+                (None, Some(text)) => Bytes::from(text),
+                (None, None) => Bytes::new(),
+            };
+            (envelope.id, envelope.path, envelope.heads, body)
+        },
+        Message::Binary(binary) => {
+            let envelope = rmp_serde::from_slice::<BinaryEnvelope>(binary).map_err(|e| DceErr::openly(400, format!("Invalid envelope: {e}")))?;
+            (envelope.id, envelope.path, envelope.heads, Bytes::from(envelope.body.into_vec()))
+        },
+        _ => return Err(DceErr::closed0("Not a data message")),
+    };
+    if let Some(id) = id {
+        heads.insert(HEAD_ID_NAME.to_string(), id);
+    }
+    heads.insert(HEAD_PATH_NAME.to_string(), path);
+    Ok((heads, body))
+}
@@ -1 +1,6 @@
 pub mod protocol;
+pub mod deflate;
+pub mod envelope;
+pub mod driver;
+pub mod registry;
+pub mod client;
@@ -4,16 +4,19 @@ use std::fmt::Debug;
 use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
 use async_trait::async_trait;
+use bytes::Bytes;
 use futures_util::SinkExt;
-use log::error;
-use tokio::net::TcpStream;
+use log::{error, warn};
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
 use tokio_tungstenite::WebSocketStream;
-use dce_router::protocol::{HEAD_ID_NAME, HEAD_PATH_NAME, Meta, RoutableProtocol};
+use dce_router::protocol::{HEAD_CODE_NAME, HEAD_ID_NAME, HEAD_PATH_NAME, MAX_HEAD_SIZE, Meta, RoutableProtocol};
 use dce_router::request::{Request, Response};
 use dce_router::router::Router;
 use dce_router::serializer::Serialized;
-use dce_util::mixed::{DceErr, DceResult};
+use dce_util::mixed::{DceErr, DceResult, SERVICE_UNAVAILABLE};
+use crate::envelope::{self, Framing};
 
 pub type SemiWebsocketRaw<'a> = Request<'a, SemiWebsocketProtocol, (), ()>;
 pub type SemiWebsocketGet<'a, Dto> = Request<'a, SemiWebsocketProtocol, (), Dto>;
@@ -30,91 +33,139 @@ pub struct SemiWebsocketProtocol {
     meta: Meta<Message, Message>,
     body_index: usize,
     binary_response: bool,
+    framing: Framing,
+    envelope_body: Option<Bytes>,
 }
 
 impl SemiWebsocketProtocol {
+    /// Parse the message in the framing, the response of an envelope request will be in the same message type with it
+    pub fn new(msg: Message, framing: Framing) -> Self {
+        if framing == Framing::Semi {
+            return Self::from(msg);
+        }
+        let (heads, body) = envelope::parse(&msg).unwrap_or_else(|e| {
+            // the empty path will be responded as not found
+            warn!("{e}");
+            (HashMap::from([(HEAD_PATH_NAME.to_string(), "".to_string())]), Bytes::new())
+        });
+        let binary_response = msg.is_binary();
+        Self { meta: Meta::new(msg, heads), body_index: 0, binary_response, framing, envelope_body: Some(body) }
+    }
+
     pub fn binary(mut self) -> Self {
         self.binary_response = true;
         self
     }
 
-    pub async fn route(
-        self,
-        router: Arc<Router<Self>>,
-        ws_stream: &mut WebSocketStream<TcpStream>,
-        context_data: HashMap<String, Box<dyn Any + Send>>,
-    ) {
-        if let Some(handled) = Self::handle(self, router, context_data).await {
-            let _ = ws_stream.send(handled).await.map_err(|e| error!("{e}"));
+    pub fn framing(&self) -> Framing {
+        self.framing
+    }
+
+    /// The handshake config which bounds the message size with the max body of the router, the oversize messages will be rejected before buffered
+    pub fn ws_config(router: &Router<SemiWebsocketProtocol>) -> WebSocketConfig {
+        let max_message_size = router.max_body().saturating_add(MAX_HEAD_SIZE);
+        WebSocketConfig { max_message_size: Some(max_message_size), max_frame_size: Some(max_message_size), ..Default::default() }
+    }
+
+    /// Pack a message in the semi websocket format, `id;path\nkey:value\n>BODY>>>\nbody`
+    pub fn pack_message(id: Option<&str>, path: &str, heads: &HashMap<String, String>, body: Option<Serialized>, binary: bool) -> Message {
+        if binary {
+            let mut binary = vec![];
+            if let Some(id) = id {
+                binary.extend(id.as_bytes());
+                binary.push(ID_PATH_SEPARATOR as u8);
+            }
+            binary.extend(path.as_bytes());
+            for (k, v) in heads {
+                binary.extend(format!("\n{k}:{v}").as_bytes());
+            }
+            binary.extend(format!("\n{}\n", HEAD_BODY_SEPARATOR).as_bytes());
+            if let Some(sd) = body {
+                binary.extend(match sd {
+                    Serialized::Bytes(bts) => bts.to_vec(),
+                    Serialized::String(str) => str.into_bytes(),
+                });
+            }
+            Message::Binary(binary)
+        } else {
+            let mut text = "".to_string();
+            if let Some(id) = id {
+                text.push_str(id);
+                text.push(ID_PATH_SEPARATOR);
+            }
+            text.push_str(path);
+            for (k, v) in heads {
+                text.push_str(format!("\n{k}:{v}").as_str());
+            }
+            text.push_str(format!("\n{}\n", HEAD_BODY_SEPARATOR).as_str());
+            if let Some(sd) = body {
+                text.push_str(match sd {
+                    Serialized::Bytes(bts) => String::from_utf8_lossy(bts.deref()).to_string(),
+                    Serialized::String(str) => str,
+                }.as_str());
+            }
+            Message::Text(text)
         }
     }
-}
 
-impl From<Message> for SemiWebsocketProtocol {
-    fn from(value: Message) -> Self {
+    /// Parse the head part of a semi websocket message, returns the heads with the id and path, and the start index of the body
+    pub fn parse_head(data: &[u8]) -> (HashMap<String, String>, usize) {
         let mut body_index = 0;
         let mut heads = HashMap::new();
-        let mut path = value.to_text().map_or("", &str::trim).to_string();
-        if let Some(index) = path.find(HEAD_BODY_SEPARATOR) {
-            let mut head_lines: Vec<_> = path[0..index].lines().map(ToString::to_string).collect();
-            path = head_lines.remove(0);
+        // only the head part need to be text, the body part could be any binary such as protobuf or images
+        let head_end = data.windows(HEAD_BODY_SEPARATOR.len()).position(|w| w == HEAD_BODY_SEPARATOR.as_bytes());
+        let head = String::from_utf8_lossy(&data[0..head_end.unwrap_or(data.len())]);
+        let mut head_lines = head.trim().lines();
+        let mut path = head_lines.next().unwrap_or("").to_string();
+        if let Some(index) = head_end {
             if let Some((tmp_id, tmp_path)) = path.split_once(ID_PATH_SEPARATOR) {
                 heads.insert(HEAD_ID_NAME.to_string(), tmp_id.to_string());
                 path = tmp_path.to_string();
             }
-            heads.extend(head_lines.iter().map(|line| line.split_once(':')
+            heads.extend(head_lines.map(|line| line.split_once(':')
                 .map_or_else(|| (line.to_string(), "".to_string()), |(k, v)| (k.to_string(), v.to_string()))));
             body_index = index + HEAD_BODY_SEPARATOR.len();
+            // skip the line break after the separator
+            body_index += data[body_index..].iter().take_while(|b| **b == b'\n' || **b == b'\r').count();
         }
         heads.insert(HEAD_PATH_NAME.to_string(), path);
-        Self { meta: Meta::new(value, heads), body_index, binary_response: false, }
+        (heads, body_index)
+    }
+
+    pub async fn route<S: AsyncRead + AsyncWrite + Unpin>(
+        self,
+        router: Arc<Router<Self>>,
+        ws_stream: &mut WebSocketStream<S>,
+        context_data: HashMap<String, Box<dyn Any + Send>>,
+    ) {
+        if let Some(handled) = Self::handle(self, router, context_data).await {
+            let _ = ws_stream.send(handled).await.map_err(|e| error!("{e}"));
+        }
+    }
+}
+
+impl From<Message> for SemiWebsocketProtocol {
+    fn from(value: Message) -> Self {
+        let data: &[u8] = match &value {
+            Message::Text(text) => text.as_bytes(),
+            Message::Binary(binary) => binary,
+            _ => &[],
+        };
+        let (heads, body_index) = Self::parse_head(data);
+        Self { meta: Meta::new(value, heads), body_index, binary_response: false, framing: Framing::Semi, envelope_body: None }
     }
 }
 
 impl Into<Message> for SemiWebsocketProtocol {
     fn into(mut self) -> Message {
-        let resp = self.resp_mut().take();
-        let (id, path) = (self.id(), self.path());
-        match resp {
+        match self.resp_mut().take() {
             Some(Response::Raw(resp)) => resp,
             resp => {
-                if self.binary_response {
-                    let mut binary = vec![];
-                    if let Some(id) = id {
-                        binary.extend(id.as_bytes());
-                        binary.push(ID_PATH_SEPARATOR as u8);
-                    }
-                    binary.extend(path.as_bytes());
-                    for (k, v) in self.resp_heads() {
-                        binary.extend(format!("\n{k}:{v}").as_bytes());
-                    }
-                    binary.extend(format!("\n{}\n", HEAD_BODY_SEPARATOR).as_bytes());
-                    if let Some(Response::Serialized(sd)) = resp {
-                        binary.extend(match sd {
-                            Serialized::Bytes(bts) => bts.to_vec(),
-                            Serialized::String(str) => str.into_bytes(),
-                        });
-                    }
-                    Message::Binary(binary)
-                } else {
-                    let mut text = "".to_string();
-                    if let Some(id) = id {
-                        text.push_str(id);
-                        text.push(ID_PATH_SEPARATOR);
-                    }
-                    text.push_str(path);
-                    for (k, v) in self.resp_heads() {
-                        text.push_str(format!("\n{k}:{v}").as_str());
-                    }
-                    text.push_str(format!("\n{}\n", HEAD_BODY_SEPARATOR).as_str());
-                    if let Some(Response::Serialized(sd)) = resp {
-                        text.push_str(match sd {
-                            Serialized::Bytes(bts) => String::from_utf8_lossy(bts.deref()).to_string(),
-                            Serialized::String(str) => str,
-                        }.as_str());
-                    }
-                    Message::Text(text)
-                }
+                let body = match resp {
+                    Some(Response::Serialized(sd)) => Some(sd),
+                    _ => None,
+                };
+                self.framing.pack(self.id(), self.path(), self.resp_heads(), body, self.binary_response)
             }
         }
     }
@@ -140,8 +191,16 @@ impl RoutableProtocol for SemiWebsocketProtocol {
     type Resp = Self::Req;
 
     async fn body(&mut self) -> DceResult<Serialized> {
-        self.req_mut().take().ok_or_else(|| DceErr::closed0("Empty request"))?.to_text()
-            .map(|t| Serialized::String(t[self.body_index ..].to_string())).map_err(DceErr::closed0)
+        if self.framing == Framing::Envelope {
+            self.req_mut().take().ok_or_else(|| DceErr::closed0("Empty request"))?;
+            let body = self.envelope_body.take().unwrap_or_default();
+            self.check_body_size(body.len())?;
+            return Ok(Serialized::Bytes(body));
+        }
+        // the message was already read, so only the size limit need to check
+        self.check_body_size(self.req()?.len().saturating_sub(self.body_index))?;
+        let data = Bytes::from(self.req_mut().take().ok_or_else(|| DceErr::closed0("Empty request"))?.into_data());
+        Ok(Serialized::Bytes(data.slice(self.body_index.min(data.len()) ..)))
     }
 
     fn pack_resp(&self, serialized: Serialized) -> Self::Resp {
@@ -150,4 +209,15 @@ impl RoutableProtocol for SemiWebsocketProtocol {
             Serialized::String(str) => str,
         })
     }
+
+    /// Keep the id and path in the error responses, and tell the error code with the `Code` head
+    fn err_into(mut self, err: DceErr) -> Self::Resp {
+        let code = match &err {
+            DceErr::Openly(e) => e.code,
+            DceErr::Closed(_) => SERVICE_UNAVAILABLE,
+        };
+        self.resp_heads_mut().insert(HEAD_CODE_NAME.to_string(), code.to_string());
+        *self.resp_mut() = Some(Response::Serialized(Serialized::String(err.to_responsible())));
+        self.into()
+    }
 }
@@ -0,0 +1,57 @@
+use std::ops::Deref;
+use tokio_tungstenite::tungstenite::Message;
+use dce_router::serializer::Serialized;
+use dce_util::registry::{ConnectionRegistry, PushTarget};
+use crate::envelope::Framing;
+
+
+/// The registry of the live semi websocket connections, set it to the [SemiWebsocketDriver](crate::driver::SemiWebsocketDriver) to register
+/// the connections, then the server could push messages to the clients by sid, uid or room. The [RegisteredConnection](dce_util::registry::RegisteredConnection)
+/// can be got from the context data with key [REGISTRY_DATA_NAME](dce_util::registry::REGISTRY_DATA_NAME) to bind the uid or join rooms
+#[derive(Debug, Clone, Default)]
+pub struct SemiWebsocketRegistry {
+    registry: ConnectionRegistry<Message>,
+    binary: bool,
+    framing: Framing,
+}
+
+impl SemiWebsocketRegistry {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Push with binary messages
+    pub fn binary(mut self, binary: bool) -> Self {
+        self.binary = binary;
+        self
+    }
+
+    /// Push in the framing, the pushes are packed once for all the target connections, so they should be in the same framing
+    pub fn framing(mut self, framing: Framing) -> Self {
+        self.framing = framing;
+        self
+    }
+
+    /// Push a message without id to the target connections, returns the count of the connections which the message was queued to
+    pub fn push(&self, target: PushTarget, path: &str, body: Serialized) -> usize {
+        self.registry.send(&target, self.pack(path, body))
+    }
+
+    /// Pack a message without id for pushing, such as to build the messages forwarded from other cluster nodes
+    pub fn pack(&self, path: &str, body: Serialized) -> Message {
+        self.framing.pack(None, path, &Default::default(), Some(body), self.binary)
+    }
+
+    /// Push a message to the connections joined the room
+    pub fn broadcast(&self, room: &str, path: &str, body: Serialized) -> usize {
+        self.push(PushTarget::Room(room.to_string()), path, body)
+    }
+}
+
+impl Deref for SemiWebsocketRegistry {
+    type Target = ConnectionRegistry<Message>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.registry
+    }
+}
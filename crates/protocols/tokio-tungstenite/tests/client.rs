@@ -0,0 +1,106 @@
+use std::time::Duration;
+use tokio::io::duplex;
+use tokio::sync::mpsc;
+use tokio::test;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::WebSocketStream;
+use dce_macro::{api, openly_err};
+use dce_router::protocol::{MAX_BODY_EXTRA_NAME, MAX_HEAD_SIZE, RoutableProtocol};
+use dce_router::router::Router;
+use dce_router::serializer::{JsonSerializer, Serialized};
+use dce_tokio_tungstenite::client::{SemiWebsocketClient, SemiWebsocketMessage};
+use dce_tokio_tungstenite::driver::SemiWebsocketDriver;
+use dce_tokio_tungstenite::protocol::{SemiWebsocket, SemiWebsocketProtocol, SemiWebsocketRaw};
+use dce_tokio_tungstenite::registry::SemiWebsocketRegistry;
+use dce_util::mixed::DceErr;
+use dce_util::registry::PushTarget;
+
+#[api]
+async fn echo(mut req: SemiWebsocketRaw) {
+    let body = req.rp_mut().body().await?;
+    req.pack(body)
+}
+
+#[api]
+async fn denied(_req: SemiWebsocketRaw) {
+    Err(openly_err!(403, "Forbidden"))
+}
+
+#[api(serializer = JsonSerializer{}, deserializer = JsonSerializer{})]
+async fn sum(mut req: SemiWebsocket<Vec<u32>, u32>) {
+    let numbers: Vec<u32> = req.req().await?;
+    req.resp(numbers.iter().sum::<u32>())
+}
+
+static PUSHED: std::sync::OnceLock<mpsc::UnboundedSender<String>> = std::sync::OnceLock::new();
+
+#[api("notice")]
+async fn notice(mut req: SemiWebsocketRaw) {
+    let body = req.rp_mut().body().await?;
+    let _ = PUSHED.get().unwrap().send(body.to_string());
+    req.end(None)
+}
+
+#[test]
+async fn request_and_push() {
+    let router = Router::new().unwrap().push(echo).push(denied).ready().unwrap();
+    let registry = SemiWebsocketRegistry::new().binary(true);
+    let (client, server) = duplex(1024);
+    let driver = SemiWebsocketDriver::new(router.clone()).binary(true).registry(registry.clone());
+    tokio::spawn(async move { driver.drive(WebSocketStream::from_raw_socket(server, Role::Server, None).await, |_| Default::default()).await });
+
+    let (sender, mut pushed) = mpsc::unbounded_channel();
+    PUSHED.set(sender).unwrap();
+    let push_router = Router::new().unwrap().push(notice).ready().unwrap();
+    let client = SemiWebsocketClient::new(WebSocketStream::from_raw_socket(client, Role::Client, None).await, Some(push_router.clone()))
+        .binary(true).timeout(Duration::from_millis(500));
+
+    let binary = vec![0u8, 159, 146, 150];
+    let (a, b) = tokio::join!(client.request(SemiWebsocketMessage::new("echo").body(binary.clone())), client.request(SemiWebsocketMessage::new("echo").body("b")));
+    assert_eq!((&a.unwrap().body[..], &b.unwrap().body[..]), (&binary[..], &b"b"[..]));
+    match client.request(SemiWebsocketMessage::new("denied")).await {
+        Err(DceErr::Openly(e)) => assert_eq!((e.code, e.message.as_str()), (403, "Forbidden")),
+        result => panic!("unexpected {result:?}"),
+    }
+
+    assert_eq!(registry.push(PushTarget::All, "notice", Serialized::String("hi".to_string())), 1);
+    assert_eq!(pushed.recv().await.unwrap(), "hi");
+}
+
+#[test]
+async fn body_limits() {
+    let router = Router::new().unwrap().set_extra(MAX_BODY_EXTRA_NAME, Box::new(16usize)).push(echo).ready().unwrap();
+    let (client, server) = duplex(1024);
+    let driver = SemiWebsocketDriver::new(router.clone());
+    let config = SemiWebsocketProtocol::ws_config(router);
+    tokio::spawn(async move { driver.drive(WebSocketStream::from_raw_socket(server, Role::Server, Some(config)).await, |_| Default::default()).await });
+    let client = SemiWebsocketClient::new(WebSocketStream::from_raw_socket(client, Role::Client, None).await, None)
+        .timeout(Duration::from_millis(500));
+
+    assert_eq!(client.request(SemiWebsocketMessage::new("echo").body("0123456789abcdef")).await.unwrap().body.len(), 16);
+    match client.request(SemiWebsocketMessage::new("echo").body("0123456789abcdefg")).await {
+        Err(DceErr::Openly(e)) => assert_eq!(e.code, 413),
+        result => panic!("unexpected {result:?}"),
+    }
+    // the message exceeded the max body and head was rejected before buffered, and the connection was closed
+    assert!(client.request(SemiWebsocketMessage::new("echo").body(vec![b'0'; 17 + MAX_HEAD_SIZE])).await.is_err());
+    assert!(client.request(SemiWebsocketMessage::new("echo").body("0")).await.is_err());
+}
+
+#[test]
+async fn binary_body() {
+    let router = Router::new().unwrap().push(echo).push(sum).ready().unwrap();
+    let (client, server) = duplex(1024);
+    let driver = SemiWebsocketDriver::new(router.clone()).binary(true);
+    tokio::spawn(async move { driver.drive(WebSocketStream::from_raw_socket(server, Role::Server, None).await, |_| Default::default()).await });
+    let client = SemiWebsocketClient::new(WebSocketStream::from_raw_socket(client, Role::Client, None).await, None)
+        .binary(true).timeout(Duration::from_millis(200));
+
+    // the body was sliced after the heads as is, not decoded as utf-8
+    let body = vec![0u8, 0xff, b'\n', 0xfe, b'\n', b'\n', 0x80];
+    let resp = client.request(SemiWebsocketMessage::new("echo").head("Note", "x").body(body.clone())).await.unwrap();
+    assert_eq!(&resp.body[..], &body[..]);
+    // decoded only when the deserializer asks
+    assert_eq!(&client.request(SemiWebsocketMessage::new("sum").body("[1, 2, 3]")).await.unwrap().body[..], b"6");
+    assert!(client.request(SemiWebsocketMessage::new("sum").body(vec![0xffu8])).await.is_err());
+}
@@ -0,0 +1,145 @@
+use std::io::Write;
+use flate2::{Compression, Decompress, FlushDecompress};
+use flate2::write::DeflateEncoder;
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt, DuplexStream};
+use tokio::test;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use dce_tokio_tungstenite::deflate::DeflateStream;
+
+const DEFLATE_TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+#[test]
+async fn round_trip() {
+    let (client, server) = duplex(64 * 1024);
+    let mut server = WebSocketStream::from_raw_socket(DeflateStream::new(server, true, usize::MAX), Role::Server, None).await;
+    let mut client = WebSocketStream::from_raw_socket(DeflateStream::new(client, true, usize::MAX), Role::Client, None).await;
+    let long = "dce ".repeat(256);
+    // the masked client frames and the unmasked server frames, compressed or too short to compress
+    for msg in [Message::Text(long.clone()), Message::Binary(long.clone().into_bytes()), Message::Text("short".to_string())] {
+        client.send(msg.clone()).await.unwrap();
+        let received = server.next().await.unwrap().unwrap();
+        assert_eq!(received, msg);
+        server.send(received).await.unwrap();
+        assert_eq!(client.next().await.unwrap().unwrap(), msg);
+    }
+}
+
+#[test]
+async fn fragments_and_controls() {
+    let (mut raw, server) = duplex(64 * 1024);
+    let mut server = WebSocketStream::from_raw_socket(DeflateStream::new(server, true, usize::MAX), Role::Server, None).await;
+    let text = "fragmented ".repeat(64);
+    let compressed = deflate(text.as_bytes());
+    let (head, tail) = compressed.split_at(compressed.len() / 2);
+    let mut wire = vec![];
+    // a compressed text message in two fragments, with a ping interleaved
+    masked_frame(0x40 | 0x1, head, &mut wire);
+    masked_frame(0x80 | 0x9, b"ping", &mut wire);
+    masked_frame(0x80, tail, &mut wire);
+    raw.write_all(&wire).await.unwrap();
+    assert_eq!(server.next().await.unwrap().unwrap(), Message::Ping(b"ping".to_vec()));
+    assert_eq!(server.next().await.unwrap().unwrap(), Message::Text(text.clone()));
+
+    // the server sends the compressed message unmasked
+    server.send(Message::Text(text.clone())).await.unwrap();
+    let (first, masked, payload) = loop {
+        let frame = read_frame(&mut raw).await;
+        // skip the pong answered automatically
+        if frame.0 & 0x0f != 0xa { break frame; }
+    };
+    assert_eq!((first, masked), (0x80 | 0x40 | 0x1, false));
+    assert_eq!(inflate(payload), text.as_bytes());
+}
+
+#[test]
+async fn client_masks() {
+    let (client, mut raw) = duplex(64 * 1024);
+    let mut client = WebSocketStream::from_raw_socket(DeflateStream::new(client, true, usize::MAX), Role::Client, None).await;
+    let text = "masked ".repeat(64);
+    let mut keys = vec![];
+    for _ in 0..2 {
+        client.send(Message::Text(text.clone())).await.unwrap();
+        let (first, key, payload) = read_masked_frame(&mut raw).await;
+        assert_eq!(first, 0x80 | 0x40 | 0x1);
+        assert_eq!(inflate(payload), text.as_bytes());
+        keys.push(key);
+    }
+    // a fresh random key for each frame
+    assert!(keys.iter().all(|key| key != &[0; 4]));
+    assert_ne!(keys[0], keys[1]);
+}
+
+#[test]
+async fn inflate_limit() {
+    let (mut raw, server) = duplex(64 * 1024);
+    let mut server = WebSocketStream::from_raw_socket(DeflateStream::new(server, true, 64 * 1024), Role::Server, None).await;
+    // a tiny compressed frame which inflates far over the limit
+    let compressed = deflate(&vec![b'x'; 1 << 20]);
+    assert!(compressed.len() < 64 * 1024);
+    let mut wire = vec![];
+    masked_frame(0x80 | 0x40 | 0x2, &compressed, &mut wire);
+    raw.write_all(&wire).await.unwrap();
+    assert!(server.next().await.unwrap().is_err());
+}
+
+fn deflate(payload: &[u8]) -> Vec<u8> {
+    let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+    encoder.write_all(payload).unwrap();
+    encoder.flush().unwrap();
+    let mut compressed = std::mem::take(encoder.get_mut());
+    compressed.truncate(compressed.len() - DEFLATE_TAIL.len());
+    compressed
+}
+
+fn inflate(mut compressed: Vec<u8>) -> Vec<u8> {
+    compressed.extend(DEFLATE_TAIL);
+    let mut payload = Vec::with_capacity(64 * 1024);
+    Decompress::new(false).decompress_vec(&compressed, &mut payload, FlushDecompress::Sync).unwrap();
+    payload
+}
+
+fn masked_frame(first: u8, payload: &[u8], out: &mut Vec<u8>) {
+    let key = [1u8, 2, 3, 4];
+    out.push(first);
+    match payload.len() {
+        len if len < 126 => out.push(0x80 | len as u8),
+        len => {
+            out.push(0x80 | 126);
+            out.extend((len as u16).to_be_bytes());
+        },
+    }
+    out.extend(key);
+    out.extend(payload.iter().enumerate().map(|(i, b)| b ^ key[i % 4]));
+}
+
+async fn read_frame(raw: &mut DuplexStream) -> (u8, bool, Vec<u8>) {
+    let mut head = [0u8; 2];
+    raw.read_exact(&mut head).await.unwrap();
+    let len = match head[1] & 0x7f {
+        126 => raw.read_u16().await.unwrap() as usize,
+        127 => raw.read_u64().await.unwrap() as usize,
+        len => len as usize,
+    };
+    let mut payload = vec![0; len];
+    raw.read_exact(&mut payload).await.unwrap();
+    (head[0], head[1] & 0x80 > 0, payload)
+}
+
+async fn read_masked_frame(raw: &mut DuplexStream) -> (u8, [u8; 4], Vec<u8>) {
+    let mut head = [0u8; 2];
+    raw.read_exact(&mut head).await.unwrap();
+    assert!(head[1] & 0x80 > 0);
+    let len = match head[1] & 0x7f {
+        126 => raw.read_u16().await.unwrap() as usize,
+        len => len as usize,
+    };
+    let mut key = [0u8; 4];
+    raw.read_exact(&mut key).await.unwrap();
+    let mut payload = vec![0; len];
+    raw.read_exact(&mut payload).await.unwrap();
+    payload.iter_mut().enumerate().for_each(|(i, b)| *b ^= key[i % 4]);
+    (head[0], key, payload)
+}
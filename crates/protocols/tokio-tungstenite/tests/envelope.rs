@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::io::duplex;
+use tokio::test;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use dce_macro::{api, openly_err};
+use dce_router::protocol::{RoutableProtocol, HEAD_ID_NAME};
+use dce_router::router::Router;
+use dce_router::serializer::Serialized;
+use dce_tokio_tungstenite::client::{SemiWebsocketClient, SemiWebsocketMessage};
+use dce_tokio_tungstenite::driver::SemiWebsocketDriver;
+use dce_tokio_tungstenite::envelope::{self, Framing, FRAMING_EXTRA_NAME};
+use dce_tokio_tungstenite::protocol::SemiWebsocketRaw;
+use dce_util::mixed::DceErr;
+
+#[api]
+async fn echo(mut req: SemiWebsocketRaw) {
+    let body = req.rp_mut().body().await?;
+    req.pack(body)
+}
+
+#[api]
+async fn denied(_req: SemiWebsocketRaw) {
+    Err(openly_err!(403, "Forbidden"))
+}
+
+#[test]
+async fn mirror() {
+    let router = Router::new().unwrap().set_extra(FRAMING_EXTRA_NAME, Box::new(Framing::Envelope)).push(echo).push(denied).ready().unwrap();
+    let (client, server) = duplex(1024);
+    // the binary option only applies to the semi framing
+    let driver = SemiWebsocketDriver::new(router.clone()).binary(true);
+    tokio::spawn(async move { driver.drive(WebSocketStream::from_raw_socket(server, Role::Server, None).await, |_| Default::default()).await });
+    let mut ws_stream = WebSocketStream::from_raw_socket(client, Role::Client, None).await;
+
+    ws_stream.send(Message::Text(json!({"id": "1", "path": "echo", "heads": {"k": "a\nb"}, "body": {"a": [1, 2]}}).to_string())).await.unwrap();
+    let Some(Ok(Message::Text(text))) = ws_stream.next().await else { panic!("not a text response") };
+    assert_eq!(serde_json::from_str::<Value>(&text).unwrap(), json!({"id": "1", "path": "echo", "body": {"a": [1, 2]}}));
+    ws_stream.send(Message::Text(json!({"id": "2", "path": "denied"}).to_string())).await.unwrap();
+    let Some(Ok(Message::Text(text))) = ws_stream.next().await else { panic!("not a text response") };
+    let resp = serde_json::from_str::<Value>(&text).unwrap();
+    assert_eq!((&resp["id"], &resp["heads"]["Code"]), (&json!("2"), &json!("403")));
+
+    let client = SemiWebsocketClient::with_framing(ws_stream, None, Framing::Envelope).binary(true).timeout(Duration::from_millis(500));
+    let binary = vec![0u8, 159, 146, 150];
+    let resp = client.request(SemiWebsocketMessage::new("echo").body(binary.clone())).await.unwrap();
+    assert_eq!(&resp.body[..], &binary[..]);
+    match client.request(SemiWebsocketMessage::new("denied")).await {
+        Err(DceErr::Openly(e)) => assert_eq!((e.code, e.message.as_str()), (403, "Forbidden")),
+        result => panic!("unexpected {result:?}"),
+    }
+    assert_eq!(Framing::Envelope.pack(None, "echo", &Default::default(), Some(Serialized::String("plain".to_string())), false),
+        Message::Text(r#"{"path":"echo","text":"plain"}"#.to_string()));
+}
+
+#[test]
+async fn round_trip() {
+    // the JSON bodies are embedded verbatim, and the others as strings
+    for (body, embedded) in [(r#""hi""#, r#""body":"hi""#), ("123", r#""body":123"#), ("1e2", r#""body":1e2"#),
+        (r#"{"b": 1, "a": [1, 2]}"#, r#""body":{"b": 1, "a": [1, 2]}"#), ("plain", r#""text":"plain""#), (" 1", r#""text":" 1""#),
+        ("null", r#""text":"null""#), ("", r#""path":"echo"}"#)] {
+        let msg = envelope::pack(Some("1"), "echo", &HashMap::new(), Some(Serialized::String(body.to_string())), false);
+        assert!(msg.to_text().unwrap().contains(embedded), "{msg}");
+        let (heads, parsed) = envelope::parse(&msg).unwrap();
+        assert_eq!((heads.get(HEAD_ID_NAME).map(String::as_str), &parsed[..]), (Some("1"), body.as_bytes()), "{msg}");
+    }
+    // a text body sent by the clients will be taken as is
+    let msg = Message::Text(json!({"path": "echo", "text": "\"hi\""}).to_string());
+    assert_eq!(&envelope::parse(&msg).unwrap().1[..], br#""hi""#);
+}
+
+#[test]
+async fn select() {
+    let router = Router::new().unwrap().push(echo).ready().unwrap();
+    assert_eq!(Framing::negotiate(["chat, dce.envelope", "dce.semi"]), Some(Framing::Envelope));
+    assert_eq!(Framing::negotiate(["chat"]), None);
+    assert_eq!(Framing::select(router, &HashMap::new()), Framing::Semi);
+    let headers = HashMap::from([("sec-websocket-protocol".to_string(), "dce.envelope".to_string())]);
+    assert_eq!(Framing::select(router, &headers), Framing::Envelope);
+}
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "session")]
+use std::sync::RwLock;
+use std::time::Duration;
+use futures_util::{SinkExt, StreamExt};
+use log::{debug, error};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::{TcpStream, ToSocketAddrs};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio_util::codec::{Decoder, Framed};
+use dce_router::protocol::{HEAD_CODE_NAME, RoutableProtocol};
+#[cfg(feature = "session")]
+use dce_router::protocol::HEAD_SID_NAME;
+use dce_router::router::Router;
+use dce_util::mixed::{DceErr, DceResult};
+#[cfg(all(feature = "unix", unix))]
+use dce_util::unix::UnixAddr;
+use crate::codec::{Frame, SemiTcpCodec};
+use crate::protocol::SemiTcpProtocol;
+
+/// Default timeout of waiting for the response
+pub const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+/// The pending outgoing frames, the sending will wait if it was full
+const OUTGOING_BUFFER_SIZE: usize = 64;
+
+
+/// A semi tcp client, the requests will be sent with an auto increment id, and the responses will be matched back by id,
+/// so the requests could be sent concurrently through the clones. The frames without id or with an unknown id are taken as
+/// the server pushes, and will be routed to the push router if set, the responses of the push apis will be dropped
+#[derive(Debug, Clone)]
+pub struct SemiTcpClient {
+    outgoing: mpsc::Sender<Frame>,
+    shared: Arc<Shared>,
+    timeout: Duration,
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    next_id: AtomicU64,
+    pending: Mutex<HashMap<String, oneshot::Sender<Frame>>>,
+    #[cfg(feature = "session")]
+    sid: RwLock<Option<String>>,
+    tasks: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl SemiTcpClient {
+    /// Connect to a semi tcp server with the binary frames
+    pub async fn connect<A: ToSocketAddrs>(addr: A, router: Option<Arc<Router<SemiTcpProtocol>>>) -> DceResult<Self> {
+        let stream = TcpStream::connect(addr).await.map_err(DceErr::closed0)?;
+        Ok(Self::new(SemiTcpCodec::binary().framed(stream), router))
+    }
+
+    /// Connect to a semi tcp server listening on a unix socket, the address prefixed with `@` is an abstract name
+    #[cfg(all(feature = "unix", unix))]
+    pub async fn connect_unix(addr: &UnixAddr, router: Option<Arc<Router<SemiTcpProtocol>>>) -> DceResult<Self> {
+        let stream = addr.connect().await.map_err(DceErr::closed0)?;
+        Ok(Self::new(SemiTcpCodec::binary().framed(stream), router))
+    }
+
+    /// Start the client on a framed stream, the stream could be a tcp stream or any wrapped stream such as a tls stream
+    pub fn new<S>(framed: Framed<S, SemiTcpCodec>, router: Option<Arc<Router<SemiTcpProtocol>>>) -> Self
+    where S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (mut sink, mut stream) = framed.split();
+        let (outgoing, mut receiver) = mpsc::channel::<Frame>(OUTGOING_BUFFER_SIZE);
+        let shared = Arc::new(Shared::default());
+        let writer = tokio::spawn(async move {
+            while let Some(frame) = receiver.recv().await {
+                if let Err(e) = sink.send(frame).await {
+                    error!("{e}");
+                    break;
+                }
+            }
+        });
+        let reader_shared = shared.clone();
+        let reader = tokio::spawn(async move {
+            while let Some(frame) = stream.next().await {
+                let frame = match frame {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        error!("{e}");
+                        break;
+                    },
+                };
+                let waiter = frame.id.as_ref().and_then(|id| reader_shared.pending.lock().ok()?.remove(id));
+                match (waiter, &router) {
+                    (Some(waiter), _) => { let _ = waiter.send(frame); },
+                    (None, Some(router)) => { SemiTcpProtocol::handle(SemiTcpProtocol::from(frame), router.clone(), Default::default()).await; },
+                    (None, None) => debug!("unhandled push: {}", frame.path),
+                }
+            }
+            // the waiters will get a closed error after their senders were dropped
+            if let Ok(mut pending) = reader_shared.pending.lock() {
+                pending.clear();
+            }
+        });
+        if let Ok(mut tasks) = shared.tasks.lock() {
+            tasks.extend([writer, reader]);
+        }
+        Self { outgoing, shared, timeout: DEFAULT_REQUEST_TIMEOUT }
+    }
+
+    /// Set the timeout of waiting for the responses
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the sid to carry in the requests
+    #[cfg(feature = "session")]
+    pub fn with_sid(self, sid: Option<String>) -> Self {
+        if let Ok(mut current) = self.shared.sid.write() {
+            *current = sid;
+        }
+        self
+    }
+
+    /// The current sid, it will be replaced with the renewed one from the response heads automatically
+    #[cfg(feature = "session")]
+    pub fn sid(&self) -> Option<String> {
+        self.shared.sid.read().ok()?.clone()
+    }
+
+    /// Send a request and wait for its response, the id of the frame will be replaced with an auto increment one.
+    /// The error responses will be converted to the openly errors with the responded code
+    pub async fn request(&self, mut frame: Frame) -> DceResult<Frame> {
+        let id = self.shared.next_id.fetch_add(1, Ordering::Relaxed).to_string();
+        frame.id = Some(id.clone());
+        let (waiter, response) = oneshot::channel();
+        self.shared.pending.lock().map_err(DceErr::closed0)?.insert(id.clone(), waiter);
+        if let Err(e) = self.send(frame).await {
+            self.forget(&id);
+            return Err(e);
+        }
+        let frame = match tokio::time::timeout(self.timeout, response).await {
+            Ok(Ok(frame)) => frame,
+            Ok(Err(_)) => return Err(DceErr::closed0("Connection closed before responded")),
+            Err(_) => {
+                self.forget(&id);
+                return Err(DceErr::closed0(format!("Request \"{id}\" timeout")));
+            },
+        };
+        #[cfg(feature = "session")]
+        if let Some(sid) = frame.heads.get(HEAD_SID_NAME) {
+            if let Ok(mut current) = self.shared.sid.write() {
+                // an empty sid means the session was dropped by the server, such as after logout
+                *current = Some(sid.clone()).filter(|sid| ! sid.is_empty());
+            }
+        }
+        match frame.heads.get(HEAD_CODE_NAME).and_then(|code| code.parse::<isize>().ok()) {
+            Some(code) => {
+                let message = String::from_utf8_lossy(&frame.body);
+                let message = message.strip_prefix(format!("{code}: ").as_str()).unwrap_or(&message);
+                Err(DceErr::openly(code, message.to_string()))
+            },
+            None => Ok(frame),
+        }
+    }
+
+    /// Send a frame without waiting for the response, the sid will be carried if set
+    pub async fn send(&self, #[cfg_attr(not(feature = "session"), allow(unused_mut))] mut frame: Frame) -> DceResult<()> {
+        #[cfg(feature = "session")]
+        if let Some(sid) = self.sid() {
+            frame.heads.entry(HEAD_SID_NAME.to_string()).or_insert(sid);
+        }
+        self.outgoing.send(frame).await.map_err(|_| DceErr::closed0("Connection closed"))
+    }
+
+    /// Stop the reading and writing, the clones will be closed too
+    pub fn close(&self) {
+        if let Ok(mut tasks) = self.shared.tasks.lock() {
+            tasks.drain(..).for_each(|task| task.abort());
+        }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.outgoing.is_closed()
+    }
+
+    fn forget(&self, id: &str) {
+        if let Ok(mut pending) = self.shared.pending.lock() {
+            pending.remove(id);
+        }
+    }
+}
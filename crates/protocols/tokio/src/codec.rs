@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind};
+use bytes::{Buf, BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+use dce_router::protocol::MAX_HEAD_SIZE;
+
+
+/// Version of the binary frame layout, the first byte of every binary frame
+pub const FRAME_VERSION: u8 = 1;
+/// Default max frame size, 8 MiB
+pub const DEFAULT_MAX_FRAME_SIZE: usize = 8 * 1024 * 1024;
+
+const FRAME_HEADER_LEN: usize = 5;
+const ID_PATH_SEPARATOR: char = ';';
+const HEAD_BODY_SEPARATOR: &str = ">BODY>>>";
+
+
+/// A decoded message of the semi tcp protocol
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Frame {
+    pub id: Option<String>,
+    pub path: String,
+    pub heads: HashMap<String, String>,
+    pub body: BytesMut,
+}
+
+impl Frame {
+    pub fn new<T: ToString>(path: T) -> Self {
+        Self { path: path.to_string(), ..Default::default() }
+    }
+
+    pub fn id<T: ToString>(mut self, id: T) -> Self {
+        self.id = Some(id.to_string());
+        self
+    }
+
+    pub fn head<K: ToString, V: ToString>(mut self, key: K, value: V) -> Self {
+        self.heads.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub fn body<T: Into<BytesMut>>(mut self, body: T) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    /// Parse the legacy text message, `id;path\nkey:value\n>BODY>>>\nbody`, the id, heads and body are optional
+    pub fn from_text(mut value: BytesMut) -> Self {
+        let mut frame = Self::default();
+        // search on the raw bytes, the lossy decoded text may be longer than the raw if it contains invalid utf-8 bytes
+        let head = match value.windows(HEAD_BODY_SEPARATOR.len()).position(|w| w == HEAD_BODY_SEPARATOR.as_bytes()) {
+            Some(index) => {
+                let head = String::from_utf8_lossy(&value[0..index]).trim().to_string();
+                let body_index = index + HEAD_BODY_SEPARATOR.len();
+                // keep the raw body bytes, only the line breaks around will be trimmed like the legacy
+                let body_index = body_index + value[body_index..].iter().take_while(|b| **b == b'\n' || **b == b'\r').count();
+                let body_end = value.len() - value[body_index..].iter().rev().take_while(|b| b.is_ascii_whitespace()).count();
+                value.truncate(body_end);
+                frame.body = value.split_off(body_index);
+                head
+            },
+            None => String::from_utf8_lossy(&value).trim().to_string(),
+        };
+        let mut lines = head.lines();
+        let path = lines.next().unwrap_or("");
+        frame.path = match path.split_once(ID_PATH_SEPARATOR) {
+            Some((id, path)) => {
+                frame.id = Some(id.to_string());
+                path.to_string()
+            },
+            None => path.to_string(),
+        };
+        frame.heads = lines.map(|line| line.split_once(':')
+            .map_or_else(|| (line.to_string(), "".to_string()), |(k, v)| (k.to_string(), v.to_string()))).collect();
+        frame
+    }
+
+    /// Pack into the legacy text message
+    pub fn into_text(self) -> BytesMut {
+        let mut text = BytesMut::new();
+        if let Some(id) = self.id {
+            text.put_slice(id.as_bytes());
+            text.put_u8(ID_PATH_SEPARATOR as u8);
+        }
+        text.put_slice(self.path.as_bytes());
+        for (k, v) in self.heads {
+            text.put_slice(format!("\n{k}:{v}").as_bytes())
+        }
+        text.put_slice(format!("\n{}\n", HEAD_BODY_SEPARATOR).as_bytes());
+        text.put(self.body);
+        text
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FrameMode {
+    /// Length prefixed frames: `[version: u8][length: u32]` followed by the `length` bytes payload,
+    /// the payload is `[id: u16 + bytes][path: u16 + bytes][heads count: u16]([key: u16 + bytes][value: u16 + bytes])*[body]`,
+    /// all the integers are big endian, and an empty id means no id
+    #[default]
+    Binary,
+    /// The legacy text message, every read chunk or datagram is taken as a message, it cannot tell the message boundaries on tcp streams
+    Text,
+}
+
+
+/// The codec of [Frame], could be used with `Framed` on tcp streams or `UdpFramed` on udp sockets
+#[derive(Debug, Clone)]
+pub struct SemiTcpCodec {
+    mode: FrameMode,
+    max_frame_size: usize,
+}
+
+impl SemiTcpCodec {
+    pub fn new(mode: FrameMode) -> Self {
+        Self { mode, max_frame_size: DEFAULT_MAX_FRAME_SIZE }
+    }
+
+    pub fn binary() -> Self {
+        Self::new(FrameMode::Binary)
+    }
+
+    pub fn text() -> Self {
+        Self::new(FrameMode::Text)
+    }
+
+    /// Set the max frame size, the payload size in binary mode or the message size in text mode, the oversize frames will be rejected with an `InvalidData` error
+    pub fn max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
+    /// Shrink the max frame size to fit the max body and the head, so that the oversize frames will be rejected before buffered
+    pub(crate) fn fit_body(&mut self, max_body: usize) {
+        self.max_frame_size = self.max_frame_size.min(max_body.saturating_add(MAX_HEAD_SIZE));
+    }
+
+    pub fn mode(&self) -> FrameMode {
+        self.mode
+    }
+
+    fn oversize(&self, size: usize) -> Error {
+        Error::new(ErrorKind::InvalidData, format!("frame size {size} exceeds the max {}", self.max_frame_size))
+    }
+
+    fn decode_binary(&self, src: &mut BytesMut) -> Result<Option<Frame>, Error> {
+        if src.len() < FRAME_HEADER_LEN {
+            return Ok(None);
+        }
+        if src[0] != FRAME_VERSION {
+            return Err(Error::new(ErrorKind::InvalidData, format!("unsupported frame version {}", src[0])));
+        }
+        let size = u32::from_be_bytes([src[1], src[2], src[3], src[4]]) as usize;
+        if size > self.max_frame_size {
+            return Err(self.oversize(size));
+        }
+        if src.len() < FRAME_HEADER_LEN + size {
+            src.reserve(FRAME_HEADER_LEN + size - src.len());
+            return Ok(None);
+        }
+        src.advance(FRAME_HEADER_LEN);
+        let mut payload = src.split_to(size);
+        let id = take_str(&mut payload)?;
+        let path = take_str(&mut payload)?;
+        let mut heads = HashMap::new();
+        for _ in 0..take_u16(&mut payload)? {
+            heads.insert(take_str(&mut payload)?, take_str(&mut payload)?);
+        }
+        Ok(Some(Frame { id: Some(id).filter(|id| ! id.is_empty()), path, heads, body: payload }))
+    }
+
+    fn encode_binary(&self, item: Frame, dst: &mut BytesMut) -> Result<(), Error> {
+        let heads_count = u16::try_from(item.heads.len()).map_err(|_| Error::new(ErrorKind::InvalidData, "too many heads to pack"))?;
+        let strings: Vec<_> = [item.id.as_deref().unwrap_or(""), item.path.as_str()].into_iter()
+            .chain(item.heads.iter().flat_map(|(k, v)| [k.as_str(), v.as_str()])).collect();
+        if let Some(long) = strings.iter().find(|s| s.len() > u16::MAX as usize) {
+            return Err(Error::new(ErrorKind::InvalidData, format!("string too long to pack: {} bytes", long.len())));
+        }
+        let size = strings.iter().map(|s| 2 + s.len()).sum::<usize>() + 2 + item.body.len();
+        if size > self.max_frame_size || size > u32::MAX as usize {
+            return Err(self.oversize(size));
+        }
+        dst.reserve(FRAME_HEADER_LEN + size);
+        dst.put_u8(FRAME_VERSION);
+        dst.put_u32(size as u32);
+        let (id_path, heads) = strings.split_at(2);
+        id_path.iter().for_each(|s| put_str(dst, s));
+        dst.put_u16(heads_count);
+        heads.iter().for_each(|s| put_str(dst, s));
+        dst.put(item.body);
+        Ok(())
+    }
+}
+
+impl Default for SemiTcpCodec {
+    fn default() -> Self {
+        Self::binary()
+    }
+}
+
+impl Decoder for SemiTcpCodec {
+    type Item = Frame;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match self.mode {
+            FrameMode::Binary => self.decode_binary(src),
+            FrameMode::Text if src.is_empty() => Ok(None),
+            FrameMode::Text if src.len() > self.max_frame_size => Err(self.oversize(src.len())),
+            FrameMode::Text => Ok(Some(Frame::from_text(src.split()))),
+        }
+    }
+}
+
+impl Encoder<Frame> for SemiTcpCodec {
+    type Error = Error;
+
+    fn encode(&mut self, item: Frame, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        match self.mode {
+            FrameMode::Binary => self.encode_binary(item, dst),
+            FrameMode::Text => {
+                let text = item.into_text();
+                if text.len() > self.max_frame_size {
+                    return Err(self.oversize(text.len()));
+                }
+                dst.put(text);
+                Ok(())
+            },
+        }
+    }
+}
+
+
+fn take_u16(src: &mut BytesMut) -> Result<u16, Error> {
+    if src.len() < 2 {
+        return Err(Error::new(ErrorKind::InvalidData, "frame truncated"));
+    }
+    Ok(src.get_u16())
+}
+
+fn take_str(src: &mut BytesMut) -> Result<String, Error> {
+    let len = take_u16(src)? as usize;
+    if src.len() < len {
+        return Err(Error::new(ErrorKind::InvalidData, "frame truncated"));
+    }
+    String::from_utf8(src.split_to(len).to_vec()).map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+fn put_str(dst: &mut BytesMut, value: &str) {
+    dst.put_u16(value.len() as u16);
+    dst.put_slice(value.as_bytes());
+}
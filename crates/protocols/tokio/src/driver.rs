@@ -0,0 +1,195 @@
+use std::any::Any;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use futures_util::{SinkExt, StreamExt};
+use log::{debug, error};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{Mutex, Semaphore};
+use tokio_util::codec::Framed;
+use dce_router::protocol::{ConnectionInfo, CONNECTION_DATA_NAME, EVENT_CONNECT_PATH, EVENT_DATA_NAME, EVENT_DISCONNECT_PATH, EVENT_IDLE_PATH, EVENT_PATH_PREFIX, RoutableProtocol};
+use dce_router::router::Router;
+use dce_util::mixed::DceErr;
+use dce_util::registry::{ConnectionRegistry, RegisteredConnection, REGISTRY_DATA_NAME};
+#[cfg(all(feature = "unix", unix))]
+use dce_util::unix::{PeerCred, PEER_CRED_DATA_NAME};
+use crate::codec::{Frame, SemiTcpCodec};
+use crate::protocol::SemiTcpProtocol;
+use crate::registry::SemiTcpRegistry;
+
+/// Default max count of the concurrently handling requests per connection
+pub const DEFAULT_MAX_IN_FLIGHT: usize = 64;
+
+
+/// Drive a framed connection, the requests will be routed concurrently, and the responses will be written back
+/// through a shared sink as soon as they were handled, so they may arrive out of order, the clients should match them by id.
+/// The lifecycle events `$connect`, `$disconnect` and `$idle` will be routed to the apis with the same paths if registered,
+/// the responses of `$connect` and `$idle` will be sent to the client, and the [ConnectionInfo] can be got from the context data.
+/// The events could be told apart by the [EVENT_DATA_NAME] context data, and the client requests with the `$` prefixed paths will be rejected
+#[derive(Debug, Clone)]
+pub struct SemiTcpDriver {
+    router: Arc<Router<SemiTcpProtocol>>,
+    max_in_flight: usize,
+    idle_timeout: Option<Duration>,
+    peer: Option<SocketAddr>,
+    registry: Option<SemiTcpRegistry>,
+    #[cfg(all(feature = "unix", unix))]
+    peer_cred: Option<Arc<PeerCred>>,
+}
+
+impl SemiTcpDriver {
+    pub fn new(router: Arc<Router<SemiTcpProtocol>>) -> Self {
+        Self {
+            router,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            idle_timeout: None,
+            peer: None,
+            registry: None,
+            #[cfg(all(feature = "unix", unix))]
+            peer_cred: None,
+        }
+    }
+
+    /// Set the max in-flight requests, the reading will be paused until some of them were handled when reached, 1 means handle one by one
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = max_in_flight.max(1);
+        self
+    }
+
+    /// Close the connection if no request received in the timeout, the `$idle` event will be routed before closing
+    pub fn idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    /// Set the peer address of the connection
+    pub fn peer(mut self, peer: SocketAddr) -> Self {
+        self.peer = Some(peer);
+        self
+    }
+
+    /// Set the peer credentials of a unix stream connection, it will be put into the context data with key [PEER_CRED_DATA_NAME],
+    /// e.g. `SemiTcpDriver::new(router).peer_cred(PeerCred::from_stream(&stream)?)`
+    #[cfg(all(feature = "unix", unix))]
+    pub fn peer_cred(mut self, peer_cred: PeerCred) -> Self {
+        self.peer_cred = Some(Arc::new(peer_cred));
+        self
+    }
+
+    /// Register the connection into the registry, so that the server could push messages to it, it will be unregistered after closed.
+    /// The sid head of the requests is not trusted, so the connection should be bound with the sid by the apis or middlewares
+    /// after the session validated, through the [RegisteredConnection] in the context data
+    pub fn registry(mut self, registry: SemiTcpRegistry) -> Self {
+        self.registry = Some(registry);
+        self
+    }
+
+    /// Read and route the frames until the connection closed, then wait for the in-flight requests to be finished.
+    /// The `context_data` will be called for each request to build its context data, the protocol could be prepared here too
+    pub async fn drive<S, F>(&self, mut framed: Framed<S, SemiTcpCodec>, context_data: F)
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+        F: Fn(&mut SemiTcpProtocol) -> HashMap<String, Box<dyn Any + Send>>,
+    {
+        framed.codec_mut().fit_body(self.router.max_body());
+        let connection = Arc::new(ConnectionInfo::new(self.peer, Default::default()));
+        let (registered, pushes) = match &self.registry {
+            Some(registry) => {
+                let (id, receiver) = registry.register(self.peer);
+                (Some(Arc::new(RegisteredConnection::new(id, ConnectionRegistry::clone(registry)))), Some(receiver))
+            },
+            None => (None, None),
+        };
+        let context_data = |rp: &mut SemiTcpProtocol| {
+            let mut data = context_data(rp);
+            data.insert(CONNECTION_DATA_NAME.to_string(), Box::new(connection.clone()));
+            #[cfg(all(feature = "unix", unix))]
+            if let Some(peer_cred) = &self.peer_cred {
+                data.insert(PEER_CRED_DATA_NAME.to_string(), Box::new(peer_cred.clone()));
+            }
+            if let Some(registered) = &registered {
+                data.insert(REGISTRY_DATA_NAME.to_string(), Box::new(registered.clone()));
+            }
+            data
+        };
+        let (sink, mut stream) = framed.split();
+        let sink = Arc::new(Mutex::new(sink));
+        // forward the pushed messages to the connection
+        let forwarder = pushes.map(|mut receiver| {
+            let sink = sink.clone();
+            tokio::spawn(async move {
+                while let Some(pushed) = receiver.recv().await {
+                    let _ = sink.lock().await.send(pushed).await.map_err(|e| error!("{e}"));
+                }
+            })
+        });
+        if let Some(handled) = self.emit(EVENT_CONNECT_PATH, &context_data).await {
+            let _ = sink.lock().await.send(handled).await.map_err(|e| error!("{e}"));
+        }
+        let permits = Arc::new(Semaphore::new(self.max_in_flight));
+        loop {
+            let frame = match self.idle_timeout {
+                Some(idle_timeout) => match tokio::time::timeout(idle_timeout, stream.next()).await {
+                    Ok(frame) => frame,
+                    Err(_) => {
+                        debug!("connection of {:?} idle timeout", self.peer);
+                        if let Some(handled) = self.emit(EVENT_IDLE_PATH, &context_data).await {
+                            let _ = sink.lock().await.send(handled).await.map_err(|e| error!("{e}"));
+                        }
+                        break;
+                    },
+                },
+                None => stream.next().await,
+            };
+            let frame = match frame {
+                Some(Ok(frame)) => frame,
+                Some(Err(err)) => {
+                    error!("{err}");
+                    break;
+                },
+                None => break,
+            };
+            // the event paths are reserved for the lifecycle events, they could not be requested by the clients
+            if frame.path.starts_with(EVENT_PATH_PREFIX) {
+                debug!("rejected the reserved path \"{}\" from {:?}", frame.path, self.peer);
+                let rejected = SemiTcpProtocol::from(frame).err_into(DceErr::openly(404, "Not Found".to_string()));
+                let _ = sink.lock().await.send(rejected).await.map_err(|e| error!("{e}"));
+                continue;
+            }
+            let Ok(permit) = permits.clone().acquire_owned().await else { break };
+            let mut rp = SemiTcpProtocol::from(frame);
+            let context_data = context_data(&mut rp);
+            let (router, sink) = (self.router.clone(), sink.clone());
+            tokio::spawn(async move {
+                if let Some(handled) = SemiTcpProtocol::handle(rp, router, context_data).await {
+                    let _ = sink.lock().await.send(handled).await.map_err(|e| error!("{e}"));
+                }
+                drop(permit);
+            });
+        }
+        // all the permits could be acquired only after the in-flight requests were finished
+        let _ = permits.acquire_many(self.max_in_flight as u32).await;
+        if let Some(forwarder) = forwarder {
+            forwarder.abort();
+        }
+        let _ = sink.lock().await.close().await;
+        // the connection was closed, so the response will be dropped
+        self.emit(EVENT_DISCONNECT_PATH, &context_data).await;
+        if let Some(registered) = registered {
+            registered.registry().unregister(registered.id());
+        }
+    }
+
+    async fn emit<F>(&self, path: &str, context_data: F) -> Option<Frame>
+    where F: Fn(&mut SemiTcpProtocol) -> HashMap<String, Box<dyn Any + Send>>,
+    {
+        if ! self.router.routable(path) {
+            return None;
+        }
+        let mut rp = SemiTcpProtocol::from(Frame::new(path));
+        let mut context_data = context_data(&mut rp);
+        context_data.insert(EVENT_DATA_NAME.to_string(), Box::new(path.to_string()));
+        SemiTcpProtocol::handle(rp, self.router.clone(), context_data).await
+    }
+}
@@ -1 +1,6 @@
-pub mod protocol;
\ No newline at end of file
+pub mod protocol;
+pub mod codec;
+pub mod driver;
+pub mod registry;
+pub mod client;
+pub mod udp;
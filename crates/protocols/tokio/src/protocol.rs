@@ -7,16 +7,17 @@ use std::sync::Arc;
 use async_trait::async_trait;
 use futures_util::SinkExt;
 use futures_util::stream::SplitSink;
-use tokio::net::TcpStream;
-use tokio_util::codec::{BytesCodec, Framed};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_util::codec::Framed;
 use tokio_util::udp::UdpFramed;
 use bytes::{BufMut, BytesMut};
-use log::{error, warn};
-use dce_router::protocol::{HEAD_ID_NAME, HEAD_PATH_NAME, Meta, RoutableProtocol};
+use log::error;
+use dce_router::protocol::{HEAD_CODE_NAME, HEAD_ID_NAME, HEAD_PATH_NAME, Meta, RoutableProtocol};
 use dce_router::request::{Request, Response};
 use dce_router::router::Router;
 use dce_router::serializer::Serialized;
-use dce_util::mixed::{DceErr, DceResult};
+use dce_util::mixed::{DceErr, DceResult, SERVICE_UNAVAILABLE};
+use crate::codec::{Frame, SemiTcpCodec};
 
 
 pub type SemiTcpRaw<'a> = Request<'a, SemiTcpProtocol, (), ()>;
@@ -25,21 +26,17 @@ pub type SemiTcpSame<'a, Dto> = Request<'a, SemiTcpProtocol, Dto, Dto>;
 pub type SemiTcp<'a, ReqDto, RespDto> = Request<'a, SemiTcpProtocol, ReqDto, RespDto>;
 
 
-const ID_PATH_SEPARATOR: char = ';';
-const HEAD_BODY_SEPARATOR: &str = ">BODY>>>";
-
-
 #[derive(Debug)]
 pub struct SemiTcpProtocol {
-    meta: Meta<BytesMut, BytesMut>,
-    body_index: usize,
+    meta: Meta<Frame, Frame>,
 }
 
 impl SemiTcpProtocol {
-    pub async fn route(
+    /// Route the message and send back the response, the stream could be a tcp stream or any wrapped stream such as a tls stream
+    pub async fn route<S: AsyncRead + AsyncWrite + Unpin>(
         self,
         router: Arc<Router<Self>>,
-        stream: &mut SplitSink<Framed<TcpStream, BytesCodec>, BytesMut>,
+        stream: &mut SplitSink<Framed<S, SemiTcpCodec>, Frame>,
         context_data: HashMap<String, Box<dyn Any + Send>>,
     ) {
         if let Some(handled) = Self::handle(self, router, context_data).await {
@@ -50,7 +47,7 @@ impl SemiTcpProtocol {
     pub async fn udp_route(
         self,
         router: Arc<Router<Self>>,
-        stream: &mut SplitSink<UdpFramed<BytesCodec>, (BytesMut, SocketAddr)>,
+        stream: &mut SplitSink<UdpFramed<SemiTcpCodec>, (Frame, SocketAddr)>,
         addr: SocketAddr,
         context_data: HashMap<String, Box<dyn Any + Send>>,
     ) {
@@ -60,55 +57,44 @@ impl SemiTcpProtocol {
     }
 }
 
+impl From<Frame> for SemiTcpProtocol {
+    fn from(mut value: Frame) -> Self {
+        let mut heads = std::mem::take(&mut value.heads);
+        if let Some(id) = value.id.take() {
+            heads.insert(HEAD_ID_NAME.to_string(), id);
+        }
+        heads.insert(HEAD_PATH_NAME.to_string(), std::mem::take(&mut value.path));
+        Self { meta: Meta::new(value, heads) }
+    }
+}
+
+/// Parse the legacy text message
 impl From<BytesMut> for SemiTcpProtocol {
     fn from(value: BytesMut) -> Self {
-        let mut body_index = 0;
-        let mut heads = HashMap::new();
-        let mut path = String::from_utf8(value.to_vec()).map_err(|e| warn!("{e}")).map_or(Default::default(), |v| v.trim().to_string());
-        if let Some(index) = path.find(HEAD_BODY_SEPARATOR) {
-            let mut head_lines: Vec<_> = path[0..index].lines().map(ToString::to_string).collect();
-            path = head_lines.remove(0);
-            if let Some((tmp_id, tmp_path)) = path.split_once(ID_PATH_SEPARATOR) {
-                heads.insert(HEAD_ID_NAME.to_string(), tmp_id.to_string());
-                path = tmp_path.to_string();
-            }
-            heads.extend(head_lines.iter().map(|line| line.split_once(':')
-                .map_or_else(|| (line.to_string(), "".to_string()), |(k, v)| (k.to_string(), v.to_string()))));
-            body_index = index + HEAD_BODY_SEPARATOR.len();
-        }
-        heads.insert(HEAD_PATH_NAME.to_string(), path);
-        Self { meta: Meta::new(value, heads), body_index, }
+        Self::from(Frame::from_text(value))
     }
 }
 
-impl Into<BytesMut> for SemiTcpProtocol {
-    fn into(mut self) -> BytesMut {
+impl Into<Frame> for SemiTcpProtocol {
+    fn into(mut self) -> Frame {
         let resp = self.meta.resp_mut().take();
-        let (id, path) = (self.id(), self.path());
         match resp {
             Some(Response::Raw(resp)) => resp,
             resp => {
-                let mut text = BytesMut::new();
-                if let Some(id) = id {
-                    text.put_slice(id.as_bytes());
-                    text.put_slice(ID_PATH_SEPARATOR.to_string().as_bytes());
-                }
-                text.put_slice(path.as_bytes());
-                for (k, v) in self.resp_heads() {
-                    text.put_slice(format!("\n{k}:{v}").as_bytes())
-                }
-                text.put_slice(format!("\n{}\n", HEAD_BODY_SEPARATOR).as_bytes());
+                let mut frame = Frame::new(self.path());
+                frame.id = self.id().map(ToString::to_string);
+                frame.heads = std::mem::take(self.resp_heads_mut());
                 if let Some(Response::Serialized(sd)) = resp {
-                    text.put(self.pack_resp(sd));
+                    frame.body = self.pack_resp(sd).body;
                 }
-                text
+                frame
             }
-        }        
+        }
     }
 }
 
 impl Deref for SemiTcpProtocol {
-    type Target = Meta<BytesMut, BytesMut>;
+    type Target = Meta<Frame, Frame>;
 
     fn deref(&self) -> &Self::Target {
         &self.meta
@@ -123,20 +109,33 @@ impl DerefMut for SemiTcpProtocol {
 
 #[async_trait]
 impl RoutableProtocol for SemiTcpProtocol {
-    type Req = BytesMut;
+    type Req = Frame;
     type Resp = Self::Req;
 
     async fn body(&mut self) -> DceResult<Serialized> {
-        String::from_utf8(self.req_mut().take().ok_or_else(|| DceErr::closed0("Empty request"))?.to_vec())
-            .map(|t| Serialized::String(t[self.body_index ..].to_string())).map_err(DceErr::closed0)
+        // the frame was already read, so only the size limit need to check
+        self.check_body_size(self.req()?.body.len())?;
+        let frame = self.req_mut().take().ok_or_else(|| DceErr::closed0("Empty request"))?;
+        Ok(Serialized::Bytes(frame.body.freeze()))
     }
 
     fn pack_resp(&self, serialized: Serialized) -> Self::Resp {
-        let mut text = BytesMut::new();
+        let mut body = BytesMut::new();
         match serialized {
-            Serialized::String(str) => text.put_slice(str.as_bytes()),
-            Serialized::Bytes(bytes) => text.put_slice(bytes.as_ref()),
+            Serialized::String(str) => body.put_slice(str.as_bytes()),
+            Serialized::Bytes(bytes) => body.put_slice(bytes.as_ref()),
         }
-        text
+        Frame::new(self.path()).body(body)
+    }
+
+    /// Keep the id and path in the error responses, and tell the error code with the `Code` head
+    fn err_into(mut self, err: DceErr) -> Self::Resp {
+        let code = match &err {
+            DceErr::Openly(e) => e.code,
+            DceErr::Closed(_) => SERVICE_UNAVAILABLE,
+        };
+        self.resp_heads_mut().insert(HEAD_CODE_NAME.to_string(), code.to_string());
+        *self.resp_mut() = Some(Response::Serialized(Serialized::String(err.to_responsible())));
+        self.into()
     }
 }
@@ -0,0 +1,45 @@
+use std::ops::Deref;
+use bytes::BytesMut;
+use dce_router::serializer::Serialized;
+use dce_util::registry::{ConnectionRegistry, PushTarget};
+use crate::codec::Frame;
+
+
+/// The registry of the live semi tcp connections, set it to the [SemiTcpDriver](crate::driver::SemiTcpDriver) to register the connections,
+/// then the server could push messages to the clients by sid, uid or room. The [RegisteredConnection](dce_util::registry::RegisteredConnection)
+/// can be got from the context data with key [REGISTRY_DATA_NAME](dce_util::registry::REGISTRY_DATA_NAME) to bind the uid or join rooms
+#[derive(Debug, Clone, Default)]
+pub struct SemiTcpRegistry(ConnectionRegistry<Frame>);
+
+impl SemiTcpRegistry {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Push a message without id to the target connections, returns the count of the connections which the message was queued to
+    pub fn push(&self, target: PushTarget, path: &str, body: Serialized) -> usize {
+        self.0.send(&target, self.pack(path, body))
+    }
+
+    /// Pack a frame without id for pushing, such as to build the frames forwarded from other cluster nodes
+    pub fn pack(&self, path: &str, body: Serialized) -> Frame {
+        let body = match body {
+            Serialized::String(str) => BytesMut::from(str.as_bytes()),
+            Serialized::Bytes(bytes) => BytesMut::from(bytes.as_ref()),
+        };
+        Frame::new(path).body(body)
+    }
+
+    /// Push a message to the connections joined the room
+    pub fn broadcast(&self, room: &str, path: &str, body: Serialized) -> usize {
+        self.push(PushTarget::Room(room.to_string()), path, body)
+    }
+}
+
+impl Deref for SemiTcpRegistry {
+    type Target = ConnectionRegistry<Frame>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
@@ -0,0 +1,273 @@
+use std::any::Any;
+use std::collections::{HashMap, VecDeque};
+use std::io::ErrorKind;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use bytes::{Bytes, BytesMut};
+use log::{debug, error, warn};
+use tokio::net::UdpSocket;
+use tokio::sync::Semaphore;
+use tokio_util::codec::{Decoder, Encoder};
+use dce_router::protocol::{ConnectionInfo, CODE_PAYLOAD_TOO_LARGE, CONNECTION_DATA_NAME, EVENT_PATH_PREFIX, HEAD_CODE_NAME, RoutableProtocol};
+#[cfg(feature = "session")]
+use dce_router::protocol::HEAD_SID_NAME;
+use dce_router::router::Router;
+use crate::codec::{Frame, SemiTcpCodec};
+use crate::driver::DEFAULT_MAX_IN_FLIGHT;
+use crate::protocol::SemiTcpProtocol;
+
+/// Default max datagram size, the ethernet mtu 1500 minus the ip and udp headers, the larger could be fragmented or dropped on the way
+pub const DEFAULT_MAX_DATAGRAM_SIZE: usize = 1472;
+/// Default window of the request dedup
+pub const DEFAULT_DEDUP_WINDOW: Duration = Duration::from_secs(10);
+/// Default max count of the dedup records
+pub const DEFAULT_MAX_DEDUP_ENTRIES: usize = 4096;
+/// The expired dedup records and peer sessions will be purged at most once in this interval
+const PURGE_INTERVAL: Duration = Duration::from_secs(1);
+
+
+/// Drive a udp socket, each datagram should contain a whole frame, the requests will be routed concurrently.
+/// - The datagrams larger than the max datagram size will be dropped, and the too large responses will be replaced with a 413 error.
+/// - The requests with id are deduplicated by (peer, id) in the dedup window, the retransmitted will not be routed again,
+///   but the cached response will be resent if the origin was handled. The oldest records will be evicted when the max entries reached.
+/// - At most max in-flight requests will be routed concurrently, the receiving will be paused until some of them were handled.
+/// - With the `session` feature, the sid could be bound to the peer address, so the client only need to carry it once
+#[derive(Debug, Clone)]
+pub struct SemiUdpDriver {
+    router: Arc<Router<SemiTcpProtocol>>,
+    codec: SemiTcpCodec,
+    max_datagram_size: usize,
+    max_in_flight: usize,
+    dedup_window: Duration,
+    max_dedup_entries: usize,
+    #[cfg(feature = "session")]
+    peer_session_ttl: Option<Duration>,
+}
+
+#[derive(Default)]
+struct State {
+    /// None means the request is still in flight
+    responses: HashMap<(SocketAddr, String), (Instant, Option<Bytes>)>,
+    /// The keys of the responses in the inserting order, to purge or evict the oldest
+    order: VecDeque<(SocketAddr, String)>,
+    #[cfg(feature = "session")]
+    sessions: HashMap<SocketAddr, (String, Instant)>,
+    purged_at: Option<Instant>,
+}
+
+impl SemiUdpDriver {
+    pub fn new(router: Arc<Router<SemiTcpProtocol>>) -> Self {
+        Self {
+            router,
+            codec: SemiTcpCodec::binary(),
+            max_datagram_size: DEFAULT_MAX_DATAGRAM_SIZE,
+            max_in_flight: DEFAULT_MAX_IN_FLIGHT,
+            dedup_window: DEFAULT_DEDUP_WINDOW,
+            max_dedup_entries: DEFAULT_MAX_DEDUP_ENTRIES,
+            #[cfg(feature = "session")]
+            peer_session_ttl: None,
+        }
+    }
+
+    /// Set the codec, such as a text mode codec for the legacy clients
+    pub fn codec(mut self, codec: SemiTcpCodec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    pub fn max_datagram_size(mut self, max_datagram_size: usize) -> Self {
+        self.max_datagram_size = max_datagram_size;
+        self
+    }
+
+    /// Set the max in-flight requests, the datagrams will wait in the socket buffer when reached, and be dropped by the system if it was full
+    pub fn max_in_flight(mut self, max_in_flight: usize) -> Self {
+        self.max_in_flight = max_in_flight.max(1);
+        self
+    }
+
+    /// Set the dedup window, zero to disable the dedup
+    pub fn dedup_window(mut self, dedup_window: Duration) -> Self {
+        self.dedup_window = dedup_window;
+        self
+    }
+
+    /// Set the max dedup records, the oldest will be evicted when reached, so the retransmitted of them could be routed again
+    pub fn max_dedup_entries(mut self, max_dedup_entries: usize) -> Self {
+        self.max_dedup_entries = max_dedup_entries.max(1);
+        self
+    }
+
+    /// Bind the sid to the peer address when a response carried it, such as after login or session renewed, then the later requests
+    /// of the peer without sid will take it, until no request received from the peer in the ttl, and a cleared sid will unbind it.
+    /// The sids carried by requests will not be bound, because the peer address of udp could be spoofed
+    #[cfg(feature = "session")]
+    pub fn peer_session(mut self, ttl: Duration) -> Self {
+        self.peer_session_ttl = Some(ttl);
+        self
+    }
+
+    /// Receive and route the datagrams until the socket failed.
+    /// The `context_data` will be called for each request to build its context data, the protocol could be prepared here too
+    pub async fn drive<F>(&self, socket: UdpSocket, context_data: F)
+    where F: Fn(&mut SemiTcpProtocol) -> HashMap<String, Box<dyn Any + Send>>,
+    {
+        let socket = Arc::new(socket);
+        let state = Arc::new(Mutex::new(State::default()));
+        let permits = Arc::new(Semaphore::new(self.max_in_flight));
+        // one more byte to tell whether the datagram was truncated
+        let mut buf = vec![0; self.max_datagram_size + 1];
+        loop {
+            let (len, peer) = match socket.recv_from(&mut buf).await {
+                Ok(received) => received,
+                // the icmp port unreachable of the previous sending could be reported here on some platforms
+                Err(err) if err.kind() == ErrorKind::ConnectionReset => continue,
+                Err(err) => {
+                    error!("{err}");
+                    break;
+                },
+            };
+            if len > self.max_datagram_size {
+                warn!("dropped an oversized datagram from {peer}");
+                continue;
+            }
+            #[cfg_attr(not(feature = "session"), allow(unused_mut))]
+            let mut frame = match self.codec.clone().decode(&mut BytesMut::from(&buf[..len])) {
+                Ok(Some(frame)) => frame,
+                Ok(None) => {
+                    warn!("dropped a truncated datagram from {peer}");
+                    continue;
+                },
+                Err(err) => {
+                    warn!("dropped an invalid datagram from {peer}: {err}");
+                    continue;
+                },
+            };
+            // no lifecycle events on udp, but the routers could be shared with the connection drivers
+            if frame.path.starts_with(EVENT_PATH_PREFIX) {
+                debug!("dropped the reserved path \"{}\" from {peer}", frame.path);
+                continue;
+            }
+            let key = frame.id.clone().filter(|_| ! self.dedup_window.is_zero()).map(|id| (peer, id));
+            if let Ok(mut state) = state.lock() {
+                self.purge(&mut state);
+                if let Some(key) = &key {
+                    match state.responses.get(key) {
+                        Some((_, Some(response))) => {
+                            debug!("resend the cached response of request {} from {peer}", key.1);
+                            if ! response.is_empty() {
+                                let (socket, response) = (socket.clone(), response.clone());
+                                tokio::spawn(async move { let _ = socket.send_to(&response, peer).await.map_err(|e| error!("{e}")); });
+                            }
+                            continue;
+                        },
+                        Some((_, None)) => continue,
+                        None => self.record(&mut state, key.clone()),
+                    }
+                }
+                #[cfg(feature = "session")]
+                self.bind_session(&mut state, peer, &mut frame);
+            }
+            let Ok(permit) = permits.clone().acquire_owned().await else { break };
+            let mut rp = SemiTcpProtocol::from(frame);
+            let mut context_data = context_data(&mut rp);
+            context_data.insert(CONNECTION_DATA_NAME.to_string(), Box::new(Arc::new(ConnectionInfo::new(Some(peer), Default::default()))));
+            let (driver, socket, state) = (self.clone(), socket.clone(), state.clone());
+            tokio::spawn(async move {
+                let handled = SemiTcpProtocol::handle(rp, driver.router.clone(), context_data).await;
+                #[cfg(feature = "session")]
+                if let (Some(handled), Ok(mut state)) = (&handled, state.lock()) {
+                    driver.apply_session(&mut state, peer, handled);
+                }
+                let response = match handled.map(|frame| driver.encode(frame)) {
+                    Some(Ok(response)) => response,
+                    Some(Err(err)) => {
+                        error!("{err}");
+                        Bytes::new()
+                    },
+                    None => Bytes::new(),
+                };
+                if let (Some(key), Ok(mut state)) = (key, state.lock()) {
+                    if let Some((_, cached)) = state.responses.get_mut(&key) {
+                        *cached = Some(response.clone());
+                    }
+                }
+                if ! response.is_empty() {
+                    let _ = socket.send_to(&response, peer).await.map_err(|e| error!("{e}"));
+                }
+                drop(permit);
+            });
+        }
+    }
+
+    fn encode(&self, frame: Frame) -> std::io::Result<Bytes> {
+        let (id, path) = (frame.id.clone(), frame.path.clone());
+        let mut dst = BytesMut::new();
+        self.codec.clone().encode(frame, &mut dst)?;
+        if dst.len() > self.max_datagram_size {
+            warn!("response of \"{path}\" was too large for a datagram");
+            let mut frame = Frame::new(path).head(HEAD_CODE_NAME, CODE_PAYLOAD_TOO_LARGE).body(format!("{CODE_PAYLOAD_TOO_LARGE}: Response Too Large").as_str());
+            frame.id = id;
+            dst.clear();
+            self.codec.clone().encode(frame, &mut dst)?;
+        }
+        Ok(dst.freeze())
+    }
+
+    fn purge(&self, state: &mut State) {
+        if state.purged_at.is_some_and(|at| at.elapsed() < PURGE_INTERVAL) {
+            return;
+        }
+        state.purged_at = Some(Instant::now());
+        // the records were inserted in time order, so the expired are at the front
+        while let Some((at, _)) = state.order.front().and_then(|key| state.responses.get(key)) {
+            if at.elapsed() < self.dedup_window {
+                break;
+            }
+            if let Some(key) = state.order.pop_front() {
+                state.responses.remove(&key);
+            }
+        }
+        #[cfg(feature = "session")]
+        if let Some(ttl) = self.peer_session_ttl {
+            state.sessions.retain(|_, (_, at)| at.elapsed() < ttl);
+        }
+    }
+
+    /// Record an in-flight request, evict the oldest records if the max entries reached
+    fn record(&self, state: &mut State, key: (SocketAddr, String)) {
+        while state.responses.len() >= self.max_dedup_entries {
+            let Some(oldest) = state.order.pop_front() else { break };
+            state.responses.remove(&oldest);
+        }
+        state.responses.insert(key.clone(), (Instant::now(), None));
+        state.order.push_back(key);
+    }
+
+    #[cfg(feature = "session")]
+    fn bind_session(&self, state: &mut State, peer: SocketAddr, frame: &mut Frame) {
+        let Some(ttl) = self.peer_session_ttl else { return };
+        // the source address could be spoofed, so the peer will be bound only with the sid responded, see `apply_session`
+        if frame.heads.contains_key(HEAD_SID_NAME) {
+            return;
+        }
+        if let Some((sid, at)) = state.sessions.get_mut(&peer).filter(|(_, at)| at.elapsed() < ttl) {
+            frame.heads.insert(HEAD_SID_NAME.to_string(), sid.clone());
+            *at = Instant::now();
+        }
+    }
+
+    #[cfg(feature = "session")]
+    fn apply_session(&self, state: &mut State, peer: SocketAddr, handled: &Frame) {
+        if self.peer_session_ttl.is_none() {
+            return;
+        }
+        match handled.heads.get(HEAD_SID_NAME) {
+            // an empty sid means the session was dropped, such as after logout
+            Some(sid) if sid.is_empty() => { state.sessions.remove(&peer); },
+            Some(sid) => { state.sessions.insert(peer, (sid.clone(), Instant::now())); },
+            None => {},
+        }
+    }
+}
@@ -0,0 +1,94 @@
+use std::time::Duration;
+use tokio::io::duplex;
+use tokio::sync::mpsc;
+use tokio::test;
+use tokio_util::codec::Decoder;
+use dce_macro::{api, openly_err};
+use dce_router::protocol::RoutableProtocol;
+use dce_router::router::Router;
+use dce_router::serializer::{JsonSerializer, Serialized};
+use dce_tokio::client::SemiTcpClient;
+use dce_tokio::codec::{Frame, SemiTcpCodec};
+use dce_tokio::driver::SemiTcpDriver;
+use dce_tokio::protocol::{SemiTcp, SemiTcpRaw};
+use dce_tokio::registry::SemiTcpRegistry;
+use dce_util::mixed::DceErr;
+use dce_util::registry::PushTarget;
+
+#[api]
+async fn echo(mut req: SemiTcpRaw) {
+    let body = req.rp_mut().body().await?;
+    req.pack(body)
+}
+
+#[api]
+async fn denied(_req: SemiTcpRaw) {
+    Err(openly_err!(403, "Forbidden"))
+}
+
+#[api]
+async fn silent(req: SemiTcpRaw) {
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    req.end(None)
+}
+
+#[api(serializer = JsonSerializer{}, deserializer = JsonSerializer{})]
+async fn sum(mut req: SemiTcp<Vec<u32>, u32>) {
+    let numbers: Vec<u32> = req.req().await?;
+    req.resp(numbers.iter().sum::<u32>())
+}
+
+static PUSHED: std::sync::OnceLock<mpsc::UnboundedSender<String>> = std::sync::OnceLock::new();
+
+#[api("notice")]
+async fn notice(mut req: SemiTcpRaw) {
+    let body = req.rp_mut().body().await?;
+    let _ = PUSHED.get().unwrap().send(body.to_string());
+    req.end(None)
+}
+
+#[test]
+async fn request_and_push() {
+    let router = Router::new().unwrap().push(echo).push(denied).push(silent).ready().unwrap();
+    let registry = SemiTcpRegistry::new();
+    let (client, server) = duplex(1024);
+    let driver = SemiTcpDriver::new(router.clone()).registry(registry.clone());
+    tokio::spawn(async move { driver.drive(SemiTcpCodec::binary().framed(server), |_| Default::default()).await });
+
+    let (sender, mut pushed) = mpsc::unbounded_channel();
+    PUSHED.set(sender).unwrap();
+    let push_router = Router::new().unwrap().push(notice).ready().unwrap();
+    let client = SemiTcpClient::new(SemiTcpCodec::binary().framed(client), Some(push_router.clone())).timeout(Duration::from_millis(200));
+
+    let (a, b) = tokio::join!(client.request(Frame::new("echo").body("a")), client.request(Frame::new("echo").body("b")));
+    assert_eq!((&a.unwrap().body[..], &b.unwrap().body[..]), (&b"a"[..], &b"b"[..]));
+    match client.request(Frame::new("denied")).await {
+        Err(DceErr::Openly(e)) => assert_eq!((e.code, e.message.as_str()), (403, "Forbidden")),
+        result => panic!("unexpected {result:?}"),
+    }
+    assert!(client.request(Frame::new("silent")).await.is_err());
+
+    assert_eq!(registry.push(PushTarget::All, "notice", Serialized::String("hi".to_string())), 1);
+    assert_eq!(pushed.recv().await.unwrap(), "hi");
+}
+
+#[test]
+async fn binary_body() {
+    let router = Router::new().unwrap().push(echo).push(sum).ready().unwrap();
+    for binary in [true, false] {
+        let codec = move || if binary { SemiTcpCodec::binary() } else { SemiTcpCodec::text() };
+        let (client, server) = duplex(1024);
+        let driver = SemiTcpDriver::new(router.clone());
+        tokio::spawn(async move { driver.drive(codec().framed(server), |_| Default::default()).await });
+        let client = SemiTcpClient::new(codec().framed(client), None).timeout(Duration::from_millis(200));
+
+        // the body was sliced after the heads as is, not decoded as utf-8
+        let body: &[u8] = &[0, 0xff, b'\n', 0xfe, b'\n', b'\n', 0x80];
+        let resp = client.request(Frame::new("echo").head("Note", "x").body(body)).await.unwrap();
+        assert_eq!(&resp.body[..], body, "binary: {binary}");
+        assert_eq!(&client.request(Frame::new("echo").body("héllo")).await.unwrap().body[..], "héllo".as_bytes());
+        // decoded only when the deserializer asks
+        assert_eq!(&client.request(Frame::new("sum").body("[1, 2, 3]")).await.unwrap().body[..], b"6");
+        assert!(client.request(Frame::new("sum").body(&[0xffu8][..])).await.is_err());
+    }
+}
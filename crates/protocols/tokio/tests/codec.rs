@@ -0,0 +1,60 @@
+use bytes::{BufMut, BytesMut};
+use tokio_util::codec::{Decoder, Encoder};
+use dce_tokio::codec::{Frame, SemiTcpCodec, FRAME_VERSION};
+
+#[test]
+fn split_and_sticky() {
+    let mut codec = SemiTcpCodec::binary();
+    let first = Frame::new("echo/first").id(1).head("Api-Version", "2").body("contains >BODY>>> inside");
+    let second = Frame::new("hello").body(&b"\x00\xff binary"[..]);
+    let mut buf = BytesMut::new();
+    codec.encode(first.clone(), &mut buf).unwrap();
+    codec.encode(second.clone(), &mut buf).unwrap();
+    assert_eq!(buf[0], FRAME_VERSION);
+
+    // feed byte by byte, the frames should only be decoded when complete
+    let mut src = BytesMut::new();
+    let mut decoded = vec![];
+    for byte in buf {
+        src.put_u8(byte);
+        if let Some(frame) = codec.decode(&mut src).unwrap() {
+            decoded.push(frame);
+        }
+    }
+    assert_eq!(decoded, vec![first, second]);
+    assert!(src.is_empty());
+}
+
+#[test]
+fn max_frame_size() {
+    let mut codec = SemiTcpCodec::binary().max_frame_size(16);
+    let mut buf = BytesMut::new();
+    assert!(codec.encode(Frame::new("hello").body("a body longer than the limit"), &mut buf).is_err());
+
+    SemiTcpCodec::binary().encode(Frame::new("hello").body("a body longer than the limit"), &mut buf).unwrap();
+    assert!(codec.decode(&mut buf).is_err());
+}
+
+#[test]
+fn text_mode() {
+    let mut codec = SemiTcpCodec::text();
+    let mut src = BytesMut::from("0;echo/me\nk:v\n>BODY>>>\nbody data\n");
+    let frame = codec.decode(&mut src).unwrap().unwrap();
+    assert_eq!(frame, Frame::new("echo/me").id(0).head("k", "v").body("body data"));
+
+    let mut dst = BytesMut::new();
+    codec.encode(Frame::new("hello").id(0).body("hello world"), &mut dst).unwrap();
+    assert_eq!(&dst[..], b"0;hello\n>BODY>>>\nhello world");
+}
+
+#[test]
+fn text_mode_invalid_utf8() {
+    let mut codec = SemiTcpCodec::text();
+    let mut src = BytesMut::from(&b"\xff\xff\xff\xff>BODY>>>"[..]);
+    let frame = codec.decode(&mut src).unwrap().unwrap();
+    assert_eq!((frame.path.as_str(), &frame.body[..]), ("\u{FFFD}\u{FFFD}\u{FFFD}\u{FFFD}", &b""[..]));
+
+    let mut src = BytesMut::from(&b"echo\n\xfe:v\n>BODY>>>\n\xff\x00 raw"[..]);
+    let frame = codec.decode(&mut src).unwrap().unwrap();
+    assert_eq!(frame, Frame::new("echo").head("\u{FFFD}", "v").body(&b"\xff\x00 raw"[..]));
+}
@@ -0,0 +1,128 @@
+use std::sync::Arc;
+use std::time::Duration;
+use futures_util::{SinkExt, StreamExt};
+use tokio::io::duplex;
+use tokio::test;
+use tokio_util::codec::Decoder;
+use dce_macro::api;
+use dce_router::protocol::{ConnectionInfo, CONNECTION_DATA_NAME, EVENT_DATA_NAME, HEAD_CODE_NAME, MAX_BODY_EXTRA_NAME, MAX_HEAD_SIZE, RoutableProtocol};
+use dce_router::router::Router;
+use dce_router::serializer::Serialized;
+use dce_tokio::codec::{Frame, SemiTcpCodec};
+use dce_tokio::driver::SemiTcpDriver;
+use dce_tokio::protocol::SemiTcpRaw;
+
+#[api]
+async fn slow(req: SemiTcpRaw) {
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    req.pack(Serialized::String("slow".to_string()))
+}
+
+#[api]
+async fn fast(req: SemiTcpRaw) {
+    assert!(req.get_as::<String>(EVENT_DATA_NAME).is_err());
+    req.pack(Serialized::String("fast".to_string()))
+}
+
+#[api]
+async fn upload(mut req: SemiTcpRaw) {
+    let size = match req.rp_mut().body().await? {
+        Serialized::Bytes(bytes) => bytes.len(),
+        Serialized::String(str) => str.len(),
+    };
+    req.pack(Serialized::String(size.to_string()))
+}
+
+#[api("$connect")]
+async fn connect(req: SemiTcpRaw) {
+    let peer = req.get_as::<Arc<ConnectionInfo>>(CONNECTION_DATA_NAME)?.peer();
+    assert_eq!(req.get_as::<String>(EVENT_DATA_NAME)?, "$connect");
+    req.pack(Serialized::String(format!("welcome {}", peer.map_or_else(String::new, |p| p.to_string()))))
+}
+
+#[api("$idle")]
+async fn idle(req: SemiTcpRaw) {
+    req.pack(Serialized::String("bye".to_string()))
+}
+
+#[test]
+async fn out_of_order() {
+    let router = Router::new().unwrap().push(slow).push(fast).ready().unwrap();
+    let (client, server) = duplex(1024);
+    let driver = SemiTcpDriver::new(router.clone());
+    tokio::spawn(async move { driver.drive(SemiTcpCodec::binary().framed(server), |_| Default::default()).await });
+
+    let (mut sink, mut stream) = SemiTcpCodec::binary().framed(client).split();
+    sink.send(Frame::new("slow").id(1)).await.unwrap();
+    sink.send(Frame::new("fast").id(2)).await.unwrap();
+    let first = stream.next().await.unwrap().unwrap();
+    let second = stream.next().await.unwrap().unwrap();
+    assert_eq!((first.id.as_deref(), &first.body[..]), (Some("2"), &b"fast"[..]));
+    assert_eq!((second.id.as_deref(), &second.body[..]), (Some("1"), &b"slow"[..]));
+}
+
+#[test]
+async fn one_by_one() {
+    let router = Router::new().unwrap().push(slow).push(fast).ready().unwrap();
+    let (client, server) = duplex(1024);
+    let driver = SemiTcpDriver::new(router.clone()).max_in_flight(1);
+    tokio::spawn(async move { driver.drive(SemiTcpCodec::binary().framed(server), |_| Default::default()).await });
+
+    let (mut sink, mut stream) = SemiTcpCodec::binary().framed(client).split();
+    sink.send(Frame::new("slow").id(1)).await.unwrap();
+    sink.send(Frame::new("fast").id(2)).await.unwrap();
+    assert_eq!(stream.next().await.unwrap().unwrap().id.as_deref(), Some("1"));
+    assert_eq!(stream.next().await.unwrap().unwrap().id.as_deref(), Some("2"));
+}
+
+#[test]
+async fn lifecycle() {
+    let router = Router::new().unwrap().push(connect).push(idle).push(fast).ready().unwrap();
+    let (client, server) = duplex(1024);
+    let driver = SemiTcpDriver::new(router.clone()).peer("127.0.0.1:2048".parse().unwrap()).idle_timeout(Duration::from_millis(200));
+    tokio::spawn(async move { driver.drive(SemiTcpCodec::binary().framed(server), |_| Default::default()).await });
+
+    let (mut sink, mut stream) = SemiTcpCodec::binary().framed(client).split();
+    let welcome = stream.next().await.unwrap().unwrap();
+    assert_eq!((welcome.path.as_str(), &welcome.body[..]), ("$connect", &b"welcome 127.0.0.1:2048"[..]));
+    sink.send(Frame::new("fast").id(1)).await.unwrap();
+    assert_eq!(&stream.next().await.unwrap().unwrap().body[..], b"fast");
+    let bye = stream.next().await.unwrap().unwrap();
+    assert_eq!((bye.path.as_str(), &bye.body[..]), ("$idle", &b"bye"[..]));
+    // closed after idle
+    assert!(stream.next().await.is_none());
+}
+
+#[test]
+async fn reserved_paths() {
+    let router = Router::new().unwrap().push(connect).push(idle).push(fast).ready().unwrap();
+    let (client, server) = duplex(1024);
+    let driver = SemiTcpDriver::new(router.clone());
+    tokio::spawn(async move { driver.drive(SemiTcpCodec::binary().framed(server), |_| Default::default()).await });
+
+    let (mut sink, mut stream) = SemiTcpCodec::binary().framed(client).split();
+    assert_eq!(stream.next().await.unwrap().unwrap().path, "$connect");
+    // the clients could not fake the lifecycle events
+    sink.send(Frame::new("$idle").id(1)).await.unwrap();
+    let rejected = stream.next().await.unwrap().unwrap();
+    assert_eq!((rejected.id.as_deref(), rejected.heads.get(HEAD_CODE_NAME).map(String::as_str)), (Some("1"), Some("404")));
+    sink.send(Frame::new("fast").id(2)).await.unwrap();
+    assert_eq!(&stream.next().await.unwrap().unwrap().body[..], b"fast");
+}
+
+#[test]
+async fn body_limits() {
+    let router = Router::new().unwrap().set_extra(MAX_BODY_EXTRA_NAME, Box::new(16usize)).push(upload).ready().unwrap();
+    let (client, server) = duplex(1024);
+    let driver = SemiTcpDriver::new(router.clone());
+    tokio::spawn(async move { driver.drive(SemiTcpCodec::binary().framed(server), |_| Default::default()).await });
+
+    let (mut sink, mut stream) = SemiTcpCodec::binary().framed(client).split();
+    sink.send(Frame::new("upload").id(1).body(&[0u8; 16][..])).await.unwrap();
+    assert_eq!(&stream.next().await.unwrap().unwrap().body[..], b"16");
+    sink.send(Frame::new("upload").id(2).body(&[0u8; 17][..])).await.unwrap();
+    assert_eq!(stream.next().await.unwrap().unwrap().heads.get(HEAD_CODE_NAME).map(String::as_str), Some("413"));
+    // the frame exceeded the max body and head was rejected before buffered, and the connection was closed
+    let _ = sink.send(Frame::new("upload").id(3).body(&vec![0u8; 17 + MAX_HEAD_SIZE][..])).await;
+    assert!(matches!(stream.next().await, None | Some(Err(_))));
+}
@@ -0,0 +1,120 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use bytes::BytesMut;
+use tokio::net::UdpSocket;
+use tokio::test;
+use tokio_util::codec::{Decoder, Encoder};
+use dce_macro::api;
+use dce_router::router::Router;
+use dce_router::serializer::Serialized;
+use dce_tokio::codec::{Frame, SemiTcpCodec};
+use dce_tokio::protocol::{SemiTcpProtocol, SemiTcpRaw};
+use dce_tokio::udp::SemiUdpDriver;
+
+static HANDLED: AtomicUsize = AtomicUsize::new(0);
+static TICKED: AtomicUsize = AtomicUsize::new(0);
+
+#[api]
+async fn count(req: SemiTcpRaw) {
+    let handled = HANDLED.fetch_add(1, Ordering::SeqCst) + 1;
+    req.pack(Serialized::String(handled.to_string()))
+}
+
+#[api]
+async fn tick(req: SemiTcpRaw) {
+    let ticked = TICKED.fetch_add(1, Ordering::SeqCst) + 1;
+    req.pack(Serialized::String(ticked.to_string()))
+}
+
+#[api]
+async fn slow(req: SemiTcpRaw) {
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    req.pack(Serialized::String("slow".to_string()))
+}
+
+#[cfg(feature = "session")]
+#[api]
+async fn whoami(req: SemiTcpRaw) {
+    use dce_router::protocol::RoutableProtocol;
+    let sid = req.rp().sid().unwrap_or("").to_string();
+    req.pack(Serialized::String(sid))
+}
+
+#[cfg(feature = "session")]
+#[api]
+async fn login(mut req: SemiTcpRaw) {
+    use dce_router::protocol::RoutableProtocol;
+    req.rp_mut().set_resp_sid("sid-b".to_string());
+    req.pack(Serialized::String("sid-b".to_string()))
+}
+
+async fn serve(driver: impl FnOnce(Arc<Router<SemiTcpProtocol>>) -> SemiUdpDriver) -> UdpSocket {
+    let router = Router::new().unwrap().push(count).push(tick).push(slow);
+    #[cfg(feature = "session")]
+    let router = router.push(whoami).push(login);
+    let driver = driver(router.ready().unwrap().clone());
+    let server = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    client.connect(server.local_addr().unwrap()).await.unwrap();
+    tokio::spawn(async move { driver.drive(server, |_| Default::default()).await });
+    client
+}
+
+async fn request(client: &UdpSocket, frame: Frame) -> Option<Frame> {
+    let mut dst = BytesMut::new();
+    SemiTcpCodec::binary().encode(frame, &mut dst).unwrap();
+    client.send(&dst).await.unwrap();
+    let mut buf = vec![0; 2048];
+    let len = tokio::time::timeout(Duration::from_millis(300), client.recv(&mut buf)).await.ok()?.unwrap();
+    SemiTcpCodec::binary().decode(&mut BytesMut::from(&buf[..len])).unwrap()
+}
+
+#[test]
+async fn dedup_and_size() {
+    let client = serve(SemiUdpDriver::new).await;
+    let first = request(&client, Frame::new("count").id(1)).await.unwrap();
+    // the retransmitted gets the cached response without handling again
+    let retransmitted = request(&client, Frame::new("count").id(1)).await.unwrap();
+    assert_eq!(first, retransmitted);
+    assert_eq!(&request(&client, Frame::new("count").id(2)).await.unwrap().body[..], b"2");
+    assert!(request(&client, Frame::new("count").id(3).body(&[0u8; 2000][..])).await.is_none());
+}
+
+#[test]
+async fn bounded() {
+    let client = serve(|router| SemiUdpDriver::new(router).max_in_flight(1).max_dedup_entries(2)).await;
+    // handled one by one
+    let started = Instant::now();
+    let mut dst = BytesMut::new();
+    for id in 1..=3 {
+        SemiTcpCodec::binary().encode(Frame::new("slow").id(id), &mut dst).unwrap();
+        client.send(&dst.split()).await.unwrap();
+    }
+    let mut buf = vec![0; 2048];
+    for _ in 1..=3 {
+        tokio::time::timeout(Duration::from_secs(1), client.recv(&mut buf)).await.unwrap().unwrap();
+    }
+    assert!(started.elapsed() >= Duration::from_millis(300));
+
+    // the oldest record was evicted, so its retransmitted was handled again
+    let first = request(&client, Frame::new("tick").id(11)).await.unwrap();
+    let second = request(&client, Frame::new("tick").id(12)).await.unwrap();
+    let third = request(&client, Frame::new("tick").id(13)).await.unwrap();
+    assert_eq!(request(&client, Frame::new("tick").id(13)).await.unwrap(), third);
+    assert_eq!(request(&client, Frame::new("tick").id(12)).await.unwrap(), second);
+    assert_ne!(request(&client, Frame::new("tick").id(11)).await.unwrap(), first);
+}
+
+#[cfg(feature = "session")]
+#[test]
+async fn peer_session() {
+    use dce_router::protocol::HEAD_SID_NAME;
+    let client = serve(|router| SemiUdpDriver::new(router).peer_session(Duration::from_secs(60))).await;
+    assert_eq!(&request(&client, Frame::new("whoami").id(1)).await.unwrap().body[..], b"");
+    assert_eq!(&request(&client, Frame::new("whoami").id(2).head(HEAD_SID_NAME, "sid-a")).await.unwrap().body[..], b"sid-a");
+    // a request carried sid will not be bound to the peer address
+    assert_eq!(&request(&client, Frame::new("whoami").id(3)).await.unwrap().body[..], b"");
+    assert_eq!(&request(&client, Frame::new("login").id(4)).await.unwrap().body[..], b"sid-b");
+    assert_eq!(&request(&client, Frame::new("whoami").id(5)).await.unwrap().body[..], b"sid-b");
+}
@@ -41,6 +41,9 @@ pub struct Api<Rp, ReqDto, RespDto>
     redirect: &'static str,
     name: &'static str,
     unresponsive: bool,
+    // 版本号，同路径的多个版本将按请求的版本选择最高兼容的一个
+    /// Version of the api, the highest compatible one of the same path apis will be chosen by the requested version
+    version: &'static str,
     // 扩展属性，可用于定义如校验方式等通用节点配置
     /// Extends properties, can be used to define general api configs such as verification methods
     extras: HashMap<&'static str, Box<dyn Any + Send + Sync>>,
@@ -62,6 +65,7 @@ impl<Rp, ReqDto, RespDto> Api<Rp, ReqDto, RespDto>
         redirect: &'static str,
         name: &'static str,
         unresponsive: bool,
+        version: &'static str,
         extras: HashMap<&'static str, Box<dyn Any + Send + Sync>>,
     ) -> Self {
         let mut path = path.trim_matches(PATH_PART_SEPARATOR);
@@ -73,7 +77,7 @@ impl<Rp, ReqDto, RespDto> Api<Rp, ReqDto, RespDto>
                 path = &path[0.. last_part_from + bound_index];
             }
         }
-        Api { controller, deserializers, serializers, method, path, suffixes, id, omission, redirect, name, unresponsive, extras, }
+        Api { controller, deserializers, serializers, method, path, suffixes, id, omission, redirect, name, unresponsive, version, extras, }
     }
 
     pub fn controller(&self) -> &Controller<Rp, ReqDto, RespDto> {
@@ -99,6 +103,7 @@ pub trait ApiTrait<Rp: RoutableProtocol> {
     fn redirect(&self) -> &'static str;
     fn name(&self) -> &'static str;
     fn unresponsive(&self) -> bool;
+    fn version(&self) -> &'static str;
     fn extras(&self) -> &HashMap<&'static str, Box<dyn Any + Send + Sync>>;
     fn method_match(&self, rp: &Rp) -> bool;
     #[cfg(feature = "async")]
@@ -145,6 +150,10 @@ impl<Rp, ReqDto, RespDto> ApiTrait<Rp> for Api<Rp, ReqDto, RespDto>
         self.unresponsive
     }
 
+    fn version(&self) -> &'static str {
+        self.version
+    }
+
     fn extras(&self) -> &HashMap<&'static str, Box<dyn Any + Send + Sync>> {
         &self.extras
     }
@@ -197,8 +206,8 @@ impl<Rp, ReqDto, RespDto> ApiTrait<Rp> for Api<Rp, ReqDto, RespDto>
 
 impl<Rp: RoutableProtocol> Debug for dyn ApiTrait<Rp> + Send + Sync + 'static {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        f.write_str(format!(r#"Api{{method: {:?}, path: "{}", suffixes: {:?}, id: "{}", omission: {}, redirect: "{}", name: "{}", unresponsive: {}, extras: {:?}}}"#,
-                            self.method(), self.path(), self.suffixes(), self.id(), self.omission(), self.redirect(), self.name(), self.unresponsive(), self.extras()).as_str())
+        f.write_str(format!(r#"Api{{method: {:?}, path: "{}", suffixes: {:?}, id: "{}", omission: {}, redirect: "{}", name: "{}", unresponsive: {}, version: "{}", extras: {:?}}}"#,
+                            self.method(), self.path(), self.suffixes(), self.id(), self.omission(), self.redirect(), self.name(), self.unresponsive(), self.version(), self.extras()).as_str())
     }
 }
 
@@ -238,6 +247,11 @@ impl Ord for Suffix {
 pub trait Method<Rp> {
     fn to_string(&self) -> String;
     fn req_match(&self, raw: &Rp) -> bool;
+    // 用于协议实现中将方法对象转回具体类型，以读取其定义的请求方式
+    /// Used to downcast the method object to the concrete type in protocol implementations, to read the defined methods
+    fn as_any(&self) -> Option<&dyn Any> {
+        None
+    }
 }
 
 impl<Rp> Debug for dyn Method<Rp> + Send + Sync + 'static {
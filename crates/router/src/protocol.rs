@@ -5,16 +5,46 @@ use crate::serializer::{Deserializer, Serializable, Serialized, Serializer};
 use log::{error, warn};
 use std::any::Any;
 use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
+use std::time::Duration;
 use crate::api::{ApiTrait, Method};
-use crate::router::{CODE_NOT_FOUND, Router};
+use crate::router::{CODE_NOT_FOUND, compare_versions, Router};
 #[cfg(feature = "async")]
 use async_trait::async_trait;
 
 
 pub const HEAD_PATH_NAME: &'static str = "$#path#";
 pub const HEAD_ID_NAME: &'static str = "$#id#";
+/// The requested api version head, such as the `Api-Version` http header or the `Api-Version:2` head line of tcp or websocket messages
+pub const HEAD_VERSION_NAME: &'static str = "Api-Version";
+/// The extras key of the max request body bytes, could be set in router extras as global config, or in api extras to override the global,
+/// e.g. `#[api(max_body = 1024)]`
+pub const MAX_BODY_EXTRA_NAME: &str = "max_body";
+/// The extras key of the request body read timeout, the value could be a `Duration` or an integer of seconds, e.g. `#[api(body_timeout = 5)]`.
+/// It only applies to the streaming bodies such as http, the message transports have read the whole message before routing
+pub const BODY_TIMEOUT_EXTRA_NAME: &str = "body_timeout";
+/// The max request body bytes if not configured
+pub const DEFAULT_MAX_BODY: usize = 4 * 1024 * 1024;
+/// The bytes reserved for the id, path and heads of a message on top of the max body, to bound the read buffers of the message transports
+pub const MAX_HEAD_SIZE: usize = 64 * 1024;
+pub const CODE_REQUEST_TIMEOUT: isize = 408;
+pub const CODE_PAYLOAD_TOO_LARGE: isize = 413;
+/// The lifecycle event paths of long-lived connections, the connection drivers will route them to the apis with the same paths if registered,
+/// e.g. `#[api("$connect")]`
+pub const EVENT_CONNECT_PATH: &str = "$connect";
+pub const EVENT_DISCONNECT_PATH: &str = "$disconnect";
+pub const EVENT_IDLE_PATH: &str = "$idle";
+/// The prefix of the reserved event paths, the inbound requests with it will be rejected by the connection drivers
+pub const EVENT_PATH_PREFIX: char = '$';
+/// The context data key of the routing event path, it only exists in the lifecycle events emitted by the drivers,
+/// e.g. `req.get_as::<String>(EVENT_DATA_NAME)`
+pub const EVENT_DATA_NAME: &str = "$#event#";
+/// The context data key of the [ConnectionInfo], e.g. `req.get_as::<Arc<ConnectionInfo>>(CONNECTION_DATA_NAME)`
+pub const CONNECTION_DATA_NAME: &str = "$#connection#";
+/// The response head of the error code of the semi tcp and websocket protocols, so that the clients could tell the error responses
+pub const HEAD_CODE_NAME: &str = "Code";
 #[cfg(feature = "session")]
 pub const HEAD_SID_NAME: &'static str = "Session-Id";
 
@@ -25,11 +55,13 @@ pub struct Meta<Req, Resp> {
     resp: Option<Response<Resp>>,
     heads: HashMap<String, String>,
     resp_heads: HashMap<String, String>,
+    max_body: usize,
+    body_timeout: Option<Duration>,
 }
 
 impl<Req, Resp> Meta<Req, Resp> {
     pub fn new(req: Req, heads: HashMap<String, String>) -> Self {
-        Self { req: Some(req), resp: None, heads, resp_heads: Default::default() }
+        Self { req: Some(req), resp: None, heads, resp_heads: Default::default(), max_body: DEFAULT_MAX_BODY, body_timeout: None }
     }
     
     pub fn req(&self) -> DceResult<&Req> {
@@ -48,6 +80,10 @@ impl<Req, Resp> Meta<Req, Resp> {
         &self.heads
     }
     
+    pub fn heads_mut(&mut self) -> &mut HashMap<String, String> {
+        &mut self.heads
+    }
+    
     pub fn resp_heads(&self) -> &HashMap<String, String> {
         &self.resp_heads
     }
@@ -55,6 +91,58 @@ impl<Req, Resp> Meta<Req, Resp> {
     pub fn resp_heads_mut(&mut self) -> &mut HashMap<String, String> {
         &mut self.resp_heads
     }
+
+    pub fn max_body(&self) -> usize {
+        self.max_body
+    }
+
+    pub fn set_max_body(&mut self, max_body: usize) {
+        self.max_body = max_body;
+    }
+
+    pub fn body_timeout(&self) -> Option<Duration> {
+        self.body_timeout
+    }
+
+    pub fn set_body_timeout(&mut self, timeout: Option<Duration>) {
+        self.body_timeout = timeout;
+    }
+
+    /// Protocols should check the body size before reading or parsing it, an openly 413 error will be returned if it exceeded the limit
+    pub fn check_body_size(&self, size: usize) -> DceResult<()> {
+        match size > self.max_body {
+            true => Err(DceErr::openly(CODE_PAYLOAD_TOO_LARGE, format!("Request body exceeded the limit of {} bytes", self.max_body))),
+            false => Ok(()),
+        }
+    }
+}
+
+
+/// The info of a long-lived connection, will be shared by all the requests and lifecycle events of the connection
+#[derive(Debug, Default, Clone)]
+pub struct ConnectionInfo {
+    peer: Option<SocketAddr>,
+    headers: HashMap<String, String>,
+}
+
+impl ConnectionInfo {
+    /// The header names will be lowercased
+    pub fn new(peer: Option<SocketAddr>, headers: HashMap<String, String>) -> Self {
+        Self { peer, headers: headers.into_iter().map(|(k, v)| (k.to_lowercase(), v)).collect() }
+    }
+
+    pub fn peer(&self) -> Option<SocketAddr> {
+        self.peer
+    }
+
+    /// The handshake headers, such as the websocket upgrading request headers
+    pub fn headers(&self) -> &HashMap<String, String> {
+        &self.headers
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_lowercase()).map(String::as_str)
+    }
 }
 
 
@@ -77,6 +165,11 @@ pub trait RoutableProtocol: From<Self::Req> + Into<Self::Resp> + Deref<Target =
         self.heads.get(HEAD_ID_NAME).map(|v| v.as_str())
     }
 
+    /// The requested api version, a leading `v` will be ignored
+    fn version(&self) -> Option<&str> {
+        self.heads.get(HEAD_VERSION_NAME).map(|v| v.trim().trim_start_matches(['v', 'V'])).filter(|v| ! v.is_empty())
+    }
+
     #[cfg(feature = "async")]
     async fn handle(self, router: Arc<Router<Self>>, context_data: HashMap<String, Box<dyn Any + Send>>) -> Option<Self::Resp> {
         let mut context = Context::new(router, self, context_data);
@@ -92,8 +185,14 @@ pub trait RoutableProtocol: From<Self::Req> + Into<Self::Resp> + Deref<Target =
     }
 
     fn api_match(&self, apis: &[&'static (dyn ApiTrait<Self> + Send + Sync)]) -> DceResult<&'static (dyn ApiTrait<Self> + Send + Sync)> {
-        apis.iter().find(|n| n.method_match(self)).map(|a| *a)
-            .ok_or_else(|| DceErr::openly(CODE_NOT_FOUND, format!(r#"Path "{}" cannot match any Api by Method"#, self.path())))
+        let version = self.version();
+        // choose the highest version not newer than the requested, or the unversioned one, the latest will be chosen if not requested
+        apis.iter().filter(|n| n.method_match(self))
+            .filter(|n| n.version().is_empty() || ! version.is_some_and(|v| compare_versions(n.version(), v).is_gt()))
+            // max_by returns the last max element, so reverse to keep the first defined one
+            .rev().max_by(|a, b| compare_versions(a.version(), b.version())).copied()
+            .ok_or_else(|| DceErr::openly(CODE_NOT_FOUND, format!(r#"Path "{}" cannot match any Api by Method{}"#, self.path(),
+                version.map_or_else(String::new, |v| format!(r#" and Version "{}""#, v)))))
     }
 
     fn deserializer<'a, ReqDto>(deserializers: &'a [Box<dyn Deserializer<ReqDto> + Send + Sync>], _context: &Context<Self>) -> DceResult<&'a Box<dyn Deserializer<ReqDto> + Send + Sync>> {
@@ -172,6 +271,12 @@ pub trait RoutableProtocol: From<Self::Req> + Into<Self::Resp> + Deref<Target =
         self.resp_heads.get(HEAD_SID_NAME)
     }
 
+    /// Respond an empty sid to tell the client to forget the old one, such as after logout
+    #[cfg(feature = "session")]
+    fn clear_resp_sid(&mut self) {
+        self.set_resp_sid(String::new());
+    }
+
     #[cfg(feature = "session")]
     fn set_session<Rp: RoutableProtocol + Debug + 'static>(context: &mut Context<Rp>, value: Box<dyn Any + Send>) {
         context.put_data("$#session#".to_string(), value);
@@ -181,4 +286,9 @@ pub trait RoutableProtocol: From<Self::Req> + Into<Self::Resp> + Deref<Target =
     fn session<S: 'static, Rp: RoutableProtocol + Debug + 'static>(context: &mut Context<Rp>) -> DceResult<&mut S> {
         context.get_as_mut("$#session#")
     }
+
+    #[cfg(feature = "session")]
+    fn take_session<Rp: RoutableProtocol + Debug + 'static>(context: &mut Context<Rp>) -> Option<Box<dyn Any + Send>> {
+        context.take_data("$#session#")
+    }
 }
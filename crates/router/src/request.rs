@@ -73,6 +73,10 @@ impl<Rp: RoutableProtocol + Debug + 'static> Context<Rp> {
         self.data.insert(key, value);
     }
 
+    pub fn take_data(&mut self, key: &str) -> Option<Box<dyn Any + Send>> {
+        self.data.remove(key)
+    }
+
     pub fn get_as<S: 'static>(&self, key: &str) -> DceResult<&S> {
         let type_name = type_name::<S>();
         self.data.get(key).ok_or_else(|| DceErr::closed0(format!("{} has not bound yet", type_name)))?
@@ -85,9 +89,14 @@ impl<Rp: RoutableProtocol + Debug + 'static> Context<Rp> {
             .downcast_mut().ok_or_else(|| DceErr::closed0(format!("Box cannot downcast to {} ref", type_name)))
     }
 
+    /// Preset a param before routing, such as the params captured from the host, the same name path param will override it
+    pub fn put_param(&mut self, key: &'static str, value: PathParam) {
+        self.path_params.insert(key, value);
+    }
+
     pub fn set_routed_info(&mut self, api: &'static (dyn ApiTrait<Rp> + Send + Sync), params: HashMap<&'static str, PathParam>, suffix: Option<&'static str>) {
         self.api = Some(api);
-        self.path_params = params;
+        self.path_params.extend(params);
         self.suffix = suffix;
     }
 }
@@ -1,4 +1,5 @@
-use std::any::type_name;
+use std::any::{Any, type_name};
+use std::cmp::Ordering;
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fmt::Debug;
 use crate::api::{ApiTrait, EventHandler};
@@ -6,8 +7,9 @@ use dce_util::mixed::{DceErr, DceResult};
 use dce_util::atom_tree::ATree;
 use dce_util::atom_tree::{KeyFactory, TreeTraverBreak};
 use std::sync::{Arc, RwLockReadGuard};
+use std::time::Duration;
 use log::debug;
-use crate::protocol::RoutableProtocol;
+use crate::protocol::{BODY_TIMEOUT_EXTRA_NAME, DEFAULT_MAX_BODY, HEAD_VERSION_NAME, MAX_BODY_EXTRA_NAME, RoutableProtocol};
 use crate::request::{PathParam, Context};
 
 pub const PATH_PART_SEPARATOR: char = '/';
@@ -17,6 +19,7 @@ const VARIABLE_CLOSING: char = '}';
 const VAR_TYPE_OPTIONAL: char = '?';
 const VAR_TYPE_EMPTABLE_VECTOR: char = '*';
 const VAR_TYPE_VECTOR: char = '+';
+const VERSION_MARK: char = 'v';
 
 pub const CODE_NOT_FOUND: isize = 404;
 
@@ -32,6 +35,13 @@ pub struct Router<Rp: RoutableProtocol + 'static> {
     apis_tree: Arc<ATree<ApiBranch<Rp>, &'static str>>,
     before_controller: Option<EventHandler<Rp>>,
     after_controller: Option<EventHandler<Rp>>,
+    // 是否有带版本号的接口，有则解析路径中的版本号
+    /// Whether there were versioned apis, the version part of path will be resolved if there were
+    versioned: bool,
+    default_version: Option<&'static str>,
+    // 扩展属性，可用于定义如跨域等全局配置
+    /// Extends properties, can be used to define global configs such as cors
+    extras: HashMap<&'static str, Box<dyn Any + Send + Sync>>,
 }
 
 impl<Rp: RoutableProtocol + Debug + 'static> Router<Rp> {
@@ -46,6 +56,9 @@ impl<Rp: RoutableProtocol + Debug + 'static> Router<Rp> {
             apis_tree: ATree::new(ApiBranch::new("", vec![]))?,
             before_controller: None,
             after_controller: None,
+            versioned: false,
+            default_version: None,
+            extras: Default::default(),
         })
     }
 
@@ -71,6 +84,21 @@ impl<Rp: RoutableProtocol + Debug + 'static> Router<Rp> {
         &self.after_controller
     }
 
+    pub fn extras(&self) -> &HashMap<&'static str, Box<dyn Any + Send + Sync>> {
+        &self.extras
+    }
+
+    pub fn set_extra(mut self, key: &'static str, value: Box<dyn Any + Send + Sync>) -> Self {
+        self.extras.insert(key, value);
+        self
+    }
+
+    /// Set the version to use when the request did not specify, the latest version will be used if not set
+    pub fn set_default_version(mut self, version: &'static str) -> Self {
+        self.default_version = Some(version);
+        self
+    }
+
     pub fn set_event_handlers(mut self, before_controller: Option<EventHandler<Rp>>, after_controller: Option<EventHandler<Rp>>) -> Self {
         self.before_controller = before_controller;
         self.after_controller = after_controller;
@@ -85,6 +113,7 @@ impl<Rp: RoutableProtocol + Debug + 'static> Router<Rp> {
         if ! api.id().is_empty() {
             self.id_api_mapping.insert(api.id(), api);
         }
+        self.versioned |= ! api.version().is_empty();
         self.api_buffer.push(api);
         self
     }
@@ -195,10 +224,53 @@ impl<Rp: RoutableProtocol + Debug + 'static> Router<Rp> {
         Ok(Box::leak(Box::new(Arc::new(self))))
     }
 
-    fn locate(
+    /// All the apis sorted by path and version, can be used to print the route table
+    pub fn routes(&self) -> Vec<&'static (dyn ApiTrait<Rp> + Send + Sync)> {
+        let mut apis = self.apis_mapping.values().flatten().copied().collect::<Vec<_>>();
+        apis.sort_by(|a, b| a.path().cmp(b.path()).then_with(|| compare_versions(a.version(), b.version())));
+        apis.dedup_by(|a, b| std::ptr::addr_eq(*a, *b));
+        apis
+    }
+
+    /// The largest max body bytes of the router extras and all the apis extras, the message transports could bound their read buffers with it
+    pub fn max_body(&self) -> usize {
+        let global = self.extras.get(MAX_BODY_EXTRA_NAME).and_then(|v| extra_as_u64(&**v)).unwrap_or(DEFAULT_MAX_BODY as u64);
+        let max = self.routes().iter().filter_map(|api| api.extras().get(MAX_BODY_EXTRA_NAME).and_then(|v| extra_as_u64(&**v))).fold(global, u64::max);
+        usize::try_from(max).unwrap_or(usize::MAX)
+    }
+
+    /// Resolve the requested version from the path prefix like `v2/users` or the suffix like `users.v2` if there were versioned apis.
+    /// The version in protocol heads takes precedence, the resolved or the default version will be put into heads if not specified,
+    /// and returns the path without the version part if resolved. It could be called again with the same result, so protocols could
+    /// resolve it to locate the api before routing
+    pub fn resolve_version(&self, rp: &mut Rp) -> Option<String> {
+        if ! self.versioned {
+            return None;
+        }
+        let path = rp.path();
+        let (prefix, rest) = path.split_once(self.path_part_separator).unwrap_or((path, ""));
+        let resolved = match version_token(prefix) {
+            Some(version) => Some((version.to_string(), rest.to_string())),
+            None => path.rfind(format!("{}{}", self.suffix_boundary, VERSION_MARK).as_str())
+                .and_then(|index| version_token(&path[index + 1..]).map(|version| (version.to_string(), path[..index].to_string()))),
+        };
+        if rp.version().is_none() {
+            if let Some(version) = resolved.as_ref().map(|(version, _)| version.clone()).or_else(|| self.default_version.map(ToString::to_string)) {
+                rp.heads_mut().insert(HEAD_VERSION_NAME.to_string(), version);
+            }
+        }
+        resolved.map(|(_, path)| path)
+    }
+
+    /// Whether the path could be located to any api, e.g. to check if a lifecycle event api was registered
+    pub fn routable(&self, path: &str) -> bool {
+        self.locate(path, |apis| apis.first().copied().ok_or_else(|| DceErr::closed0("No api"))).is_ok()
+    }
+
+    pub fn locate(
         &self,
         mut path: &str,
-        api_finder: impl Fn(&Vec<&'static (dyn ApiTrait<Rp> + Send + Sync)>) -> DceResult<&'static (dyn ApiTrait<Rp> + Send + Sync)>,
+        mut api_finder: impl FnMut(&Vec<&'static (dyn ApiTrait<Rp> + Send + Sync)>) -> DceResult<&'static (dyn ApiTrait<Rp> + Send + Sync)>,
     ) -> DceResult<(&'static (dyn ApiTrait<Rp> + Send + Sync), HashMap<&'static str, PathParam>, Option<&'static str>)> {
         let request_path = path;
         let mut api;
@@ -323,10 +395,24 @@ impl<Rp: RoutableProtocol + Debug + 'static> Router<Rp> {
         return matches.map(|tree| (tree.clone(), ""));
     }
 
+    /// Apply the body limits configured in api extras, or in router extras as the global default
+    fn apply_body_limits(api: &'static (dyn ApiTrait<Rp> + Send + Sync), context: &mut Context<Rp>) {
+        let extra = |name| api.extras().get(name).or_else(|| context.router().extras().get(name)).map(|v| &**v);
+        let max_body = extra(MAX_BODY_EXTRA_NAME).and_then(extra_as_u64);
+        let body_timeout = extra(BODY_TIMEOUT_EXTRA_NAME).and_then(|v| v.downcast_ref::<Duration>().copied().or_else(|| extra_as_u64(v).map(Duration::from_secs)));
+        if let Some(max_body) = max_body {
+            context.rp_mut().set_max_body(usize::try_from(max_body).unwrap_or(usize::MAX));
+        }
+        if body_timeout.is_some() {
+            context.rp_mut().set_body_timeout(body_timeout);
+        }
+    }
+
     #[cfg(feature = "async")]
     async fn routed_handle(result: DceResult<(&'static (dyn ApiTrait<Rp> + Send + Sync), HashMap<&'static str, PathParam>, Option<&'static str>)>, context: &mut Context<Rp>) -> DceResult<()> {
         let (api, path_args, suffix) = result?;
         context.set_routed_info(api, path_args, suffix);
+        Self::apply_body_limits(api, context);
         api.call_controller(context).await
     }
 
@@ -334,17 +420,20 @@ impl<Rp: RoutableProtocol + Debug + 'static> Router<Rp> {
     fn routed_handle(result: DceResult<(&'static (dyn ApiTrait<Rp> + Send + Sync), HashMap<&'static str, PathParam>, Option<&'static str>)>, context: &mut Context<Rp>) -> DceResult<()> {
         let (api, path_args, suffix) = result?;
         context.set_routed_info(api, path_args, suffix);
+        Self::apply_body_limits(api, context);
         api.call_controller(context)
     }
 
     #[cfg(feature = "async")]
     pub async fn route(context: &mut Context<Rp>) -> DceResult<()> {
-        Self::routed_handle(context.router().locate(context.rp().path(), |apis| context.rp().api_match(apis)), context).await
+        let path = context.router().clone().resolve_version(context.rp_mut());
+        Self::routed_handle(context.router().locate(path.as_deref().unwrap_or(context.rp().path()), |apis| context.rp().api_match(apis)), context).await
     }
 
     #[cfg(not(feature = "async"))]
     pub fn route(context: &mut Context<Rp>) -> DceResult<()> {
-        Self::routed_handle(context.router().locate(context.rp().path(), |apis| context.rp().api_match(apis)), context)
+        let path = context.router().clone().resolve_version(context.rp_mut());
+        Self::routed_handle(context.router().locate(path.as_deref().unwrap_or(context.rp().path()), |apis| context.rp().api_match(apis)), context)
     }
 
     fn id_locate(&self, id: &str) -> DceResult<(&'static (dyn ApiTrait<Rp> + Send + Sync), HashMap<&'static str, PathParam>, Option<&'static str>)> {
@@ -367,6 +456,38 @@ impl<Rp: RoutableProtocol + Debug + 'static> Router<Rp> {
     }
 }
 
+/// Compare the versions like `2` and `2.1` by numeric parts, a version with more parts is newer when the common parts are equal
+pub fn compare_versions(a: &str, b: &str) -> Ordering {
+    let (mut a_parts, mut b_parts) = (a.split('.').filter(|p| ! p.is_empty()), b.split('.').filter(|p| ! p.is_empty()));
+    loop {
+        match (a_parts.next(), b_parts.next()) {
+            (Some(a), Some(b)) => match match (a.parse::<u64>(), b.parse::<u64>()) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                _ => a.cmp(b),
+            } {
+                Ordering::Equal => continue,
+                ordering => return ordering,
+            },
+            (Some(_), None) => return Ordering::Greater,
+            (None, Some(_)) => return Ordering::Less,
+            (None, None) => return Ordering::Equal,
+        }
+    }
+}
+
+/// Get the integer extras value, literals in the `#[api]` attribute will be boxed as `i32` if not suffixed
+fn extra_as_u64(value: &(dyn Any + Send + Sync)) -> Option<u64> {
+    value.downcast_ref::<u64>().copied()
+        .or_else(|| value.downcast_ref::<usize>().map(|v| *v as u64))
+        .or_else(|| value.downcast_ref::<u32>().map(|v| *v as u64))
+        .or_else(|| value.downcast_ref::<i64>().and_then(|v| u64::try_from(*v).ok()))
+        .or_else(|| value.downcast_ref::<i32>().and_then(|v| u64::try_from(*v).ok()))
+}
+
+fn version_token(part: &str) -> Option<&str> {
+    part.strip_prefix(VERSION_MARK).filter(|v| v.starts_with(|c: char| c.is_ascii_digit()) && v.chars().all(|c| c.is_ascii_digit() || c == '.'))
+}
+
 #[derive(PartialEq, Debug, Clone)]
 enum VarType {
     Required(&'static str),
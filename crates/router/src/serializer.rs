@@ -27,8 +27,23 @@ impl Serialized {
         }
     }
     
+    /// Decode as UTF-8 text, the invalid bytes will be treated as error, for the text deserializers
+    pub fn into_text(self) -> DceResult<String> {
+        match self {
+            Serialized::String(v) => Ok(v),
+            Serialized::Bytes(v) => String::from_utf8(v.to_vec()).map_err(|e| DceErr::openly0(format!("Body is not a valid UTF-8 text: {e}"))),
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Serialized::String(v) => v.as_bytes(),
+            Serialized::Bytes(v) => v.as_ref(),
+        }
+    }
+
     pub fn json_value(&self) -> DceResult<Value> {
-        serde_json::from_str(self.to_string().as_str()).map_err(DceErr::closed0)
+        serde_json::from_slice(self.as_bytes()).map_err(DceErr::closed0)
     }
 }
 
@@ -0,0 +1,13 @@
+use std::cmp::Ordering;
+use dce_router::router::compare_versions;
+
+#[test]
+fn compare() {
+    assert_eq!(compare_versions("2", "2"), Ordering::Equal);
+    assert_eq!(compare_versions("2.10", "2.9"), Ordering::Greater);
+    assert_eq!(compare_versions("10", "9"), Ordering::Greater);
+    assert_eq!(compare_versions("2.1", "2"), Ordering::Greater);
+    assert_eq!(compare_versions("2", "2.0.1"), Ordering::Less);
+    assert_eq!(compare_versions("2.", "2"), Ordering::Equal);
+    assert_eq!(compare_versions("1.b", "1.a"), Ordering::Greater);
+}
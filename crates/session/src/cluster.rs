@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::marker::PhantomData;
+use std::sync::Arc;
+use async_trait::async_trait;
+use futures::lock::Mutex;
+use log::{debug, warn};
+use serde::Serialize;
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::task::JoinHandle;
+use dce_util::mixed::{DceErr, DceResult};
+use dce_util::registry::{ConnectionRegistry, PushTarget, PUSH_BUFFER_SIZE};
+use crate::connection::Connection;
+use crate::user::{UidGetter, User};
+#[cfg(feature = "redis")]
+use futures::StreamExt;
+#[cfg(feature = "redis")]
+use redis::aio::MultiplexedConnection;
+#[cfg(feature = "redis")]
+use redis::{AsyncCommands, Client};
+
+
+/// A message forwarded to the node which hosting the sids
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClusterMessage {
+    pub sids: Vec<String>,
+    pub path: String,
+    pub body: Vec<u8>,
+}
+
+impl ClusterMessage {
+    pub fn new(sids: Vec<String>, path: &str, body: Vec<u8>) -> Self {
+        Self { sids, path: path.to_string(), body }
+    }
+
+    /// Encode as `[u16 sid count]([u16 len][sid])*[u16 len][path][body]`, the lengths are big endian
+    pub fn encode(&self) -> DceResult<Vec<u8>> {
+        let mut buf = Vec::with_capacity(self.body.len() + self.path.len() + 4);
+        put_u16(&mut buf, self.sids.len())?;
+        for sid in &self.sids {
+            put_str(&mut buf, sid)?;
+        }
+        put_str(&mut buf, &self.path)?;
+        buf.extend_from_slice(&self.body);
+        Ok(buf)
+    }
+
+    pub fn decode(mut buf: &[u8]) -> DceResult<Self> {
+        let count = take_u16(&mut buf)?;
+        let sids = (0..count).map(|_| take_str(&mut buf)).collect::<DceResult<Vec<_>>>()?;
+        let path = take_str(&mut buf)?;
+        Ok(Self { sids, path, body: buf.to_vec() })
+    }
+}
+
+
+/// A bus to deliver the messages between the cluster nodes, each node subscribes with its server name which recorded in the `$server` session field
+#[async_trait]
+pub trait ClusterBus: Send + Sync {
+    /// Publish the message to the node of the server
+    async fn publish(&self, server: &str, message: ClusterMessage) -> DceResult<()>;
+
+    /// Subscribe the messages published to the server
+    async fn subscribe(&self, server: &str) -> DceResult<Receiver<ClusterMessage>>;
+}
+
+
+/// An in-process bus, the clones share the same subscriptions, mainly for tests or running multiple nodes in one process
+#[derive(Debug, Clone, Default)]
+pub struct LocalBus {
+    subscribers: Arc<std::sync::Mutex<HashMap<String, Sender<ClusterMessage>>>>,
+}
+
+impl LocalBus {
+    pub fn new() -> Self {
+        Default::default()
+    }
+}
+
+#[async_trait]
+impl ClusterBus for LocalBus {
+    async fn publish(&self, server: &str, message: ClusterMessage) -> DceResult<()> {
+        let sender = self.subscribers.lock().map_err(DceErr::closed0)?.get(server).cloned()
+            .ok_or_else(|| DceErr::closed0(format!("No node subscribed for server \"{server}\"")))?;
+        sender.send(message).await.map_err(DceErr::closed0)
+    }
+
+    async fn subscribe(&self, server: &str) -> DceResult<Receiver<ClusterMessage>> {
+        let (sender, receiver) = mpsc::channel(PUSH_BUFFER_SIZE);
+        self.subscribers.lock().map_err(DceErr::closed0)?.insert(server.to_string(), sender);
+        Ok(receiver)
+    }
+}
+
+
+/// A redis pub/sub bus, the messages of a server will be published to the channel `{prefix}{server}`
+#[cfg(feature = "redis")]
+#[derive(Clone)]
+pub struct RedisBus {
+    client: Client,
+    publisher: MultiplexedConnection,
+    prefix: String,
+}
+
+#[cfg(feature = "redis")]
+impl RedisBus {
+    pub const DEFAULT_CHANNEL_PREFIX: &'static str = "dcecluster:";
+
+    pub async fn new(client: Client) -> DceResult<Self> {
+        let publisher = client.get_multiplexed_async_connection().await.map_err(DceErr::closed0)?;
+        Ok(Self { client, publisher, prefix: Self::DEFAULT_CHANNEL_PREFIX.to_string() })
+    }
+
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    fn channel(&self, server: &str) -> String {
+        format!("{}{}", self.prefix, server)
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl ClusterBus for RedisBus {
+    async fn publish(&self, server: &str, message: ClusterMessage) -> DceResult<()> {
+        let receivers: usize = self.publisher.clone().publish(self.channel(server), message.encode()?).await.map_err(DceErr::closed0)?;
+        if receivers == 0 {
+            return Err(DceErr::closed0(format!("No node subscribed for server \"{server}\"")));
+        }
+        Ok(())
+    }
+
+    async fn subscribe(&self, server: &str) -> DceResult<Receiver<ClusterMessage>> {
+        let mut pubsub = self.client.get_async_pubsub().await.map_err(DceErr::closed0)?;
+        pubsub.subscribe(self.channel(server)).await.map_err(DceErr::closed0)?;
+        let (sender, receiver) = mpsc::channel(PUSH_BUFFER_SIZE);
+        tokio::spawn(async move {
+            let mut messages = pubsub.into_on_message();
+            while let Some(message) = messages.next().await {
+                match ClusterMessage::decode(message.get_payload_bytes()) {
+                    Ok(message) => if sender.send(message).await.is_err() { break },
+                    Err(e) => warn!("{e}"),
+                }
+            }
+        });
+        Ok(receiver)
+    }
+}
+
+#[cfg(feature = "redis")]
+impl Debug for RedisBus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(format!("RedisBus {{ prefix: {:?} }}", self.prefix).as_str())
+    }
+}
+
+
+type Pack<M> = Arc<dyn Fn(&str, Vec<u8>) -> M + Send + Sync>;
+
+/// A node of the cluster, pushes to the sids or uids connected to other nodes will be forwarded to them through the bus,
+/// and the forwarded messages will be delivered to the local registry. The `server` must be the same with the one passed to
+/// [Connection::connect()], and the `session` is only used to look up the `$server` field and the sids of users, so its sid doesn't matter
+pub struct ClusterNode<S, U, M> {
+    server: String,
+    bus: Arc<dyn ClusterBus>,
+    registry: ConnectionRegistry<M>,
+    session: Arc<Mutex<S>>,
+    pack: Pack<M>,
+    _marker: PhantomData<U>,
+}
+
+impl<S, U, M> ClusterNode<S, U, M>
+where
+    S: Connection + User<U> + Send,
+    U: UidGetter + Serialize + Send + Sync + 'static,
+    M: Clone + Send + 'static,
+{
+    /// The `pack` is used to build the message of the registry from the path and body, such as `SemiWebsocketRegistry::pack()`
+    pub fn new<F>(server: &str, bus: Arc<dyn ClusterBus>, registry: ConnectionRegistry<M>, session: S, pack: F) -> Self
+    where F: Fn(&str, Vec<u8>) -> M + Send + Sync + 'static,
+    {
+        Self { server: server.to_string(), bus, registry, session: Arc::new(Mutex::new(session)), pack: Arc::new(pack), _marker: PhantomData }
+    }
+
+    pub fn server(&self) -> &str {
+        &self.server
+    }
+
+    pub fn registry(&self) -> &ConnectionRegistry<M> {
+        &self.registry
+    }
+
+    /// Subscribe the bus and deliver the forwarded messages to the local registry until the bus closed
+    pub async fn serve(&self) -> DceResult<JoinHandle<()>> {
+        let mut receiver = self.bus.subscribe(&self.server).await?;
+        let (registry, pack) = (self.registry.clone(), self.pack.clone());
+        Ok(tokio::spawn(async move {
+            while let Some(message) = receiver.recv().await {
+                let delivered = registry.send(&PushTarget::Sids(message.sids), pack(&message.path, message.body));
+                debug!("delivered forwarded message \"{}\" to {delivered} connections", message.path);
+            }
+        }))
+    }
+
+    /// Push to the local connections of the target, and forward to other nodes for the sids or uids not connected to this node.
+    /// The rooms are local to the node, so [PushTarget::Room] and [PushTarget::All] will not be forwarded.
+    /// Returns the count of the local connections which the message was queued to, plus the count of the forwarded sids
+    pub async fn push(&self, target: PushTarget, path: &str, body: Vec<u8>) -> DceResult<usize> {
+        let mut count = self.registry.send(&target, (self.pack)(path, body.clone()));
+        let sids = match target {
+            PushTarget::Sid(sid) => vec![sid],
+            PushTarget::Sids(sids) => sids,
+            PushTarget::Uid(uid) => self.session.lock().await.sids(uid).await?.into_iter().collect(),
+            _ => return Ok(count),
+        };
+        let remote = sids.into_iter().filter(|sid| self.registry.connections(&PushTarget::Sid(sid.clone())).is_empty()).collect::<Vec<_>>();
+        if remote.is_empty() {
+            return Ok(count);
+        }
+        let mut servers = HashMap::<String, Vec<String>>::new();
+        for (sid, server) in self.session.lock().await.servers(&remote).await? {
+            // a sid bound with this server but not connected here is stale, nothing to deliver
+            if server != self.server {
+                servers.entry(server).or_default().push(sid);
+            }
+        }
+        for (server, sids) in servers {
+            let len = sids.len();
+            match self.bus.publish(&server, ClusterMessage::new(sids, path, body.clone())).await {
+                Ok(_) => count += len,
+                Err(e) => warn!("failed to forward to \"{server}\": {e}"),
+            }
+        }
+        Ok(count)
+    }
+
+    /// Unbind the sids of the live connections from this server, should be called before the node shutdown,
+    /// so that the pushes to them will not be forwarded to this server. Returns the count of the unbound
+    pub async fn shutdown(&self) -> DceResult<usize> {
+        let sids = self.registry.sids();
+        self.session.lock().await.unbind_server(&sids, &self.server).await
+    }
+}
+
+impl<S, U, M> Debug for ClusterNode<S, U, M> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(format!("ClusterNode {{ server: {:?}, registry: {:?} }}", self.server, self.registry).as_str())
+    }
+}
+
+
+fn put_u16(buf: &mut Vec<u8>, len: usize) -> DceResult<()> {
+    let len = u16::try_from(len).map_err(|_| DceErr::closed0(format!("Length {len} overflowed u16")))?;
+    buf.extend_from_slice(&len.to_be_bytes());
+    Ok(())
+}
+
+fn put_str(buf: &mut Vec<u8>, str: &str) -> DceResult<()> {
+    put_u16(buf, str.len())?;
+    buf.extend_from_slice(str.as_bytes());
+    Ok(())
+}
+
+fn take_u16(buf: &mut &[u8]) -> DceResult<u16> {
+    let (len, rest) = buf.split_first_chunk::<2>().ok_or_else(|| DceErr::closed0("Truncated cluster message"))?;
+    *buf = rest;
+    Ok(u16::from_be_bytes(*len))
+}
+
+fn take_str(buf: &mut &[u8]) -> DceResult<String> {
+    let len = take_u16(buf)? as usize;
+    if buf.len() < len {
+        return Err(DceErr::closed0("Truncated cluster message"));
+    }
+    let (str, rest) = buf.split_at(len);
+    *buf = rest;
+    String::from_utf8(str.to_vec()).map_err(DceErr::closed0)
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::sync::{Arc, Weak};
 use dce_util::mixed::{DceErr, DceResult};
 use crate::session::Session;
@@ -45,6 +46,20 @@ pub trait Connection: Session {
 
     #[cfg(not(feature = "async"))]
     fn unbinding(&mut self) -> DceResult<bool>;
+
+    /// Get the hosting servers of the sids, the sids not bound with any server will be omitted
+    #[cfg(feature = "async")]
+    async fn servers(&mut self, sids: &[String]) -> DceResult<HashMap<String, String>>;
+
+    #[cfg(not(feature = "async"))]
+    fn servers(&mut self, sids: &[String]) -> DceResult<HashMap<String, String>>;
+
+    /// Unbind the sids from the server, the sids bound with another server will not be touched, returns the count of the unbound
+    #[cfg(feature = "async")]
+    async fn unbind_server(&mut self, sids: &[String], server: &str) -> DceResult<usize>;
+
+    #[cfg(not(feature = "async"))]
+    fn unbind_server(&mut self, sids: &[String], server: &str) -> DceResult<usize>;
     
     fn connect(mut self, server: String) -> Arc<Mutex<Self>> {
         self.conn_meta_mut().server_unbound = Some(Some(server));
@@ -3,6 +3,8 @@ pub mod session;
 pub mod user;
 #[cfg(feature = "connection")]
 pub mod connection;
+#[cfg(feature = "cluster")]
+pub mod cluster;
 #[cfg(feature = "auto-renew")]
 pub mod auto;
 #[cfg(feature = "redis")]
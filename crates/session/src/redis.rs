@@ -19,6 +19,9 @@ use crate::connection::{Connection, ConnectionMeta};
 #[cfg(feature = "auto-renew")]
 use crate::auto::AutoRenew;
 
+/// Delete the server field only if it still points to the server, the session may have been reconnected to another server
+#[cfg(feature = "connection")]
+const UNBIND_SERVER_SCRIPT: &str = r#"if redis.call("HGET", KEYS[1], ARGV[1]) == ARGV[2] then return redis.call("HDEL", KEYS[1], ARGV[1]) end return 0"#;
 
 pub type RedisBasic<R> = RedisSession<R, SessionUser>;
 
@@ -402,4 +405,54 @@ impl<R, U> Connection for RedisSession<R, U> where
         let (key, value) = (self.key(), self.conn_meta().server_field().to_string());
         return self.redis()?.hdel(key, value).map_err(DceErr::closed0);
     }
+
+    #[cfg(feature = "async")]
+    async fn servers(&mut self, sids: &[String]) -> DceResult<HashMap<String, String>> {
+        let field = self.conn_meta().server_field();
+        let mut servers = HashMap::new();
+        for sid in sids {
+            let key = Self::gen_key(self.meta.sid_name(), sid.as_str());
+            if let Some(server) = self.redis()?.hget::<_, _, Option<String>>(key, field).await.map_err(DceErr::closed0)? {
+                servers.insert(sid.clone(), server);
+            }
+        }
+        Ok(servers)
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn servers(&mut self, sids: &[String]) -> DceResult<HashMap<String, String>> {
+        let field = self.conn_meta().server_field();
+        let mut servers = HashMap::new();
+        for sid in sids {
+            let key = Self::gen_key(self.meta.sid_name(), sid.as_str());
+            if let Some(server) = self.redis()?.hget::<_, _, Option<String>>(key, field).map_err(DceErr::closed0)? {
+                servers.insert(sid.clone(), server);
+            }
+        }
+        Ok(servers)
+    }
+
+    #[cfg(feature = "async")]
+    async fn unbind_server(&mut self, sids: &[String], server: &str) -> DceResult<usize> {
+        let field = self.conn_meta().server_field();
+        let mut count = 0;
+        for sid in sids {
+            let key = Self::gen_key(self.meta.sid_name(), sid.as_str());
+            count += redis::cmd("EVAL").arg(UNBIND_SERVER_SCRIPT).arg(1).arg(key).arg(field).arg(server)
+                .query_async::<_, usize>(self.redis()?).await.map_err(DceErr::closed0)?;
+        }
+        Ok(count)
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn unbind_server(&mut self, sids: &[String], server: &str) -> DceResult<usize> {
+        let field = self.conn_meta().server_field();
+        let mut count = 0;
+        for sid in sids {
+            let key = Self::gen_key(self.meta.sid_name(), sid.as_str());
+            count += redis::cmd("EVAL").arg(UNBIND_SERVER_SCRIPT).arg(1).arg(key).arg(field).arg(server)
+                .query::<usize>(self.redis()?).map_err(DceErr::closed0)?;
+        }
+        Ok(count)
+    }
 }
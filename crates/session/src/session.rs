@@ -70,7 +70,8 @@ impl Meta {
         Ok(Self {ttl_minutes, create_stamp, sid, sid_name: DEFAULT_ID_NAME, touches: None, #[cfg(feature = "test")] sid_pool })
     }
 
-    fn parse_sid(sid: &str) -> DceResult<(u16, u64)> {
+    /// Parse the ttl minutes and create timestamp from the sid
+    pub fn parse_sid(sid: &str) -> DceResult<(u16, u64)> {
         const MIN_SID_LEN: usize = 76;
         if sid.len() < MIN_SID_LEN { return DceErr::closed0_wrap(format!(r#"invalid sid "{}", less then {} chars"#, sid, MIN_SID_LEN)); }
         let ttl_minutes = u16::from_str_radix(&sid[64..68], 16).map_err(DceErr::closed0)?;
@@ -0,0 +1,80 @@
+use std::sync::Arc;
+use redis::{cmd, Value};
+use redis_test::{MockCmd, MockRedisConnection};
+use serde::{Deserialize, Serialize};
+use tokio::test;
+use dce_session::cluster::{ClusterMessage, ClusterNode, LocalBus};
+use dce_session::redis::RedisSession;
+use dce_session::session::{DEFAULT_TTL_MINUTES, Meta, Session};
+use dce_session::user::{UidGetter, User};
+use dce_util::registry::{ConnectionRegistry, PushTarget};
+use crate::common::redis;
+
+mod common;
+
+type Node = ClusterNode<RedisSession<MockRedisConnection, Member>, Member, String>;
+
+fn node(server: &str, bus: &LocalBus, commands: Vec<MockCmd>) -> Node {
+    let session = RedisSession::<_, Member>::new_with_id(vec![Meta::gen_id(DEFAULT_TTL_MINUTES).unwrap().0]).unwrap().with(redis(commands));
+    ClusterNode::new(server, Arc::new(bus.clone()), ConnectionRegistry::new(), session, |path, body| format!("{path}:{}", String::from_utf8_lossy(&body)))
+}
+
+fn skey(sid: &str) -> String {
+    RedisSession::<MockRedisConnection, Member>::gen_key("dcesid", sid)
+}
+
+#[test]
+async fn forward() {
+    let bus = LocalBus::new();
+    let ukey = RedisSession::<MockRedisConnection, Member>::gen_user_key("dceusmap", 1);
+    let node_a = node("node-a", &bus, vec![
+        MockCmd::new(cmd("HGET").arg(skey("sid-b")).arg("$server"), Ok("node-b")),
+        MockCmd::new(cmd("SMEMBERS").arg(&ukey), Ok(Value::Bulk(vec![Value::Data(b"sid-a".to_vec()), Value::Data(b"sid-b".to_vec())]))),
+        MockCmd::new(cmd("EXISTS").arg(skey("sid-a")), Ok("1")),
+        MockCmd::new(cmd("EXISTS").arg(skey("sid-b")), Ok("1")),
+        MockCmd::new(cmd("HGET").arg(skey("sid-b")).arg("$server"), Ok("node-b")),
+    ]);
+    let node_b = node("node-b", &bus, vec![]);
+    let (a, mut a_pushes) = node_a.registry().register(None);
+    node_a.registry().bind_sid(a, "sid-a".to_string());
+    node_a.registry().bind_uid(a, Some(1));
+    let (b, mut b_pushes) = node_b.registry().register(None);
+    node_b.registry().bind_sid(b, "sid-b".to_string());
+    node_b.serve().await.unwrap();
+
+    assert_eq!(node_a.push(PushTarget::Sid("sid-b".to_string()), "notice", b"hi".to_vec()).await.unwrap(), 1);
+    assert_eq!(b_pushes.recv().await.unwrap(), "notice:hi");
+    // one local and one forwarded
+    assert_eq!(node_a.push(PushTarget::Uid(1), "notice", b"all".to_vec()).await.unwrap(), 2);
+    assert_eq!(a_pushes.try_recv().unwrap(), "notice:all");
+    assert_eq!(b_pushes.recv().await.unwrap(), "notice:all");
+}
+
+#[test]
+async fn shutdown() {
+    let script = r#"if redis.call("HGET", KEYS[1], ARGV[1]) == ARGV[2] then return redis.call("HDEL", KEYS[1], ARGV[1]) end return 0"#;
+    let node = node("node-a", &LocalBus::new(), vec![
+        MockCmd::new(cmd("EVAL").arg(script).arg(1).arg(skey("sid-a")).arg("$server").arg("node-a"), Ok(1)),
+    ]);
+    let (a, _pushes) = node.registry().register(None);
+    node.registry().bind_sid(a, "sid-a".to_string());
+    assert_eq!(node.shutdown().await.unwrap(), 1);
+}
+
+#[test]
+async fn codec() {
+    let message = ClusterMessage::new(vec!["sid-a".to_string(), "sid-b".to_string()], "notice", vec![0, 159, 146, 150]);
+    assert_eq!(ClusterMessage::decode(&message.encode().unwrap()).unwrap(), message);
+    assert!(ClusterMessage::decode(&[0, 1, 0]).is_err());
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+struct Member {
+    id: u64,
+}
+
+impl UidGetter for Member {
+    fn id(&self) -> u64 {
+        self.id
+    }
+}
@@ -1,2 +1,8 @@
 pub mod mixed;
 pub mod atom_tree;
+#[cfg(feature = "tls")]
+pub mod tls;
+#[cfg(feature = "registry")]
+pub mod registry;
+#[cfg(all(feature = "unix", unix))]
+pub mod unix;
@@ -0,0 +1,235 @@
+use std::borrow::Borrow;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{Debug, Formatter};
+use std::hash::Hash;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use tokio::sync::mpsc::{self, Receiver, Sender};
+
+/// The context data key of the [RegisteredConnection], can be got with `context.get_as::<Arc<RegisteredConnection<M>>>(REGISTRY_DATA_NAME)`
+pub const REGISTRY_DATA_NAME: &str = "$#registry#";
+/// The pending pushes of a connection, the later will be dropped if it was full
+pub const PUSH_BUFFER_SIZE: usize = 256;
+
+
+/// Target of the pushes
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PushTarget {
+    /// The connection id assigned by the registry
+    Connection(u64),
+    Sid(String),
+    /// The sids of a user, such as the sids got from `User::sids()` of the dce-session, the sids not connected to this server will be ignored
+    Sids(Vec<String>),
+    /// The connections bound with the uid
+    Uid(u64),
+    Room(String),
+    All,
+}
+
+
+/// A registry of the live connections, the connections could be bound with sid, uid and rooms, then the server could push messages to them.
+/// The clones share the same connections
+pub struct ConnectionRegistry<M> {
+    next_id: Arc<AtomicU64>,
+    inner: Arc<RwLock<Inner<M>>>,
+}
+
+struct Inner<M> {
+    connections: HashMap<u64, Entry<M>>,
+    sids: HashMap<String, HashSet<u64>>,
+    uids: HashMap<u64, HashSet<u64>>,
+    rooms: HashMap<String, HashSet<u64>>,
+}
+
+struct Entry<M> {
+    sender: Sender<M>,
+    peer: Option<SocketAddr>,
+    sid: Option<String>,
+    uid: Option<u64>,
+    rooms: HashSet<String>,
+}
+
+impl<M: Clone> ConnectionRegistry<M> {
+    pub fn new() -> Self {
+        Self {
+            next_id: Arc::new(AtomicU64::new(1)),
+            inner: Arc::new(RwLock::new(Inner { connections: Default::default(), sids: Default::default(), uids: Default::default(), rooms: Default::default() })),
+        }
+    }
+
+    /// Register a connection, returns the connection id and the receiver of the pushed messages, the receiver should be forwarded to the connection
+    pub fn register(&self, peer: Option<SocketAddr>) -> (u64, Receiver<M>) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (sender, receiver) = mpsc::channel(PUSH_BUFFER_SIZE);
+        if let Ok(mut inner) = self.inner.write() {
+            inner.connections.insert(id, Entry { sender, peer, sid: None, uid: None, rooms: Default::default() });
+        }
+        (id, receiver)
+    }
+
+    /// Remove the connection and all its bindings
+    pub fn unregister(&self, id: u64) {
+        let Ok(mut inner) = self.inner.write() else { return };
+        let Some(entry) = inner.connections.remove(&id) else { return };
+        if let Some(sid) = entry.sid {
+            remove_from(&mut inner.sids, &sid, id);
+        }
+        if let Some(uid) = entry.uid {
+            remove_from(&mut inner.uids, &uid, id);
+        }
+        for room in entry.rooms {
+            remove_from(&mut inner.rooms, &room, id);
+        }
+    }
+
+    /// Bind the connection with a sid, the old sid will be replaced, such as after the sid was renewed
+    pub fn bind_sid(&self, id: u64, sid: String) {
+        let Ok(mut inner) = self.inner.write() else { return };
+        let Some(entry) = inner.connections.get_mut(&id) else { return };
+        if entry.sid.as_ref() == Some(&sid) {
+            return;
+        }
+        if let Some(old) = entry.sid.replace(sid.clone()) {
+            remove_from(&mut inner.sids, &old, id);
+        }
+        inner.sids.entry(sid).or_default().insert(id);
+    }
+
+    /// Bind the connection with a uid, such as after the user logged in, None to unbind, such as after logout
+    pub fn bind_uid(&self, id: u64, uid: Option<u64>) {
+        let Ok(mut inner) = self.inner.write() else { return };
+        let Some(entry) = inner.connections.get_mut(&id) else { return };
+        let old = std::mem::replace(&mut entry.uid, uid);
+        if let Some(old) = old {
+            remove_from(&mut inner.uids, &old, id);
+        }
+        if let Some(uid) = uid {
+            inner.uids.entry(uid).or_default().insert(id);
+        }
+    }
+
+    pub fn join<T: ToString>(&self, id: u64, room: T) {
+        let Ok(mut inner) = self.inner.write() else { return };
+        let Some(entry) = inner.connections.get_mut(&id) else { return };
+        let room = room.to_string();
+        if entry.rooms.insert(room.clone()) {
+            inner.rooms.entry(room).or_default().insert(id);
+        }
+    }
+
+    pub fn leave(&self, id: u64, room: &str) {
+        let Ok(mut inner) = self.inner.write() else { return };
+        let Some(entry) = inner.connections.get_mut(&id) else { return };
+        if entry.rooms.remove(room) {
+            remove_from(&mut inner.rooms, room, id);
+        }
+    }
+
+    /// The connection ids of the target
+    pub fn connections(&self, target: &PushTarget) -> Vec<u64> {
+        let Ok(inner) = self.inner.read() else { return vec![] };
+        let ids_of = |map: Option<&HashSet<u64>>| map.map_or_else(Vec::new, |ids| ids.iter().copied().collect());
+        match target {
+            PushTarget::Connection(id) => inner.connections.contains_key(id).then_some(*id).into_iter().collect(),
+            PushTarget::Sid(sid) => ids_of(inner.sids.get(sid)),
+            PushTarget::Sids(sids) => sids.iter().flat_map(|sid| ids_of(inner.sids.get(sid))).collect::<HashSet<_>>().into_iter().collect(),
+            PushTarget::Uid(uid) => ids_of(inner.uids.get(uid)),
+            PushTarget::Room(room) => ids_of(inner.rooms.get(room)),
+            PushTarget::All => inner.connections.keys().copied().collect(),
+        }
+    }
+
+    /// The sids bound with the live connections
+    pub fn sids(&self) -> Vec<String> {
+        self.inner.read().map_or_else(|_| vec![], |inner| inner.sids.keys().cloned().collect())
+    }
+
+    pub fn peer(&self, id: u64) -> Option<SocketAddr> {
+        self.inner.read().ok()?.connections.get(&id)?.peer
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.read().map_or(0, |inner| inner.connections.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Send the message to the target connections, returns the count of the connections which the message was queued to.
+    /// The message will not be queued to the connections which had too many pending pushes
+    pub fn send(&self, target: &PushTarget, message: M) -> usize {
+        let ids = self.connections(target);
+        let Ok(inner) = self.inner.read() else { return 0 };
+        ids.iter().filter_map(|id| inner.connections.get(id))
+            .filter(|entry| entry.sender.try_send(message.clone()).is_ok())
+            .count()
+    }
+}
+
+/// A connection registered in the registry, the connection drivers will unregister it after the connection closed
+#[derive(Debug, Clone)]
+pub struct RegisteredConnection<M> {
+    id: u64,
+    registry: ConnectionRegistry<M>,
+}
+
+impl<M: Clone> RegisteredConnection<M> {
+    pub fn new(id: u64, registry: ConnectionRegistry<M>) -> Self {
+        Self { id, registry }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn registry(&self) -> &ConnectionRegistry<M> {
+        &self.registry
+    }
+
+    pub fn bind_sid(&self, sid: String) {
+        self.registry.bind_sid(self.id, sid)
+    }
+
+    pub fn bind_uid(&self, uid: Option<u64>) {
+        self.registry.bind_uid(self.id, uid)
+    }
+
+    pub fn join<T: ToString>(&self, room: T) {
+        self.registry.join(self.id, room)
+    }
+
+    pub fn leave(&self, room: &str) {
+        self.registry.leave(self.id, room)
+    }
+}
+
+
+impl<M: Clone> Default for ConnectionRegistry<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M> Clone for ConnectionRegistry<M> {
+    fn clone(&self) -> Self {
+        Self { next_id: self.next_id.clone(), inner: self.inner.clone() }
+    }
+}
+
+impl<M> Debug for ConnectionRegistry<M> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let connections = self.inner.read().map_or(0, |inner| inner.connections.len());
+        f.write_str(format!("ConnectionRegistry {{ connections: {connections} }}").as_str())
+    }
+}
+
+fn remove_from<K: Hash + Eq + Borrow<Q>, Q: Hash + Eq + ?Sized>(map: &mut HashMap<K, HashSet<u64>>, key: &Q, id: u64) {
+    if let Some(ids) = map.get_mut(key) {
+        ids.remove(&id);
+        if ids.is_empty() {
+            map.remove(key);
+        }
+    }
+}
@@ -0,0 +1,289 @@
+use std::fmt::{Debug, Formatter};
+use std::io::{Error, ErrorKind, Result as IoResult};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, RwLock, Weak};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime};
+use log::{error, info, warn};
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use rustls_pki_types::pem::PemObject;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::Interval;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::server::WebPkiClientVerifier;
+use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+use tokio_rustls::server::TlsStream;
+use crate::mixed::{DceErr, DceResult};
+
+/// The context data key of the [PeerIdentity], can be got with `context.get_as::<Arc<PeerIdentity>>(PEER_IDENTITY_DATA_NAME)`
+pub const PEER_IDENTITY_DATA_NAME: &str = "$#peer_identity#";
+const DEFAULT_WATCH_INTERVAL_SECONDS: u64 = 10;
+const DEFAULT_HANDSHAKE_TIMEOUT_SECONDS: u64 = 10;
+
+
+/// Tls config of the listeners, the certificate and key should be PEM files
+#[derive(Debug, Clone)]
+pub struct TlsConfig {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    client_ca_path: Option<PathBuf>,
+    client_auth_required: bool,
+    alpn_protocols: Vec<Vec<u8>>,
+    watch_interval: Option<Duration>,
+    handshake_timeout: Duration,
+}
+
+impl TlsConfig {
+    pub fn new<C: AsRef<Path>, K: AsRef<Path>>(cert_path: C, key_path: K) -> Self {
+        Self {
+            cert_path: cert_path.as_ref().to_path_buf(),
+            key_path: key_path.as_ref().to_path_buf(),
+            client_ca_path: None,
+            client_auth_required: false,
+            alpn_protocols: vec![],
+            watch_interval: Some(Duration::from_secs(DEFAULT_WATCH_INTERVAL_SECONDS)),
+            handshake_timeout: Duration::from_secs(DEFAULT_HANDSHAKE_TIMEOUT_SECONDS),
+        }
+    }
+
+    /// Verify the client certificates with the CA PEM file, the clients without certificate will be rejected if required
+    pub fn client_auth<P: AsRef<Path>>(mut self, ca_path: P, required: bool) -> Self {
+        self.client_ca_path = Some(ca_path.as_ref().to_path_buf());
+        self.client_auth_required = required;
+        self
+    }
+
+    /// Set the ALPN protocols in the order of preference, e.g. `["h2", "http/1.1"]`
+    pub fn alpn<I: IntoIterator<Item = T>, T: AsRef<[u8]>>(mut self, protocols: I) -> Self {
+        self.alpn_protocols = protocols.into_iter().map(|p| p.as_ref().to_vec()).collect();
+        self
+    }
+
+    /// Set the interval to check whether the PEM files changed, default 10 seconds, None to disable
+    pub fn watch_interval(mut self, interval: Option<Duration>) -> Self {
+        self.watch_interval = interval;
+        self
+    }
+
+    /// Set the max duration of the handshakes, the slow clients will be dropped to keep them from holding the connections, default 10 seconds
+    pub fn handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+
+    fn paths(&self) -> impl Iterator<Item = &PathBuf> {
+        [&self.cert_path, &self.key_path].into_iter().chain(self.client_ca_path.iter())
+    }
+
+    fn load(&self) -> DceResult<ServerConfig> {
+        let certs = CertificateDer::pem_file_iter(&self.cert_path).and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| DceErr::closed0(format!("cannot load certificates from {}: {e}", self.cert_path.display())))?;
+        let key = PrivateKeyDer::from_pem_file(&self.key_path)
+            .map_err(|e| DceErr::closed0(format!("cannot load private key from {}: {e}", self.key_path.display())))?;
+        let provider = Arc::new(ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone()).with_safe_default_protocol_versions().map_err(DceErr::closed0)?;
+        let builder = match &self.client_ca_path {
+            Some(ca_path) => {
+                let mut roots = RootCertStore::empty();
+                for ca in CertificateDer::pem_file_iter(ca_path).map_err(|e| DceErr::closed0(format!("cannot load client CA from {}: {e}", ca_path.display())))? {
+                    roots.add(ca.map_err(DceErr::closed0)?).map_err(DceErr::closed0)?;
+                }
+                let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+                let verifier = if self.client_auth_required { verifier } else { verifier.allow_unauthenticated() };
+                builder.with_client_cert_verifier(verifier.build().map_err(DceErr::closed0)?)
+            },
+            None => builder.with_no_client_auth(),
+        };
+        let mut config = builder.with_single_cert(certs, key).map_err(DceErr::closed0)?;
+        config.alpn_protocols = self.alpn_protocols.clone();
+        Ok(config)
+    }
+}
+
+
+/// A reloadable tls acceptor, the certificates will be reloaded on `SIGHUP` or when the PEM files changed,
+/// the old certificates will be kept if failed to reload
+pub struct TlsAcceptor {
+    config: TlsConfig,
+    acceptor: RwLock<tokio_rustls::TlsAcceptor>,
+    modified: Mutex<Vec<Option<SystemTime>>>,
+}
+
+impl Debug for TlsAcceptor {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TlsAcceptor").field("config", &self.config).finish()
+    }
+}
+
+impl TlsAcceptor {
+    /// Load the certificates and start the reload watcher if in a tokio runtime
+    pub fn new(config: TlsConfig) -> DceResult<Arc<Self>> {
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config.load()?));
+        let modified = Mutex::new(Self::modified_stamps(&config));
+        let acceptor = Arc::new(Self { config, acceptor: RwLock::new(acceptor), modified });
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => { handle.spawn(Self::watch(Arc::downgrade(&acceptor))); },
+            Err(_) => warn!("not in a tokio runtime, the tls certificates will not be reloaded automatically"),
+        }
+        Ok(acceptor)
+    }
+
+    /// Reload the certificates, the new handshakes will use the new certificates
+    pub fn reload(&self) -> DceResult<()> {
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(self.config.load()?));
+        *self.acceptor.write().map_err(DceErr::closed0)? = acceptor;
+        Ok(())
+    }
+
+    /// Accept a tls stream, a `TimedOut` error will be returned if the handshake was not finished within the handshake timeout
+    pub async fn accept<S: AsyncRead + AsyncWrite + Unpin>(&self, stream: S) -> IoResult<TlsStream<S>> {
+        let acceptor = self.acceptor.read().map_err(|e| Error::other(e.to_string()))?.clone();
+        tokio::time::timeout(self.config.handshake_timeout, acceptor.accept(stream)).await
+            .map_err(|_| Error::new(ErrorKind::TimedOut, "tls handshake timed out"))?
+    }
+
+    /// Accept a stream in tls if the acceptor was given, or else pass it through, the [PeerIdentity] of the tls stream will be returned too.
+    /// It was the common part of the listeners, they could put the identity into the context data with key [PEER_IDENTITY_DATA_NAME]
+    pub async fn accept_optional<S: AsyncRead + AsyncWrite + Unpin>(acceptor: Option<&Self>, stream: S) -> IoResult<(MaybeTlsStream<S>, Option<Arc<PeerIdentity>>)> {
+        let Some(acceptor) = acceptor else { return Ok((MaybeTlsStream::Plain(stream), None)) };
+        let stream = acceptor.accept(stream).await?;
+        let identity = Arc::new(PeerIdentity::from_stream(&stream));
+        Ok((MaybeTlsStream::Tls(Box::new(stream)), Some(identity)))
+    }
+
+    fn modified_stamps(config: &TlsConfig) -> Vec<Option<SystemTime>> {
+        config.paths().map(|p| p.metadata().and_then(|m| m.modified()).ok()).collect()
+    }
+
+    fn files_changed(&self) -> bool {
+        let stamps = Self::modified_stamps(&self.config);
+        let Ok(mut modified) = self.modified.lock() else { return false };
+        if *modified == stamps { return false; }
+        *modified = stamps;
+        true
+    }
+
+    async fn watch(acceptor: Weak<Self>) {
+        #[cfg(unix)]
+        let mut hangup = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+            .map_err(|e| warn!("cannot listen the SIGHUP signal: {e}")).ok();
+        #[cfg(not(unix))]
+        let mut hangup: Option<()> = None;
+        let mut interval = acceptor.upgrade().and_then(|a| a.config.watch_interval).map(tokio::time::interval);
+        loop {
+            let by_signal = tokio::select! {
+                received = Self::hangup(&mut hangup) => received,
+                _ = Self::tick(&mut interval) => false,
+            };
+            // stop watching after the acceptor dropped
+            let Some(acceptor) = acceptor.upgrade() else { break };
+            if by_signal || acceptor.files_changed() {
+                match acceptor.reload() {
+                    Ok(_) => info!("tls certificates reloaded from {}", acceptor.config.cert_path.display()),
+                    Err(err) => error!("failed to reload tls certificates, keep using the old: {err}"),
+                }
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    async fn hangup(hangup: &mut Option<tokio::signal::unix::Signal>) -> bool {
+        if let Some(signal) = hangup {
+            if signal.recv().await.is_some() { return true; }
+        }
+        *hangup = None;
+        std::future::pending().await
+    }
+
+    #[cfg(not(unix))]
+    async fn hangup(_: &mut Option<()>) -> bool {
+        std::future::pending().await
+    }
+
+    async fn tick(interval: &mut Option<Interval>) {
+        match interval {
+            Some(interval) => { interval.tick().await; },
+            None => std::future::pending().await,
+        }
+    }
+}
+
+
+/// The peer info of a tls connection, the certificates is the DER chain of the client, could be parsed by x509 libs
+#[derive(Debug, Clone)]
+pub struct PeerIdentity {
+    certificates: Vec<CertificateDer<'static>>,
+    server_name: Option<String>,
+    alpn_protocol: Option<Vec<u8>>,
+}
+
+impl PeerIdentity {
+    pub fn from_stream<S>(stream: &TlsStream<S>) -> Self {
+        let connection = stream.get_ref().1;
+        Self {
+            certificates: connection.peer_certificates().map_or_else(Vec::new, |certs| certs.iter().map(|c| c.clone().into_owned()).collect()),
+            server_name: connection.server_name().map(ToString::to_string),
+            alpn_protocol: connection.alpn_protocol().map(<[u8]>::to_vec),
+        }
+    }
+
+    /// Whether the client presented a verified certificate
+    pub fn is_authenticated(&self) -> bool {
+        ! self.certificates.is_empty()
+    }
+
+    /// The client certificate chain, the end entity certificate first
+    pub fn certificates(&self) -> &[CertificateDer<'static>] {
+        &self.certificates
+    }
+
+    /// The SNI host name the client requested
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_deref()
+    }
+
+    pub fn alpn_protocol(&self) -> Option<&[u8]> {
+        self.alpn_protocol.as_deref()
+    }
+}
+
+
+/// A stream accepted by [TlsAcceptor::accept_optional]
+#[derive(Debug)]
+pub enum MaybeTlsStream<S> {
+    Plain(S),
+    Tls(Box<TlsStream<S>>),
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncRead for MaybeTlsStream<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<IoResult<()>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            Self::Tls(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWrite for MaybeTlsStream<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<IoResult<usize>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            Self::Tls(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            Self::Tls(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<IoResult<()>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            Self::Tls(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
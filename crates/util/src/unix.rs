@@ -0,0 +1,133 @@
+use std::io::{Error, ErrorKind, Result as IoResult};
+#[cfg(target_os = "linux")]
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use log::{debug, warn};
+use tokio::net::{UnixListener, UnixStream};
+
+/// The context data key of the [PeerCred], can be got with `context.get_as::<Arc<PeerCred>>(PEER_CRED_DATA_NAME)`
+pub const PEER_CRED_DATA_NAME: &str = "$#peer_cred#";
+/// The prefix of the abstract socket names, such as `@dce.sock`
+pub const ABSTRACT_PREFIX: char = '@';
+
+
+/// A unix socket address, the path prefixed with `@` means an abstract socket name, which is linux only and will not create a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UnixAddr {
+    Path(PathBuf),
+    Abstract(String),
+}
+
+impl UnixAddr {
+    pub fn new<P: AsRef<Path>>(addr: P) -> Self {
+        let addr = addr.as_ref();
+        match addr.to_str().and_then(|addr| addr.strip_prefix(ABSTRACT_PREFIX)) {
+            Some(name) => Self::Abstract(name.to_string()),
+            None => Self::Path(addr.to_path_buf()),
+        }
+    }
+
+    /// Bind a listener on the address. A stale socket file left by a crashed process will be removed before binding,
+    /// but an alive one will not, an `AddrInUse` error will be returned instead, and the non-socket files will never be removed
+    pub fn bind(&self) -> IoResult<UnixListener> {
+        let listener = std::os::unix::net::UnixListener::bind_addr(&self.socket_addr()?).or_else(|err| match self {
+            Self::Path(path) if err.kind() == ErrorKind::AddrInUse && Self::remove_stale(path)? => std::os::unix::net::UnixListener::bind(path),
+            _ => Err(err),
+        })?;
+        listener.set_nonblocking(true)?;
+        UnixListener::from_std(listener)
+    }
+
+    pub async fn connect(&self) -> IoResult<UnixStream> {
+        let stream = std::os::unix::net::UnixStream::connect_addr(&self.socket_addr()?)?;
+        stream.set_nonblocking(true)?;
+        UnixStream::from_std(stream)
+    }
+
+    /// Remove the socket file after the listener closed, nothing to do for the abstract names
+    pub fn unlink(&self) -> IoResult<()> {
+        match self {
+            Self::Path(path) if path.exists() => std::fs::remove_file(path),
+            _ => Ok(()),
+        }
+    }
+
+    fn socket_addr(&self) -> IoResult<SocketAddr> {
+        match self {
+            Self::Path(path) => SocketAddr::from_pathname(path),
+            #[cfg(target_os = "linux")]
+            Self::Abstract(name) => SocketAddr::from_abstract_name(name),
+            #[cfg(not(target_os = "linux"))]
+            Self::Abstract(name) => Err(Error::new(ErrorKind::Unsupported, format!("abstract socket \"{ABSTRACT_PREFIX}{name}\" is linux only"))),
+        }
+    }
+
+    /// Returns true if the path was a socket that nobody listening on, and it was removed
+    fn remove_stale(path: &Path) -> IoResult<bool> {
+        if ! std::fs::symlink_metadata(path)?.file_type().is_socket() {
+            return Ok(false);
+        }
+        match std::os::unix::net::UnixStream::connect(path) {
+            Ok(_) => Err(Error::new(ErrorKind::AddrInUse, format!("{} is listening by another process", path.display()))),
+            Err(err) if err.kind() == ErrorKind::ConnectionRefused => {
+                warn!("removing the stale socket file {}", path.display());
+                std::fs::remove_file(path)?;
+                Ok(true)
+            },
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl std::fmt::Display for UnixAddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Path(path) => write!(f, "{}", path.display()),
+            Self::Abstract(name) => write!(f, "{ABSTRACT_PREFIX}{name}"),
+        }
+    }
+}
+
+
+/// The credentials of the peer process of a unix stream, got from `SO_PEERCRED` or the similar on other platforms,
+/// could be used to authorize the local clients
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerCred {
+    uid: u32,
+    gid: u32,
+    pid: Option<i32>,
+}
+
+impl PeerCred {
+    pub fn from_stream(stream: &UnixStream) -> IoResult<Self> {
+        let cred = stream.peer_cred()?;
+        Ok(Self { uid: cred.uid(), gid: cred.gid(), pid: cred.pid() })
+    }
+
+    pub fn uid(&self) -> u32 {
+        self.uid
+    }
+
+    pub fn gid(&self) -> u32 {
+        self.gid
+    }
+
+    /// The pid of the peer process, could be None on some platforms
+    pub fn pid(&self) -> Option<i32> {
+        self.pid
+    }
+}
+
+
+/// Accept the unix streams with the peer credentials, the streams which credentials could not be got will be dropped
+pub async fn accept(listener: &UnixListener) -> IoResult<(UnixStream, PeerCred)> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        match PeerCred::from_stream(&stream) {
+            Ok(cred) => return Ok((stream, cred)),
+            Err(err) => debug!("dropped a unix stream without peer credentials: {err}"),
+        }
+    }
+}
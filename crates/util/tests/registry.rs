@@ -0,0 +1,29 @@
+use dce_util::registry::{ConnectionRegistry, PushTarget, RegisteredConnection};
+
+#[test]
+fn bindings() {
+    let registry = ConnectionRegistry::<String>::new();
+    let (a, mut a_pushes) = registry.register(None);
+    let (b, mut b_pushes) = registry.register(None);
+    let a = RegisteredConnection::new(a, registry.clone());
+    a.bind_sid("sid-a".to_string());
+    a.bind_uid(Some(1));
+    a.join("lobby");
+    registry.bind_uid(b, Some(1));
+
+    assert_eq!(registry.send(&PushTarget::Uid(1), "to user".to_string()), 2);
+    assert_eq!(registry.send(&PushTarget::Sids(vec!["sid-a".to_string(), "sid-other".to_string()]), "to sids".to_string()), 1);
+    assert_eq!(registry.send(&PushTarget::Room("lobby".to_string()), "to lobby".to_string()), 1);
+    assert_eq!(a_pushes.try_recv().unwrap(), "to user");
+    assert_eq!(a_pushes.try_recv().unwrap(), "to sids");
+    assert_eq!(a_pushes.try_recv().unwrap(), "to lobby");
+    assert_eq!(b_pushes.try_recv().unwrap(), "to user");
+    assert!(b_pushes.try_recv().is_err());
+
+    a.leave("lobby");
+    assert_eq!(registry.send(&PushTarget::Room("lobby".to_string()), "to lobby".to_string()), 0);
+    registry.unregister(a.id());
+    assert_eq!(registry.send(&PushTarget::Uid(1), "to user".to_string()), 1);
+    assert_eq!(registry.send(&PushTarget::Sid("sid-a".to_string()), "to sid".to_string()), 0);
+    assert_eq!(registry.len(), 1);
+}
@@ -0,0 +1,124 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::io::ErrorKind;
+use std::sync::Arc;
+use std::time::Duration;
+use rcgen::{BasicConstraints, CertificateParams, CertifiedKey, ExtendedKeyUsagePurpose, IsCa, KeyPair};
+use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+use tokio::test;
+use tokio_rustls::rustls::crypto::ring;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName};
+use tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+use dce_util::tls::{MaybeTlsStream, PeerIdentity, TlsAcceptor, TlsConfig};
+
+#[test]
+async fn reload() {
+    let dir = temp_dir("reload");
+    let ca = ca();
+    let first = leaf(&ca, ExtendedKeyUsagePurpose::ServerAuth);
+    write_pem(&dir, "server", &first);
+    let acceptor = TlsAcceptor::new(TlsConfig::new(dir.join("server.crt"), dir.join("server.key")).watch_interval(None)).unwrap();
+    assert_eq!(handshake(&acceptor, &ca, None).await.unwrap().0, first.cert.der().to_vec());
+
+    let second = leaf(&ca, ExtendedKeyUsagePurpose::ServerAuth);
+    write_pem(&dir, "server", &second);
+    acceptor.reload().unwrap();
+    assert_eq!(handshake(&acceptor, &ca, None).await.unwrap().0, second.cert.der().to_vec());
+
+    // keep the old certificates if failed to reload
+    fs::write(dir.join("server.key"), "broken").unwrap();
+    assert!(acceptor.reload().is_err());
+    assert_eq!(handshake(&acceptor, &ca, None).await.unwrap().0, second.cert.der().to_vec());
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+async fn client_auth() {
+    let dir = temp_dir("client_auth");
+    let ca = ca();
+    write_pem(&dir, "server", &leaf(&ca, ExtendedKeyUsagePurpose::ServerAuth));
+    fs::write(dir.join("ca.crt"), ca.cert.pem()).unwrap();
+    let config = TlsConfig::new(dir.join("server.crt"), dir.join("server.key")).watch_interval(None);
+    let client = leaf(&ca, ExtendedKeyUsagePurpose::ClientAuth);
+
+    let required = TlsAcceptor::new(config.clone().client_auth(dir.join("ca.crt"), true)).unwrap();
+    assert!(handshake(&required, &ca, None).await.is_none());
+    let (_, identity) = handshake(&required, &ca, Some(&client)).await.unwrap();
+    assert!(identity.is_authenticated());
+    assert_eq!(identity.certificates()[0].to_vec(), client.cert.der().to_vec());
+    assert_eq!(identity.server_name(), Some("localhost"));
+
+    let optional = TlsAcceptor::new(config.client_auth(dir.join("ca.crt"), false)).unwrap();
+    assert!(! handshake(&optional, &ca, None).await.unwrap().1.is_authenticated());
+    let _ = fs::remove_dir_all(dir);
+}
+
+#[test]
+async fn accept_timeout() {
+    let dir = temp_dir("accept_timeout");
+    let ca = ca();
+    write_pem(&dir, "server", &leaf(&ca, ExtendedKeyUsagePurpose::ServerAuth));
+    let acceptor = TlsAcceptor::new(TlsConfig::new(dir.join("server.crt"), dir.join("server.key"))
+        .watch_interval(None).handshake_timeout(Duration::from_millis(100))).unwrap();
+    // a silent client would not hold the connection longer than the handshake timeout
+    let (_client_io, server_io) = duplex(1024);
+    let err = TlsAcceptor::accept_optional(Some(&acceptor), server_io).await.unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::TimedOut);
+    assert!(handshake(&acceptor, &ca, None).await.is_some());
+
+    // pass through the plain streams without identity
+    let (mut client_io, server_io) = duplex(1024);
+    let (mut stream, identity) = TlsAcceptor::accept_optional(None, server_io).await.unwrap();
+    assert!(matches!(stream, MaybeTlsStream::Plain(_)) && identity.is_none());
+    client_io.write_all(b"ping").await.unwrap();
+    let mut buf = [0; 4];
+    stream.read_exact(&mut buf).await.unwrap();
+    assert_eq!(&buf, b"ping");
+    let _ = fs::remove_dir_all(dir);
+}
+
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("dce-util-tls-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn ca() -> CertifiedKey {
+    let key_pair = KeyPair::generate().unwrap();
+    let mut params = CertificateParams::new(vec![]).unwrap();
+    params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    CertifiedKey { cert: params.self_signed(&key_pair).unwrap(), key_pair }
+}
+
+fn leaf(ca: &CertifiedKey, usage: ExtendedKeyUsagePurpose) -> CertifiedKey {
+    let key_pair = KeyPair::generate().unwrap();
+    let mut params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+    params.extended_key_usages = vec![usage];
+    CertifiedKey { cert: params.signed_by(&key_pair, &ca.cert, &ca.key_pair).unwrap(), key_pair }
+}
+
+fn write_pem(dir: &Path, name: &str, certified: &CertifiedKey) {
+    fs::write(dir.join(format!("{name}.crt")), certified.cert.pem()).unwrap();
+    fs::write(dir.join(format!("{name}.key")), certified.key_pair.serialize_pem()).unwrap();
+}
+
+/// Returns the server certificate the client received and the client identity the server got, or None if handshake failed
+async fn handshake(acceptor: &TlsAcceptor, ca: &CertifiedKey, client: Option<&CertifiedKey>) -> Option<(Vec<u8>, PeerIdentity)> {
+    let mut roots = RootCertStore::empty();
+    roots.add(ca.cert.der().clone()).unwrap();
+    let builder = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions().unwrap().with_root_certificates(roots);
+    let config = match client {
+        Some(client) => builder.with_client_auth_cert(vec![client.cert.der().clone()],
+            PrivateKeyDer::try_from(client.key_pair.serialize_der()).unwrap()).unwrap(),
+        None => builder.with_no_client_auth(),
+    };
+    let (client_io, server_io) = duplex(16 * 1024);
+    let connector = TlsConnector::from(Arc::new(config));
+    let (server, client) = tokio::join!(acceptor.accept(server_io), connector.connect(ServerName::try_from("localhost").unwrap(), client_io));
+    let (server, client) = (server.ok()?, client.ok()?);
+    let server_cert: &CertificateDer = client.get_ref().1.peer_certificates()?.first()?;
+    Some((server_cert.to_vec(), PeerIdentity::from_stream(&server)))
+}
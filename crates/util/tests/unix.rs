@@ -0,0 +1,45 @@
+#![cfg(unix)]
+
+use std::io::ErrorKind;
+use std::os::unix::fs::MetadataExt;
+use tokio::test;
+use dce_util::unix::{accept, UnixAddr};
+
+#[test]
+async fn bind() {
+    let dir = std::env::temp_dir().join(format!("dce-unix-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let addr = UnixAddr::new(dir.join("bind.sock"));
+    // a stale socket file left by a dropped listener
+    drop(addr.bind().unwrap());
+    let listener = addr.bind().unwrap();
+    assert_eq!(addr.bind().unwrap_err().kind(), ErrorKind::AddrInUse);
+
+    let client = tokio::spawn({
+        let addr = addr.clone();
+        async move { addr.connect().await.unwrap() }
+    });
+    let (_stream, cred) = accept(&listener).await.unwrap();
+    let _client = client.await.unwrap();
+    let owner = std::fs::metadata(&dir).unwrap();
+    assert_eq!((cred.uid(), cred.gid()), (owner.uid(), owner.gid()));
+    assert_eq!(cred.pid(), Some(std::process::id() as i32));
+
+    drop(listener);
+    addr.unlink().unwrap();
+    // the regular files will not be taken as the stale sockets
+    std::fs::write(dir.join("regular"), b"").unwrap();
+    assert!(UnixAddr::new(dir.join("regular")).bind().is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+async fn abstract_name() {
+    let addr = UnixAddr::new(format!("@dce-unix-{}", std::process::id()));
+    assert!(matches!(&addr, UnixAddr::Abstract(name) if ! name.starts_with('@')));
+    let listener = addr.bind().unwrap();
+    let _client = addr.connect().await.unwrap();
+    assert!(accept(&listener).await.is_ok());
+    assert!(! std::path::Path::new(&addr.to_string()).exists());
+}
@@ -13,10 +13,16 @@ use dce_cli::protocol::{CliProtocol, CliRaw};
 use dce_router::router::Router;
 use dce_router::serializer::Serialized;
 use dce_util::mixed::DceErr;
+#[cfg(unix)]
+use dce_util::unix::UnixAddr;
 use rand::random;
 use tokio_tungstenite::tungstenite::client::IntoClientRequest;
 use tokio_tungstenite::tungstenite::http::HeaderValue;
 use dce_macro::{api, closed_err};
+use dce_tokio::client::SemiTcpClient;
+use dce_tokio::codec::{Frame, SemiTcpCodec};
+use dce_tokio_tungstenite::client::{SemiWebsocketClient, SemiWebsocketMessage};
+use dce_tokio_tungstenite::envelope::PROTOCOL_HEADER;
 
 pub fn append(router: Router<CliProtocol>) -> Router<CliProtocol> {
     router.push(tcp_interactive)
@@ -28,7 +34,7 @@ pub fn append(router: Router<CliProtocol>) -> Router<CliProtocol> {
 }
 
 
-/// `cargo run --bin app --target-dir target/tcp-interactive -- tcp interactive 127.0.0.1:2048`
+/// `cargo run --bin app --target-dir target/tcp-interactive -- tcp interactive 127.0.0.1:2048`, the server should be started with `mode=text`
 /// and then type in:
 /// `hello>BODY>>>`
 #[api("tcp/interactive/{address}")]
@@ -47,7 +53,7 @@ pub async fn tcp_interactive(req: CliRaw) {
     }
 }
 
-/// `cargo run --bin app --target-dir target/udp-interactive -- udp interactive 127.0.0.1:2049`
+/// `cargo run --bin app --target-dir target/udp-interactive -- udp interactive 127.0.0.1:2049`, the server should be started with `mode=text`
 /// and then type in:
 /// `hello>BODY>>>`
 #[api("udp/interactive/{address}")]
@@ -116,21 +122,20 @@ pub async fn websocket_interactive(req: CliRaw) {
 
 /// `cargo run --bin app -- tcp 127.0.0.1:2048 -- hello`
 /// `cargo run --bin app -- tcp 127.0.0.1:2048 -- echo "echo me"`
+/// `cargo run --bin app -- tcp unix:@dce-tcp -- hello`, connect to a unix socket server
 #[api("tcp/{address}")]
 pub async fn tcp(req: CliRaw) {
-    let addr = req.param("address")?.as_str().unwrap().parse::<SocketAddr>().expect("not a valid socket address");
-    let stream = TcpStream::connect(addr).await.expect("tcp connect failed");
-    let (mut sink, mut stream) = BytesCodec::new().framed(stream).split();
+    let addr = req.param("address")?.as_str().unwrap();
+    let client = match addr.strip_prefix("unix:") {
+        #[cfg(unix)]
+        Some(addr) => SemiTcpClient::connect_unix(&UnixAddr::new(addr), None).await?,
+        _ => SemiTcpClient::connect(addr.parse::<SocketAddr>().expect("not a valid socket address"), None).await?,
+    };
 
     let pass = req.rp().pass();
     assert!(! pass.is_empty(), "pass args cannot be empty");
-    match sink.send(Bytes::from(format!("0;{}>BODY>>>{}", pass.join("/"), random::<usize>()))).await {
-        Ok(_) => match stream.next().await {
-            Some(Ok(msg)) => req.pack(Serialized::Bytes(msg.freeze())),
-            _ => Err(closed_err!("failed to receive message")),
-        },
-        Err(err) => DceErr::closed0_wrap(err),
-    }
+    let frame = client.request(Frame::new(pass.join("/")).body(random::<usize>().to_string().as_str())).await?;
+    req.pack(Serialized::Bytes(frame.body.freeze()))
 }
 
 /// `cargo run --bin app -- udp 127.0.0.1:2049 -- hello`
@@ -140,13 +145,13 @@ pub async fn udp(req: CliRaw) {
     let addr = req.param("address")?.as_str().unwrap().parse::<SocketAddr>().expect("not a valid socket address");
     let socket = UdpSocket::bind("0.0.0.0:0".parse::<SocketAddr>().unwrap()).await.expect("udp connect failed");
     socket.connect(&addr).await.unwrap();
-    let (mut sink, mut stream) = UdpFramed::new(socket, BytesCodec::new()).split();
+    let (mut sink, mut stream) = UdpFramed::new(socket, SemiTcpCodec::binary()).split();
 
     let pass = req.rp().pass();
     assert!(! pass.is_empty(), "pass args cannot be empty");
-    match sink.send((Bytes::from(format!("0;{}>BODY>>>{}", pass.join("/"), random::<usize>())), addr)).await {
+    match sink.send((Frame::new(pass.join("/")).id(0).body(random::<usize>().to_string().as_str()), addr)).await {
         Ok(_) => match stream.next().await {
-            Some(Ok((msg, _))) => req.pack(Serialized::Bytes(msg.freeze())),
+            Some(Ok((frame, _))) => req.pack(Serialized::Bytes(frame.body.freeze())),
             _ => Err(closed_err!("failed to receive message")),
         },
         Err(err) => DceErr::closed0_wrap(err),
@@ -155,25 +160,28 @@ pub async fn udp(req: CliRaw) {
 
 /// `cargo run --bin app -- websocket 127.0.0.1:2047 -- hello`
 /// `cargo run --bin app -- websocket 127.0.0.1:2047 -- echo "echo me"`
+/// `cargo run --bin app -- websocket 127.0.0.1:2047 --framing dce.envelope -- echo`, request in the JSON envelope framing
 #[api("websocket/{address}")]
 pub async fn websocket(req: CliRaw) {
     let addr = req.param("address")?.as_str().unwrap();    
     let url = Url::parse(&format!("ws://{}/", addr)).unwrap();
     let mut ws_req = url.into_client_request().unwrap();
-    if let Some(sid) = req.rp().args().get("--sid") {
+    let sid = req.rp().args().get("--sid").cloned();
+    if let Some(sid) = &sid {
         ws_req.headers_mut().insert("X-Session-Id", HeaderValue::from_str(sid).unwrap());
     }
+    if let Some(subprotocol) = req.rp().args().get("--framing") {
+        ws_req.headers_mut().insert(PROTOCOL_HEADER, HeaderValue::from_str(subprotocol).unwrap());
+    }
     let data = req.rp().args().get("--data").map_or_else(|| random::<usize>().to_string(), Clone::clone);
-    let (ws_stream, _) = connect_async(ws_req).await.expect("Failed to connect");
-    let (mut sink, mut stream) = ws_stream.split();
+    let client = SemiWebsocketClient::connect(ws_req, None).await?.with_sid(sid.clone());
 
     let pass = req.rp().pass();
     assert!(! pass.is_empty(), "pass args cannot be empty");
-    match sink.send(Message::from(format!("0;{}>BODY>>>{}", pass.join("/"), data))).await {
-        Ok(_) => match stream.next().await {
-            Some(Ok(msg)) => req.pack(Serialized::String(msg.to_string())),
-            _ => Err(closed_err!("failed to receive message")),
-        },
-        Err(err) => DceErr::closed0_wrap(err),
-    }
+    let msg = client.request(SemiWebsocketMessage::new(&pass.join("/")).body(data)).await?;
+    let body = String::from_utf8_lossy(&msg.body).to_string();
+    req.pack(Serialized::String(match client.sid() {
+        Some(new_sid) if Some(&new_sid) != sid.as_ref() => format!("{body}\nRenewed sid: {new_sid}"),
+        _ => body,
+    }))
 }
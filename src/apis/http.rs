@@ -1,31 +1,53 @@
 use std::net::SocketAddr;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 use http_body_util::{BodyExt, Full};
-use hyper::Response;
-use hyper::server::conn::http1;
-use hyper::service::service_fn;
-use hyper_util::rt::TokioIo;
-use log::info;
+use hyper::{Response, StatusCode, Uri};
 use sailfish::TemplateOnce;
-use dce_hyper::protocol::HttpMethod::{Get, Options, Post};
+use dce_hyper::server::ServeOptions;
+use dce_hyper::protocol::HttpMethod::{Get, Post};
 use dce_router::api::EventHandler;
+use dce_router::protocol::MAX_BODY_EXTRA_NAME;
 use dce_router::request::{PathParam, Context};
 use dce_router::router::Router;
 use dce_router::serializer::JsonSerializer;
 use serde::{Deserialize, Serialize};
-use tokio::net::TcpListener;
 use dce_cli::protocol::CliRaw;
 use dce_hyper::protocol::{Http, HttpGet, HttpRaw, HyperHttpProtocol};
 use dce_hyper::serializer::SailfishSerializer;
+use dce_hyper::cookie::{Cookie, SameSite};
+use dce_hyper::cache::{CachePolicy, ResponseCache, RESPONSE_CACHE_EXTRA_NAME};
+use dce_hyper::compression::{Compression, COMPRESSION_EXTRA_NAME};
+use dce_hyper::cors::{Cors, CORS_EXTRA_NAME};
+use dce_hyper::proxy::{ProxyResponder, Upstream};
+use dce_hyper::response::ResponseBuilder;
+use dce_hyper::sse::{Event, SseResponder};
+use dce_hyper::static_file::{StaticDir, StaticResponder};
+use dce_hyper::vhost::Dispatcher;
+use dce_hyper::websocket::{Handshake, HANDSHAKE_DATA_NAME, WebsocketUpgrader};
+use dce_tokio_tungstenite::deflate::DEFLATE_EXTRA_NAME;
+use dce_tokio_tungstenite::protocol::{SemiWebsocketProtocol, SemiWebsocketRaw};
+use dce_router::serializer::Serialized;
 use dce_macro::{api, openly_err};
 use dce_util::mixed::DceResult;
+use dce_util::tls::{TlsAcceptor, TlsConfig};
 
 
+static WS_ROUTER: OnceLock<&'static Arc<Router<SemiWebsocketProtocol>>> = OnceLock::new();
+
 /// `set RUST_LOG=debug && cargo run --bin app --target-dir target/http -- http start`
+/// `cargo run --bin app --target-dir target/http -- http start cert=./cert.pem key=./key.pem`, serve https
+/// `cargo run --bin app --target-dir target/http -- http start unix=/tmp/dce-http.sock`, then `curl --unix-socket /tmp/dce-http.sock http://localhost/hello`
 #[api("http/start")]
-async fn http_start(_: CliRaw) {
+async fn http_start(req: CliRaw) {
     let addr = SocketAddr::from(([127, 0, 0, 1], 2046));
+    let _ = WS_ROUTER.set(Router::new()?.set_extra(DEFLATE_EXTRA_NAME, Box::new(true)).push(handshake).ready()?);
     let router = Router::new()?
         .set_event_handlers(Some(EventHandler::Async(Box::new(|context| Box::pin(interceptor(context))))), None)
+        .set_extra(CORS_EXTRA_NAME, Box::new(Cors::new().max_age(600)))
+        .set_extra(COMPRESSION_EXTRA_NAME, Box::new(Compression::new()))
+        .set_extra(MAX_BODY_EXTRA_NAME, Box::new(1024 * 1024))
+        .set_extra(RESPONSE_CACHE_EXTRA_NAME, Box::new(ResponseCache::new(1024)))
         .push(var1)
         .push(var2)
         .push(var3)
@@ -36,23 +58,36 @@ async fn http_start(_: CliRaw) {
         .push(hello)
         .push(hello_post)
         .push(home)
+        .push(clock)
+        .push(greeting)
+        .push(drunkce)
+        .push(ws)
+        .push(assets)
+        .push(legacy)
+        .push(users)
+        .push(users_v2)
+        .push(routes)
+        .push(now)
+        .push(now_refresh)
         .ready()?;
 
-    let listener = TcpListener::bind(addr).await.expect(format!("cannot bind tcp to {}", addr).as_str());
-    info!("Dce started at {}:{} with Hyper", addr.ip(), addr.port());
-    loop {
-        let (stream, _) = listener.accept().await.expect("cannot accept tcp stream");
-        let io = TokioIo::new(stream);
-
-        tokio::task::spawn(async {
-            if let Err(err) = http1::Builder::new()
-                .serve_connection(io, service_fn(|req| HyperHttpProtocol::from(req).route(router.clone(), Default::default())))
-                .await
-            {
-                println!("Error serving connection: {:?}", err);
-            }
-        });
+    let tenant_router = Router::new()?.push(tenant).ready()?;
+    let dispatcher = Dispatcher::new()
+        .host("{tenant}.localhost", tenant_router.clone())
+        .header("X-Tenant", "{tenant}", tenant_router.clone())?
+        .fallback(router.clone());
+
+    let mut options = ServeOptions::new();
+    if let (Some(cert), Some(key)) = (req.rp().args().get("cert"), req.rp().args().get("key")) {
+        options = options.tls(TlsAcceptor::new(TlsConfig::new(cert, key).alpn(["h2", "http/1.1"]))?);
     }
+    #[cfg(unix)]
+    if let Some(path) = req.rp().args().get("unix") {
+        HyperHttpProtocol::serve_unix(dispatcher, path, options).await?;
+        return req.end(None);
+    }
+    HyperHttpProtocol::serve(dispatcher, addr, options).await?;
+    req.end(None)
 }
 
 async fn interceptor(context: &mut Context<HyperHttpProtocol>) -> DceResult<()> {
@@ -66,6 +101,52 @@ async fn interceptor(context: &mut Context<HyperHttpProtocol>) -> DceResult<()>
     Ok(())
 }
 
+/// `curl -H "Host: drunk.localhost" http://127.0.0.1:2046/tenant`
+/// `curl -H "X-Tenant: drunk" http://127.0.0.1:2046/tenant`
+#[api("tenant")]
+pub fn tenant(req: HttpRaw) {
+    let tenant = format!("tenant: {}", req.param("tenant")?.as_str().unwrap_or_default());
+    req.raw_resp(Response::new(Full::from(tenant).boxed()))
+}
+
+/// `curl http://127.0.0.1:2046/v1/users`
+/// `curl -H "Api-Version: 1" http://127.0.0.1:2046/users`
+#[api("users", version = "1")]
+pub fn users(req: HttpRaw) {
+    req.raw_resp(Response::new(Full::from("users v1").boxed()))
+}
+
+/// `curl http://127.0.0.1:2046/users`
+/// `curl http://127.0.0.1:2046/users.v3`, fallback to the highest compatible version 2
+#[api("users", version = "2")]
+pub fn users_v2(req: HttpRaw) {
+    req.raw_resp(Response::new(Full::from("users v2").boxed()))
+}
+
+/// `curl http://127.0.0.1:2046/routes`
+#[api]
+pub fn routes(req: HttpRaw) {
+    let routes = req.router().routes().iter()
+        .map(|api| format!("/{} {} {}", api.path(), api.method().as_ref().map_or_else(|| "*".to_string(), |m| m.to_string()), api.version()))
+        .collect::<Vec<_>>().join("\n");
+    req.raw_resp(Response::new(Full::from(routes).boxed()))
+}
+
+/// `curl -i http://127.0.0.1:2046/now`, the response will be kept for 10 seconds
+/// `curl -i -H 'If-None-Match: "<etag>"' http://127.0.0.1:2046/now`, answered with 304
+#[api(cache = CachePolicy::new().control("public, max-age=10").ttl(Duration::from_secs(10)))]
+pub fn now(req: HttpRaw) {
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map_or(0, |d| d.as_millis());
+    req.raw_resp(Response::new(Full::from(now.to_string()).boxed()))
+}
+
+/// `curl -X POST http://127.0.0.1:2046/now/refresh`
+#[api("now/refresh", method = Post)]
+pub fn now_refresh(req: HttpRaw) {
+    let removed = ResponseCache::from_router(req.router()).map_or(0, |cache| cache.invalidate("now"));
+    req.raw_resp(Response::new(Full::from(format!("{} invalidated", removed)).boxed()))
+}
+
 /// `curl http://127.0.0.1:2046/var1`
 #[api("{var1}")]
 pub fn var1(req: HttpRaw) {
@@ -133,7 +214,9 @@ pub async fn hello(req: HttpRaw) -> DceResult<Option<HttpResp>> {
 }
 
 /// `curl -H "Content-Type: application/json" -d "{""user"":""Drunk"",""age"":18}" http://127.0.0.1:2046/hello`
-#[api("hello", method = [Post, Options], serializer = [JsonSerializer{}])]
+/// `curl -i -X OPTIONS -H "Origin: https://drunkce.com" -H "Access-Control-Request-Method: POST" http://127.0.0.1:2046/hello`
+/// `curl -i -X OPTIONS http://127.0.0.1:2046/hello`, answered with `Allow: GET, HEAD, OPTIONS, POST`
+#[api("hello", method = Post, serializer = [JsonSerializer{}], cors = Cors::new().origins(["https://drunkce.com"]).credentials(true), max_body = 1024, body_timeout = 5)]
 pub async fn hello_post(mut req: Http<GreetingReq, GreetingResp>) {
     let legal_age = 18;
     let body: Greeting = req.req().await?;
@@ -156,6 +239,64 @@ pub async fn home(req: HttpGet<Greeting>) {
     })
 }
 
+/// `curl -N http://127.0.0.1:2046/clock`
+/// `curl -N -H "Last-Event-ID: 10" http://127.0.0.1:2046/clock`
+#[api]
+pub async fn clock(req: HttpRaw) {
+    req.sse(None, |sender| async move {
+        let mut tick = sender.last_event_id().and_then(|id| id.parse::<u64>().ok()).unwrap_or(0);
+        while ! sender.is_closed() {
+            tick += 1;
+            sender.send(Event::new(format!("tick {}", tick)).name("tick").id(tick)).await?;
+            tokio::time::sleep(Duration::from_secs(1)).await;
+        }
+        Ok(())
+    })
+}
+
+/// `curl -i -X POST http://127.0.0.1:2046/greeting`
+#[api("greeting", method = Post, serializer = JsonSerializer{})]
+pub async fn greeting(req: HttpRaw) {
+    req.with_status(StatusCode::CREATED)
+        .with_header("X-Greeting", "Hello Dce")?
+        .with_cookie(Cookie::new("greeted", "1").path("/").http_only(true).same_site(SameSite::Lax))?
+        .success(None)
+}
+
+/// `curl -i http://127.0.0.1:2046/drunkce`
+#[api]
+pub async fn drunkce(req: HttpRaw) {
+    req.redirect("https://drunkce.com", Some(StatusCode::MOVED_PERMANENTLY))
+}
+
+/// `curl -i http://127.0.0.1:2046/assets/docs/dce-router-flow.svg`
+/// `curl -i -H "Range: bytes=0-99" http://127.0.0.1:2046/assets/docs/README-zh.md`
+/// `curl -i -H "If-None-Match: $etag" http://127.0.0.1:2046/assets/docs/README-zh.md`
+#[api("assets/{path*}")]
+pub async fn assets(req: HttpRaw) {
+    req.serve_static(&StaticDir::new("./assets").precompressed(true)).await
+}
+
+/// Forward to the upstream service, e.g. start one with `python -m http.server 8080`, then
+/// `curl -i http://127.0.0.1:2046/legacy/README.md`
+#[api("legacy/{path*}", method = [Get, Post], upstream = Upstream::new(Uri::from_static("http://127.0.0.1:8080")))]
+pub async fn legacy(req: HttpRaw) {
+    req.proxy(None).await
+}
+
+/// Connect to `ws://127.0.0.1:2046/ws` with header `X-Session-Id: $session_id`, then send `0;handshake>BODY>>>`
+#[api]
+pub async fn ws(req: HttpRaw) {
+    req.upgrade_websocket(Arc::clone(WS_ROUTER.get().expect("websocket router not ready")), false)
+}
+
+#[api]
+pub async fn handshake(req: SemiWebsocketRaw) {
+    let handshake = req.get_as::<Arc<Handshake>>(HANDSHAKE_DATA_NAME)?;
+    let info = format!("Upgraded from {} with sid {:?}", handshake.uri(), handshake.sid());
+    req.pack(Serialized::String(info))
+}
+
 
 #[derive(Debug, Clone, TemplateOnce)]
 #[template(path = "home.html")]
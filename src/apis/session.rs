@@ -1,21 +1,18 @@
 use std::collections::HashSet;
 use std::net::SocketAddr;
-use hyper::server::conn::http1;
-use hyper::service::service_fn;
-use hyper_util::rt::TokioIo;
-use log::info;
 use redis::aio::MultiplexedConnection;
 use redis::Client;
+use dce_hyper::server::ServeOptions;
 use dce_hyper::protocol::HttpMethod::{Patch, Post};
 use dce_router::api::{EventHandler};
 use dce_router::request::{Context, Response};
 use dce_router::router::Router;
 use dce_router::serializer::{Serialized};
 use serde::{Deserialize, Serialize};
-use tokio::net::TcpListener;
 use tokio::sync::OnceCell;
 use dce_cli::protocol::CliRaw;
 use dce_hyper::protocol::{HttpRaw, HyperHttpProtocol};
+use dce_hyper::sid::{SidCarrier, SidTransport, SID_TRANSPORT_EXTRA_NAME};
 use dce_macro::{api};
 use dce_router::protocol::RoutableProtocol;
 use dce_session::auto::AutoRenew;
@@ -44,31 +41,21 @@ async fn http_start_session(req: CliRaw) {
     let router = Router::new()?
         .set_event_handlers(Some(EventHandler::Async(Box::new(|context| Box::pin(before_controller(context))))),
                             Some(EventHandler::Async(Box::new(|context| Box::pin(after_controller(context))))))
+        .set_extra(SID_TRANSPORT_EXTRA_NAME, Box::new(SidTransport::new(SidCarrier::Both)))
         .push(index)
         .push(login)
+        .push(logout)
         .push(profile)
         .push(modify)
         .push(user)
         .ready()?;
     
-    let listener = TcpListener::bind(addr).await.expect(format!("cannot bind tcp to {}", addr).as_str());
-    info!("Dce started at {}:{} with session feature and Hyper crate", addr.ip(), addr.port());
-    loop {
-        let (stream, _) = listener.accept().await.expect("cannot accept tcp stream");
-        let io = TokioIo::new(stream);
-
-        tokio::task::spawn(async {
-            if let Err(err) = http1::Builder::new()
-                .serve_connection(io, service_fn(|req| HyperHttpProtocol::from(req).route(router.clone(), Default::default()))).await
-            {
-                println!("Error serving connection: {:?}", err);
-            }
-        });
-    }
+    HyperHttpProtocol::serve(router.clone(), addr, ServeOptions::new()).await?;
+    req.end(None)
 }
 
 async fn after_controller(context: &mut Context<HyperHttpProtocol>) -> DceResult<()> {
-    if let Some(new_sid) = context.rp_mut().get_resp_sid().map(|s| s.to_string()) {
+    if let Some(new_sid) = context.rp_mut().get_resp_sid().filter(|s| ! s.is_empty()).map(|s| s.to_string()) {
         if let Some(Response::Serialized(Serialized::String(body))) = context.rp_mut().resp_mut() {
             body.push_str(format!("\n\nGot new sid, you can use it to access private page:\n{}", new_sid).as_str());
         }
@@ -174,6 +161,15 @@ async fn login(mut req: HttpRaw) {
     req.pack(Serialized::String("Failed to login".to_string()))
 }
 
+/// `curl -i -X POST http://127.0.0.1:2050/logout -b "session_id=$session_id"`, the sid cookie will be removed
+#[api(method = [Post])]
+async fn logout(mut req: HttpRaw) {
+    let session = HyperHttpProtocol::session::<RedisSession<MultiplexedConnection, Member>, _>(&mut req)?;
+    session.logout().await?;
+    req.rp_mut().clear_resp_sid();
+    req.pack(Serialized::String("Logged out".to_string()))
+}
+
 /// `curl http://127.0.0.1:2050/manage/profile`, without sid, cannot access got 401
 /// `curl http://127.0.0.1:2050/manage/profile -H "X-Session-Id: $session_id"`, pass sid on header, can access if sid is valid
 /// `curl http://127.0.0.1:2050/manage/profile -b "session_id=$session_id"`, pass sid in cookies, can access if sid is valid
@@ -1,16 +1,16 @@
 use std::any::Any;
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 use futures_util::lock::Mutex;
-use futures_util::StreamExt;
-use log::{error, info, warn};
+use log::{info, warn};
 use redis::aio::MultiplexedConnection;
 use redis::Client;
 use serde::{Deserialize, Serialize};
 use tokio::net::TcpListener;
 use tokio::sync::OnceCell;
-use tokio_tungstenite::accept_hdr_async;
+use tokio_tungstenite::accept_hdr_async_with_config;
 use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::Message;
 use dce_cli::protocol::CliRaw;
 use dce_macro::api;
 use dce_router::api::EventHandler;
@@ -19,15 +19,20 @@ use dce_router::request::Context;
 use dce_router::router::Router;
 use dce_router::serializer::Serialized;
 use dce_session::auto::AutoRenew;
+use dce_session::cluster::{ClusterNode, RedisBus};
 use dce_session::connection::Connection;
 use dce_session::redis::RedisSession;
 use dce_session::session::{DEFAULT_TTL_MINUTES, Session};
 use dce_session::user::{UidGetter, User};
+use dce_tokio_tungstenite::driver::SemiWebsocketDriver;
 use dce_tokio_tungstenite::protocol::{SemiWebsocketProtocol, SemiWebsocketRaw};
+use dce_tokio_tungstenite::registry::SemiWebsocketRegistry;
 use dce_util::mixed::{DceErr, DceResult};
+use dce_util::registry::{PushTarget, RegisteredConnection, REGISTRY_DATA_NAME};
 
 
 static REDIS_CLIENT: OnceCell<Client> = OnceCell::const_new();
+static CLUSTER: OnceLock<ClusterNode<RedisSession<MultiplexedConnection, Member>, Member, Message>> = OnceLock::new();
 
 pub fn redis_prepare(host: &str) {
     REDIS_CLIENT.set(Client::open(format!("redis://{host}")).unwrap()).unwrap()
@@ -38,48 +43,61 @@ pub async fn redis() -> MultiplexedConnection {
 }
 
 /// `set RUST_LOG=debug && cargo run --bin app --features connection-session --target-dir target/session_ws -- websocket start session redis=127.0.0.1:6379`
+///
+/// Start another node with `port=2052` to try the cluster, the pushes to the users connected to the other node will be forwarded through redis
 #[api("websocket/start/session")]
 pub async fn websocket_start_session(req: CliRaw) {
     let redis_host = req.rp().args().get("redis").ok_or(DceErr::closed0(r#"You must specific the redis host, for example "http start session redis=127.0.0.1:6379""#))?;
     redis_prepare(redis_host);
-    let addr = "0.0.0.0:2051";
-    let server = TcpListener::bind(addr).await.unwrap();
+    let addr = format!("0.0.0.0:{}", req.rp().args().get("port").map_or("2051", String::as_str));
+    let server = TcpListener::bind(&addr).await.unwrap();
+    let server_addr = server.local_addr().map_or_else(|_| "".to_string(), |a| a.to_string());
     let router = Router::new()?
         .set_event_handlers(Some(EventHandler::Async(Box::new(|c| Box::pin(before_controller(c))))), None)
         .push(login)
         .push(profile)
+        .push(notify)
         .ready()?;
+    let registry = SemiWebsocketRegistry::new().binary(true);
+    let pack_registry = registry.clone();
+    let bus = RedisBus::new(REDIS_CLIENT.get().unwrap().clone()).await?;
+    let cluster = CLUSTER.get_or_init(|| ClusterNode::new(&server_addr, Arc::new(bus), (*registry).clone(),
+        RedisSession::new(DEFAULT_TTL_MINUTES).unwrap(), move |path, body| pack_registry.pack(path, Serialized::Bytes(body.into()))));
+    cluster.serve().await?;
 
     info!("Dce started at {} with session feature and tokio-tungstenite", addr);
 
-    while let Ok((stream, _)) = server.accept().await {
-        let server_addr = server.local_addr().map_or_else(|_| "".to_string(), |a| a.to_string());
+    loop {
+        let stream = tokio::select! {
+            accepted = server.accept() => match accepted {
+                Ok((stream, _)) => stream,
+                Err(_) => break,
+            },
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        let (server_addr, registry) = (server_addr.clone(), registry.clone());
         tokio::spawn(async move {
             let mut sid: Option<String> = None;
-            let mut ws_stream = accept_hdr_async(stream, |req: &Request, response: Response| {
+            let ws_stream = accept_hdr_async_with_config(stream, |req: &Request, response: Response| {
                 let _ = req.headers().get("X-Session-Id").map(|v| sid = v.to_str().map_or(None, |v| Some(v.to_string())));
                 Ok(response)
-            }).await.expect("Error during the websocket handshake occurred");
+            }, Some(SemiWebsocketProtocol::ws_config(router))).await.expect("Error during the websocket handshake occurred");
 
             match match sid {
                 Some(sid) => RedisSession::new_with_id(vec![sid]),
                 None => RedisSession::<MultiplexedConnection, Member>::new(DEFAULT_TTL_MINUTES),
             }.map(|r| r.connect(server_addr)) {
                 Ok(root) => {
-                    while let Some(msg) = ws_stream.next().await {
-                        match msg {
-                            Ok(msg) if msg.is_text() || msg.is_binary() => SemiWebsocketProtocol::from(msg).binary()
-                                .route(router.clone(), &mut ws_stream, HashMap::from([("root_session".to_string(), Box::new(root.clone()) as Box<dyn Any + Send>)])).await,
-                            Err(err) => error!("{err}"),
-                            _ => {} // when ping pong or other do nothing
-                        };
-                    }
+                    SemiWebsocketDriver::new(router.clone()).binary(true).registry(registry)
+                        .drive(ws_stream, |_| HashMap::from([("root_session".to_string(), Box::new(root.clone()) as Box<dyn Any + Send>)])).await;
                     let _ = root.lock().await.redis_then(redis().await).disconnect().await;
                 },
                 Err(e) => warn!("{e}"),
             }
         });
     }
+    // unbind the sessions from this server, so that the other nodes will not forward to it anymore
+    let _ = cluster.shutdown().await.map(|count| info!("{count} sessions unbound from {server_addr}")).map_err(|e| warn!("{e}"));
     req.end(None)
 }
 
@@ -98,6 +116,10 @@ async fn before_controller(context: &mut Context<SemiWebsocketProtocol>) -> DceR
         auth.try_renew().await?;
     }
 
+    // bind the validated sid, the pushes to it will be sent to this connection
+    if let Ok(registered) = context.get_as::<Arc<RegisteredConnection<Message>>>(REGISTRY_DATA_NAME) {
+        registered.bind_sid(session.id().to_string());
+    }
     SemiWebsocketProtocol::set_session(context, Box::new(session.unwrap()));
     Ok(())
 }
@@ -158,6 +180,9 @@ pub async fn login(mut req: SemiWebsocketRaw) {
     let session = SemiWebsocketProtocol::session::<RedisSession<MultiplexedConnection, Member>, _>(&mut req)?;
     if session.login(member.clone(), DEFAULT_TTL_MINUTES).await? {
         let new_sid = session.id().to_string();
+        let registered = req.get_as::<Arc<RegisteredConnection<Message>>>(REGISTRY_DATA_NAME)?;
+        registered.bind_sid(new_sid.clone());
+        registered.bind_uid(Some(member.id));
         req.rp_mut().set_resp_sid(new_sid);
         return req.pack(Serialized::String(format!("Succeed login with:\n{:?}", member)))
     }
@@ -175,6 +200,14 @@ pub async fn profile(mut req: SemiWebsocketRaw) {
     req.pack(Serialized::String(format!("Your profile:\n{:?}", member)))
 }
 
+/// `cargo run --bin app --features connection-session -- websocket 127.0.0.1:2052 --sid $SESSION_ID -- notify/100`, the user 100 connected to any node will receive the `notified` message
+#[api("notify/{uid}")]
+pub async fn notify(req: SemiWebsocketRaw) {
+    let uid = req.param("uid")?.as_str().and_then(|uid| uid.parse::<u64>().ok()).ok_or_else(|| DceErr::openly(1002, "Invalid uid".to_string()))?;
+    let count = CLUSTER.get().unwrap().push(PushTarget::Uid(uid), "notified", b"You got a notice".to_vec()).await?;
+    req.pack(Serialized::String(format!("Notified {count} connections")))
+}
+
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 struct Member {
@@ -1,43 +1,78 @@
-use futures_util::StreamExt;
-use log::info;
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use log::{error, info};
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpListener;
-use tokio_util::bytes::BytesMut;
-use tokio_util::codec::{BytesCodec, Decoder};
+use tokio_util::codec::Decoder;
 use dce_cli::protocol::CliRaw;
 use dce_macro::api;
-use dce_router::protocol::RoutableProtocol;
+use dce_router::protocol::{ConnectionInfo, CONNECTION_DATA_NAME, RoutableProtocol};
 use dce_router::router::Router;
 use dce_router::serializer::Serialized;
-use dce_tokio::protocol::{SemiTcpProtocol, SemiTcpRaw};
+use dce_tokio::codec::SemiTcpCodec;
+use dce_tokio::driver::SemiTcpDriver;
+use dce_tokio::protocol::SemiTcpRaw;
+use dce_util::mixed::DceErr;
+use dce_util::tls::{TlsAcceptor, TlsConfig, PEER_IDENTITY_DATA_NAME};
+#[cfg(unix)]
+use dce_util::unix::{self, PeerCred, UnixAddr, PEER_CRED_DATA_NAME};
 
 /// `set RUST_LOG=debug && cargo run --bin app --target-dir target/tcp -- tcp start`
+/// `cargo run --bin app --target-dir target/tcp -- tcp start cert=./cert.pem key=./key.pem`, serve with tls
+/// `cargo run --bin app --target-dir target/tcp -- tcp start mode=text`, serve the legacy text messages for the interactive client
+/// `cargo run --bin app --target-dir target/tcp -- tcp start unix=@dce-tcp`, serve on a unix socket, the `@` prefixed is an abstract name
 #[api("tcp/start")]
 pub async fn tcp_start(req: CliRaw) {
-    let addr = "0.0.0.0:2048";
-    let server = TcpListener::bind(addr).await.unwrap();
+    let codec = match req.rp().args().get("mode").map(String::as_str) {
+        Some("text") => SemiTcpCodec::text(),
+        _ => SemiTcpCodec::binary(),
+    };
+    let tls = match (req.rp().args().get("cert"), req.rp().args().get("key")) {
+        (Some(cert), Some(key)) => Some(TlsAcceptor::new(TlsConfig::new(cert, key))?),
+        _ => None,
+    };
     let router = Router::new()?
         .push(hello)
         .push(echo)
+        .push(whoami)
+        .push(disconnect)
         .ready()?;
 
-    info!("Dce started at {} with tokio-tcp", addr);
+    #[cfg(unix)]
+    if let Some(addr) = req.rp().args().get("unix") {
+        let addr = UnixAddr::new(addr);
+        let server = addr.bind().map_err(DceErr::closed0)?;
+        info!("Dce started at unix:{} with tokio-tcp", addr);
+        while let Ok((stream, peer_cred)) = unix::accept(&server).await {
+            let driver = SemiTcpDriver::new(router.clone()).peer_cred(peer_cred).idle_timeout(Duration::from_secs(300));
+            tokio::spawn(serve(stream, codec.clone(), driver, Default::default));
+        }
+        return req.end(None);
+    }
 
-    while let Ok((stream, _)) = server.accept().await {
-        tokio::spawn(async {
-            let framed = BytesCodec::new().framed(stream);
-            let (mut frame_writer, mut frame_reader) = framed.split::<BytesMut>();
+    let addr = "0.0.0.0:2048";
+    let server = TcpListener::bind(addr).await.unwrap();
+    info!("Dce started at {} with tokio-tcp", addr);
 
-            while let Some(msg) = frame_reader.next().await {
-                match msg {
-                    Ok(str) => SemiTcpProtocol::from(str).route(router.clone(), &mut frame_writer, Default::default()).await,
-                    Err(err) => println!("Socket closed with error: {:?}", err),
-                };
+    while let Ok((stream, peer)) = server.accept().await {
+        let (tls, codec) = (tls.clone(), codec.clone());
+        let driver = SemiTcpDriver::new(router.clone()).peer(peer).idle_timeout(Duration::from_secs(300));
+        tokio::spawn(async move {
+            match TlsAcceptor::accept_optional(tls.as_deref(), stream).await {
+                Ok((stream, identity)) => serve(stream, codec, driver, move || identity.iter()
+                    .map(|identity| (PEER_IDENTITY_DATA_NAME.to_string(), Box::new(identity.clone()) as Box<dyn Any + Send>)).collect()).await,
+                Err(err) => error!("tls handshake with {peer} failed: {err}"),
             }
         });
     }
     req.end(None)
 }
 
+async fn serve<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(stream: S, codec: SemiTcpCodec, driver: SemiTcpDriver, context_data: impl Fn() -> HashMap<String, Box<dyn Any + Send>>) {
+    driver.drive(codec.framed(stream), |_| context_data()).await
+}
 
 /// `cargo run --bin app -- tcp 127.0.0.1:2048 -- hello`
 #[api]
@@ -53,3 +88,22 @@ pub async fn echo(mut req: SemiTcpRaw) {
     let body = format!(r#"path param data: "{}"{}body data: "{}""#, param, "\n", body);
     req.pack(Serialized::String(body))
 }
+
+/// `cargo run --bin app -- tcp unix:@dce-tcp -- whoami`
+#[api]
+pub async fn whoami(req: SemiTcpRaw) {
+    #[cfg(unix)]
+    if let Ok(cred) = req.get_as::<Arc<PeerCred>>(PEER_CRED_DATA_NAME) {
+        let body = format!("uid: {}, gid: {}, pid: {:?}", cred.uid(), cred.gid(), cred.pid());
+        return req.pack(Serialized::String(body));
+    }
+    let peer = req.get_as::<Arc<ConnectionInfo>>(CONNECTION_DATA_NAME)?.peer();
+    req.pack(Serialized::String(format!("peer: {peer:?}")))
+}
+
+/// Routed by the driver after the connection closed
+#[api("$disconnect")]
+pub async fn disconnect(req: SemiTcpRaw) {
+    info!("connection of {:?} closed", req.get_as::<Arc<ConnectionInfo>>(CONNECTION_DATA_NAME)?.peer());
+    req.end(None)
+}
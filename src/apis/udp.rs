@@ -1,16 +1,16 @@
-use futures_util::StreamExt;
 use log::info;
 use tokio::net::UdpSocket;
-use tokio_util::codec::BytesCodec;
-use tokio_util::udp::UdpFramed;
 use dce_cli::protocol::CliRaw;
 use dce_macro::api;
 use dce_router::protocol::RoutableProtocol;
 use dce_router::router::Router;
 use dce_router::serializer::Serialized;
-use dce_tokio::protocol::{SemiTcpProtocol, SemiTcpRaw};
+use dce_tokio::codec::SemiTcpCodec;
+use dce_tokio::protocol::SemiTcpRaw;
+use dce_tokio::udp::SemiUdpDriver;
 
 /// `set RUST_LOG=debug && cargo run --bin app --target-dir target/udp -- udp start`
+/// `cargo run --bin app --target-dir target/udp -- udp start mode=text`, serve the legacy text messages for the interactive client
 #[api("udp/start")]
 pub async fn udp_start(req: CliRaw) {
     let addr = "0.0.0.0:2049";
@@ -22,14 +22,11 @@ pub async fn udp_start(req: CliRaw) {
 
     info!("Dce started at {} with tokio-udp", addr);
 
-    let (mut sink, mut stream) = UdpFramed::new(socket, BytesCodec::new()).split();
-
-    while let Some(msg) = stream.next().await {
-        match msg {
-            Ok((msg, addr)) => SemiTcpProtocol::from(msg).udp_route(router.clone(), &mut sink, addr, Default::default()).await,
-            Err(err) => println!("Socket closed with error: {:?}", err)
-        };
-    }
+    let codec = match req.rp().args().get("mode").map(String::as_str) {
+        Some("text") => SemiTcpCodec::text(),
+        _ => SemiTcpCodec::binary(),
+    };
+    SemiUdpDriver::new(router.clone()).codec(codec).drive(socket, |_| Default::default()).await;
     req.end(None)
 }
 
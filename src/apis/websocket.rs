@@ -1,46 +1,75 @@
-use futures_util::StreamExt;
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
 use log::{error, info};
+use tokio::io::{AsyncRead, AsyncWrite};
 use tokio::net::TcpListener;
-use tokio_tungstenite::accept_async;
+use tokio_tungstenite::tungstenite::Message;
 use dce_cli::protocol::CliRaw;
 use dce_macro::api;
-use dce_router::protocol::RoutableProtocol;
+use dce_router::protocol::{ConnectionInfo, CONNECTION_DATA_NAME, RoutableProtocol};
 use dce_router::router::Router;
 use dce_router::serializer::Serialized;
+use dce_tokio_tungstenite::deflate::{self, DEFLATE_EXTRA_NAME};
+use dce_tokio_tungstenite::driver::SemiWebsocketDriver;
+use dce_tokio_tungstenite::registry::SemiWebsocketRegistry;
 use dce_tokio_tungstenite::protocol::{SemiWebsocketProtocol, SemiWebsocketRaw};
+use dce_util::registry::{RegisteredConnection, REGISTRY_DATA_NAME};
+use dce_util::tls::{TlsAcceptor, TlsConfig, PEER_IDENTITY_DATA_NAME};
 
 
+static REGISTRY: OnceLock<SemiWebsocketRegistry> = OnceLock::new();
+
 /// `set RUST_LOG=debug && cargo run --bin app --target-dir target/websocket -- websocket start`
+/// `cargo run --bin app --target-dir target/websocket -- websocket start cert=./cert.pem key=./key.pem`, serve wss
 #[api("websocket/start")]
 pub async fn websocket_start(req: CliRaw) {
     let addr = "0.0.0.0:2047";
     let server = TcpListener::bind(addr).await.unwrap();
+    let tls = match (req.rp().args().get("cert"), req.rp().args().get("key")) {
+        (Some(cert), Some(key)) => Some(TlsAcceptor::new(TlsConfig::new(cert, key))?),
+        _ => None,
+    };
     let router = Router::new()?
+        .set_extra(DEFLATE_EXTRA_NAME, Box::new(true))
         .push(hello)
         .push(echo)
+        .push(disconnect)
+        .push(room_join)
+        .push(room_say)
         .ready()?;
+    let registry = REGISTRY.get_or_init(|| SemiWebsocketRegistry::new().binary(true));
 
     info!("Dce started at {} with tokio-tungstenite", addr);
 
-    while let Ok((stream, _)) = server.accept().await {
-        tokio::spawn(async {
-            let mut ws_stream = accept_async(stream)
-                .await
-                .expect("Error during the websocket handshake occurred");
-
-            while let Some(msg) = ws_stream.next().await {
-                match msg {
-                    Ok(msg) => if msg.is_text() || msg.is_binary() {
-                        SemiWebsocketProtocol::from(msg).binary().route(router.clone(), &mut ws_stream, Default::default()).await
-                    },
-                    Err(err) => error!("{err}")
-                };
+    while let Ok((stream, peer)) = server.accept().await {
+        let tls = tls.clone();
+        let driver = SemiWebsocketDriver::new(router.clone()).binary(true).peer(peer)
+            .heartbeat(Duration::from_secs(30)).idle_timeout(Duration::from_secs(300)).registry(registry.clone());
+        tokio::spawn(async move {
+            match TlsAcceptor::accept_optional(tls.as_deref(), stream).await {
+                Ok((stream, identity)) => serve(stream, router, driver, move || identity.iter()
+                    .map(|identity| (PEER_IDENTITY_DATA_NAME.to_string(), Box::new(identity.clone()) as Box<dyn Any + Send>)).collect()).await,
+                Err(err) => error!("tls handshake with {peer} failed: {err}"),
             }
         });
     }
     req.end(None)
 }
 
+async fn serve<S: AsyncRead + AsyncWrite + Unpin + Send + 'static>(
+    stream: S,
+    router: &Arc<Router<SemiWebsocketProtocol>>,
+    driver: SemiWebsocketDriver,
+    context_data: impl Fn() -> HashMap<String, Box<dyn Any + Send>>,
+) {
+    let (ws_stream, headers) = match deflate::accept_with_headers(stream, router).await {
+        Ok(accepted) => accepted,
+        Err(err) => return error!("Error during the websocket handshake occurred: {err}"),
+    };
+    driver.headers(headers).drive(ws_stream, |_| context_data()).await
+}
 
 /// `cargo run --bin app -- websocket 127.0.0.1:2047 -- hello`
 #[api]
@@ -56,3 +85,28 @@ pub async fn echo(mut req: SemiWebsocketRaw) {
     let body = format!(r#"path param data: "{}"{}body data: "{}""#, param, "\n", body);
     req.pack(Serialized::String(body))
 }
+
+/// Routed by the driver after the connection closed, by the client or for idle timeout or dead peer
+#[api("$disconnect")]
+pub async fn disconnect(req: SemiWebsocketRaw) {
+    let connection = req.get_as::<Arc<ConnectionInfo>>(CONNECTION_DATA_NAME)?;
+    info!("connection of {:?} closed, user agent: {:?}", connection.peer(), connection.header("user-agent"));
+    req.end(None)
+}
+
+/// Join a room to receive the pushes of it, e.g. `websocket interactive 127.0.0.1:2047` and then type in `room/join/lobby>BODY>>>`
+#[api("room/join/{room}")]
+pub async fn room_join(req: SemiWebsocketRaw) {
+    let room = req.param("room")?.as_str().unwrap_or("").to_string();
+    req.get_as::<Arc<RegisteredConnection<Message>>>(REGISTRY_DATA_NAME)?.join(&room);
+    req.pack(Serialized::String(format!("joined {room}")))
+}
+
+/// Broadcast the body to the room members with path `room/said/{room}`, e.g. `room/say/lobby>BODY>>>hello everyone`
+#[api("room/say/{room}")]
+pub async fn room_say(mut req: SemiWebsocketRaw) {
+    let body = req.rp_mut().body().await?;
+    let room = req.param("room")?.as_str().unwrap_or("").to_string();
+    let count = REGISTRY.get().map_or(0, |registry| registry.broadcast(&room, &format!("room/said/{room}"), body));
+    req.pack(Serialized::String(format!("pushed to {count} connections")))
+}